@@ -0,0 +1,137 @@
+//! Audio playback state.
+//!
+//! There is no output device yet; the mixer keeps track of what is playing
+//! and for how long, so that the runtime can schedule around voices.
+
+pub mod ogg;
+
+use lazy_static::*;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::config;
+
+lazy_static! {
+    // Ogg files under the root path, keyed by their uppercase file stem.
+    static ref AUDIO_FILES: HashMap<String, PathBuf> = {
+        let mut files = HashMap::new();
+        index_audio_files(config::get_root_path().as_ref(), &mut files);
+        files
+    };
+}
+
+fn index_audio_files(dir: &Path, files: &mut HashMap<String, PathBuf>) {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+
+        if path.is_dir() {
+            index_audio_files(&path, files);
+            continue;
+        }
+
+        let is_ogg = path
+            .extension()
+            .and_then(|v| v.to_str())
+            .map(|v| v.eq_ignore_ascii_case("ogg"))
+            .unwrap_or_default();
+
+        if let (true, Some(stem)) = (is_ogg, path.file_stem().and_then(|v| v.to_str())) {
+            files.insert(stem.to_ascii_uppercase(), path.clone());
+        }
+    }
+}
+
+/// Finds an audio file by the name used in scripts.
+pub fn find_audio(filename: &str) -> Option<&'static Path> {
+    let stem = Path::new(filename.split('\\').last()?)
+        .file_stem()?
+        .to_str()?;
+    AUDIO_FILES
+        .get(&stem.to_ascii_uppercase())
+        .map(PathBuf::as_path)
+}
+
+#[derive(Clone, Debug)]
+pub struct Playback {
+    pub filename: String,
+    pub started: Instant,
+    pub duration: Option<Duration>,
+}
+
+impl Playback {
+    fn new(filename: &str, now: Instant) -> Self {
+        let duration = find_audio(filename)
+            .and_then(|path| std::fs::read(path).ok())
+            .and_then(|bytes| ogg::duration(&bytes));
+
+        if duration.is_none() {
+            log::warn!("failed to obtain the duration of {}", filename);
+        }
+
+        Self {
+            filename: filename.into(),
+            started: now,
+            duration,
+        }
+    }
+
+    /// The time the playback ends at, if known.
+    pub fn end(&self) -> Option<Instant> {
+        Some(self.started + self.duration?)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Mixer {
+    voice: Option<Playback>,
+    music: Option<(String, bool)>,
+}
+
+impl Mixer {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn play_voice(&mut self, filename: &str, now: Instant) {
+        log::debug!("voice: {}", filename);
+        self.voice = Some(Playback::new(filename, now));
+    }
+
+    pub fn stop_voice(&mut self) {
+        self.voice = None;
+    }
+
+    pub fn voice(&self) -> Option<&Playback> {
+        self.voice.as_ref()
+    }
+
+    /// Returns `true` while the current voice line is still playing.
+    pub fn is_voice_playing(&self, now: Instant) -> bool {
+        self.voice
+            .as_ref()
+            .and_then(Playback::end)
+            .map(|end| now < end)
+            .unwrap_or_default()
+    }
+
+    pub fn play_music(&mut self, filename: &str, is_looped: bool) {
+        log::debug!("music: {} (looped: {})", filename, is_looped);
+        self.music = Some((filename.into(), is_looped));
+    }
+
+    pub fn fade_music(&mut self, duration: f64) {
+        log::debug!("music fade: {}", duration);
+        self.music = None;
+    }
+
+    pub fn music(&self) -> Option<&str> {
+        self.music.as_ref().map(|(filename, _)| filename.as_str())
+    }
+}
@@ -0,0 +1,107 @@
+//! Minimal Ogg container reader.
+//!
+//! Only the stream header and the granule positions are inspected; no
+//! decoding is done here.
+
+use std::time::Duration;
+
+const PAGE_HEADER_SIZE: usize = 27;
+
+struct Page<'a> {
+    granule_position: i64,
+    body: &'a [u8],
+}
+
+fn pages(bytes: &[u8]) -> impl Iterator<Item = Page<'_>> {
+    let mut offset = 0;
+
+    std::iter::from_fn(move || {
+        let header = bytes.get(offset..offset + PAGE_HEADER_SIZE)?;
+
+        if &header[0..4] != b"OggS" {
+            return None;
+        }
+
+        let mut granule_position = [0u8; 8];
+        granule_position.copy_from_slice(&header[6..14]);
+        let granule_position = i64::from_le_bytes(granule_position);
+
+        let segments = header[26] as usize;
+        let segment_table = bytes.get(offset + PAGE_HEADER_SIZE..)?.get(..segments)?;
+        let body_size: usize = segment_table.iter().map(|&v| v as usize).sum();
+
+        let body_offset = offset + PAGE_HEADER_SIZE + segments;
+        let body = bytes.get(body_offset..body_offset + body_size)?;
+
+        offset = body_offset + body_size;
+
+        Some(Page {
+            granule_position,
+            body,
+        })
+    })
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    let mut buf = [0u8; 4];
+    buf.copy_from_slice(bytes.get(offset..offset + 4)?);
+    Some(u32::from_le_bytes(buf))
+}
+
+/// Returns the duration of an Ogg Vorbis or Ogg Opus stream.
+pub fn duration(bytes: &[u8]) -> Option<Duration> {
+    let mut pages = pages(bytes);
+    let first = pages.next()?;
+
+    // (sample rate, samples to skip)
+    let (sample_rate, pre_skip) = if first.body.starts_with(b"\x01vorbis") {
+        (read_u32(first.body, 12)?, 0)
+    } else if first.body.starts_with(b"OpusHead") {
+        // granule positions of Opus streams are always in 48 kHz
+        let pre_skip = u16::from_le_bytes([*first.body.get(10)?, *first.body.get(11)?]);
+        (48000, pre_skip as i64)
+    } else {
+        return None;
+    };
+
+    if sample_rate == 0 {
+        return None;
+    }
+
+    let last_granule = pages
+        .map(|p| p.granule_position)
+        .filter(|&g| g >= 0)
+        .last()
+        .unwrap_or(0);
+
+    let samples = (last_granule - pre_skip).max(0);
+
+    Some(Duration::from_secs_f64(samples as f64 / sample_rate as f64))
+}
+
+#[cfg(test)]
+fn page(granule_position: i64, body: &[u8]) -> Vec<u8> {
+    let mut page = b"OggS\x00\x00".to_vec();
+    page.extend_from_slice(&granule_position.to_le_bytes());
+    page.extend_from_slice(&[0u8; 12]); // serial, sequence, crc
+    page.push(1);
+    page.push(body.len() as u8);
+    page.extend_from_slice(body);
+    page
+}
+
+#[test]
+fn test_vorbis_duration() {
+    let mut header = b"\x01vorbis".to_vec();
+    header.extend_from_slice(&0u32.to_le_bytes()); // version
+    header.push(2); // channels
+    header.extend_from_slice(&44100u32.to_le_bytes());
+
+    let mut stream = page(0, &header);
+    stream.append(&mut page(-1, b"comment"));
+    stream.append(&mut page(44100, b"audio"));
+    stream.append(&mut page(88200 + 22050, b"audio"));
+
+    assert_eq!(duration(&stream), Some(Duration::from_millis(2500)));
+    assert_eq!(duration(b"RIFF"), None);
+}
@@ -0,0 +1,103 @@
+//! `assets`: lists and verifies the assets under the root path.
+//!
+//! Every file is opened with the reader the engine uses for its kind.
+
+use std::path::{Path, PathBuf};
+
+use crate::audio::ogg;
+use crate::config;
+use crate::constants;
+use crate::format::emotbl::load_emotbl;
+use crate::format::fautotbl::load_face_map;
+use crate::format::s25::S25Archive;
+use crate::script::loader;
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+// describes the asset, or the reason it is broken; `None` for unknown kinds
+fn verify(path: &Path) -> Option<Result<String, String>> {
+    let name = path.file_name()?.to_str()?.to_ascii_uppercase();
+    let extension = path.extension()?.to_str()?.to_ascii_uppercase();
+
+    let result = match (name.as_str(), extension.as_str()) {
+        ("EMOTBL.BIN", _) => load_emotbl(path)
+            .map(|emotbl| format!("{} emotions", emotbl.len()))
+            .map_err(|err| err.to_string()),
+        ("FAUTOTBL.BIN", _) => load_face_map(path)
+            .map(|(faces, _)| format!("{} faces", faces.len()))
+            .map_err(|err| err.to_string()),
+        (_, "S25") => S25Archive::open(path)
+            .map(|s25| format!("{} entries", s25.total_entries()))
+            .map_err(|err| format!("{:?}", err)),
+        (_, "OGG") => std::fs::read(path)
+            .map_err(|err| err.to_string())
+            .and_then(|bytes| ogg::duration(&bytes).ok_or_else(|| "broken stream".into()))
+            .map(|duration| format!("{:.2}s", duration.as_secs_f64())),
+        (_, "TXT") => path
+            .to_str()
+            .ok_or_else(|| "non-UTF-8 path".to_string())
+            .and_then(|p| loader::read_rio_scene(p).map_err(|err| err.to_string()))
+            .map(|script| format!("{} commands", script.len())),
+        (_, "TTF") | (_, "OTF") => {
+            std::fs::read(path)
+                .map_err(|err| err.to_string())
+                .and_then(|bytes| {
+                    rusttype::Font::try_from_vec(bytes)
+                        .map(|font| format!("{} glyphs", font.glyph_count()))
+                        .ok_or_else(|| "broken font".into())
+                })
+        }
+        _ => return None,
+    };
+
+    Some(result)
+}
+
+pub fn run() -> bool {
+    let root = config::get_root_path();
+
+    let mut files = vec![];
+    if let Err(err) = collect_files(root.as_ref(), &mut files) {
+        eprintln!("{}: {}", root, err);
+        return false;
+    }
+
+    files.sort();
+
+    let mut broken = 0;
+
+    for path in &files {
+        let relative = path.strip_prefix(root).unwrap_or(path).display();
+
+        match verify(path) {
+            Some(Ok(description)) => println!("ok      {}  {}", relative, description),
+            Some(Err(err)) => {
+                println!("broken  {}  {}", relative, err);
+                broken += 1;
+            }
+            None => println!("-       {}", relative),
+        }
+    }
+
+    // needed to draw any text
+    if config::find_asset(constants::FONT_PATH).is_none() {
+        println!("missing {}", constants::FONT_PATH);
+        broken += 1;
+    }
+
+    println!("{} files: {} broken or missing", files.len(), broken);
+
+    broken == 0
+}
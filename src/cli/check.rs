@@ -0,0 +1,195 @@
+//! `check`: parses and validates scenarios.
+//!
+//! Scenarios called from the ones checked are checked as well, so that the
+//! whole story is covered from its entry.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::Path;
+
+use crate::config;
+use crate::script::loader;
+use crate::script::mil::command::{Command, RuntimeCommand};
+use crate::script::mil::expr::Expr;
+use crate::script::rio::command::Command as RioCommand;
+use crate::script::runtime::js::JsRuntime;
+
+#[derive(Default)]
+struct Report {
+    errors: usize,
+    warnings: usize,
+}
+
+impl Report {
+    fn error(&mut self, scenario: &str, message: impl std::fmt::Display) {
+        println!("{}: error: {}", scenario, message);
+        self.errors += 1;
+    }
+
+    fn warn(&mut self, scenario: &str, message: impl std::fmt::Display) {
+        println!("{}: warning: {}", scenario, message);
+        self.warnings += 1;
+    }
+}
+
+// a label referred to from another scenario
+struct LabelRef {
+    from: String,
+    scenario: String,
+    label: String,
+}
+
+pub fn run(files: &[String]) -> bool {
+    let mut queue: VecDeque<String> = if files.is_empty() {
+        std::iter::once(config::get_entry_scenario())
+            .chain(config::get_scenario_chain())
+            .map(String::from)
+            .collect()
+    } else {
+        files.iter().cloned().collect()
+    };
+
+    let mut js = JsRuntime::new();
+    let mut report = Report::default();
+
+    let mut visited = HashSet::new();
+    let mut labels = HashMap::new();
+    let mut label_refs = vec![];
+
+    while let Some(scenario) = queue.pop_front() {
+        if !visited.insert(scenario.clone()) {
+            continue;
+        }
+
+        if !Path::new(&scenario).exists() {
+            report.error(&scenario, "not found");
+            continue;
+        }
+
+        if !loader::is_js_scene(&scenario) {
+            match loader::read_rio_scene(&scenario) {
+                Ok(script) => check_rio(&scenario, &script, &mut report),
+                Err(err) => {
+                    report.error(&scenario, err);
+                    continue;
+                }
+            }
+        }
+
+        let script = match loader::load_scene(&mut js, &scenario) {
+            Ok(script) => loader::apply_passes(&mut js, script),
+            Err(err) => {
+                report.error(&scenario, err);
+                continue;
+            }
+        };
+
+        let found = loader::find_labels(&script);
+        let check_label = |label: &str, report: &mut Report| {
+            if !found.contains_key(label) {
+                report.error(&scenario, format_args!("undefined label `{}`", label));
+            }
+        };
+
+        for command in &script {
+            match command {
+                Command::RuntimeCommand(RuntimeCommand::Branch(label))
+                | Command::RuntimeCommand(RuntimeCommand::BranchUnless(label)) => {
+                    check_label(label, &mut report)
+                }
+                Command::RuntimeCommand(RuntimeCommand::Choice(options)) => {
+                    for option in options {
+                        check_label(&option.label, &mut report);
+                    }
+                }
+                Command::RuntimeCommand(RuntimeCommand::Call {
+                    scenario: callee,
+                    label,
+                }) => {
+                    let callee = loader::resolve_scenario(&scenario, callee);
+
+                    if let Some(label) = label {
+                        label_refs.push(LabelRef {
+                            from: scenario.clone(),
+                            scenario: callee.clone(),
+                            label: label.clone(),
+                        });
+                    }
+
+                    queue.push_back(callee);
+                }
+                _ => {}
+            }
+        }
+
+        let count = script
+            .iter()
+            .filter(|c| matches!(c, Command::RuntimeCommand(RuntimeCommand::Label(_))))
+            .count();
+        if count != found.len() {
+            report.warn(&scenario, "duplicate labels; branches go to the last one");
+        }
+
+        labels.insert(scenario, found);
+    }
+
+    for r in &label_refs {
+        // missing scenarios have been reported already
+        if let Some(found) = labels.get(&r.scenario) {
+            if !found.contains_key(&r.label) {
+                report.error(
+                    &r.from,
+                    format_args!("undefined label `{}` in {}", r.label, r.scenario),
+                );
+            }
+        }
+    }
+
+    println!(
+        "checked {} scenarios: {} errors, {} warnings",
+        visited.len(),
+        report.errors,
+        report.warnings
+    );
+
+    report.errors == 0
+}
+
+// problems lost in the transpiled program
+fn check_rio(scenario: &str, script: &[RioCommand], report: &mut Report) {
+    let mut depth = 0usize;
+    let mut unknown = 0usize;
+
+    for command in script {
+        let expr = match command {
+            RioCommand::Set { value, .. } => Some(value),
+            RioCommand::If { condition } | RioCommand::JumpIf { condition, .. } => Some(condition),
+            _ => None,
+        };
+
+        if let Some(expr) = expr {
+            if let Err(err) = Expr::parse(expr) {
+                report.error(
+                    scenario,
+                    format_args!("invalid expression `{}`: {}", expr, err),
+                );
+            }
+        }
+
+        match command {
+            RioCommand::If { .. } => depth += 1,
+            RioCommand::Else if depth == 0 => report.error(scenario, "$ELSE without $IF"),
+            RioCommand::EndIf if depth == 0 => report.error(scenario, "$ENDIF without $IF"),
+            RioCommand::EndIf => depth -= 1,
+            RioCommand::Unknown => unknown += 1,
+            _ => {}
+        }
+    }
+
+    if depth > 0 {
+        report.error(scenario, "$IF without $ENDIF");
+    }
+
+    if unknown > 0 {
+        report.warn(scenario, format_args!("{} unsupported commands", unknown));
+    }
+}
@@ -0,0 +1,36 @@
+//! `dump-mil`: prints the MIL program of a scenario.
+
+use miniserde::json::{self, Value};
+
+use crate::script::loader;
+use crate::script::mil::marshal::Marshaller;
+use crate::script::runtime::js::JsRuntime;
+
+pub fn run(file: &str, raw: bool, as_json: bool) -> bool {
+    let mut js = JsRuntime::new();
+
+    let script = match loader::load_scene(&mut js, file) {
+        Ok(script) => script,
+        Err(err) => {
+            eprintln!("{}: {}", file, err);
+            return false;
+        }
+    };
+
+    let script = if raw {
+        script
+    } else {
+        loader::apply_passes(&mut js, script)
+    };
+
+    if as_json {
+        let array = Marshaller::new().encode_all(&script);
+        println!("{}", json::to_string(&Value::Array(array)));
+    } else {
+        for (i, command) in script.iter().enumerate() {
+            println!("{:>6}  {:?}", i, command);
+        }
+    }
+
+    true
+}
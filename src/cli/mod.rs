@@ -0,0 +1,397 @@
+//! Command-line interface.
+//!
+//! `play` runs the game, the other subcommands are headless tools working on
+//! scenarios and assets.
+
+pub mod assets;
+pub mod check;
+pub mod dump_mil;
+pub mod record;
+pub mod render;
+
+use miniserde::json;
+use thiserror::Error;
+
+use std::path::PathBuf;
+
+use crate::config::ConfigSource;
+
+pub const USAGE: &str = "\
+usage: nkts [--config FILE] [--set KEY=VALUE]... [COMMAND]
+
+commands:
+    play [--entry FILE] [--root DIR] [--backend vulkano|cpu]
+                            runs the game (default)
+    check [FILE]...         parses and validates scenarios; the configured
+                            ones and those they call if none given
+    dump-mil [--raw] [--json] FILE
+                            prints the MIL program of a scenario; `--raw`
+                            skips the passes
+    render [--entry FILE] [--output FILE] INDEX
+                            composites the dialogue line INDEX to a PNG
+    record [--entry FILE] [--fps N] [--duration SECS] [--output PATH]
+                            plays headless and writes every frame to PATH;
+                            a Y4M stream if it ends in `.y4m`, PNGs in the
+                            directory otherwise
+    assets                  lists and verifies the assets under the root path
+    help                    prints this message";
+
+#[derive(Debug, Error)]
+pub enum CliError {
+    #[error("unknown command `{0}`")]
+    UnknownCommand(String),
+    #[error("unknown option `{0}`")]
+    UnknownOption(String),
+    #[error("`{0}` takes a value")]
+    MissingValue(String),
+    #[error("unexpected argument `{0}`")]
+    UnexpectedArgument(String),
+    #[error("`{0}` takes {1}")]
+    MissingArgument(&'static str, &'static str),
+    #[error("invalid dialogue index `{0}`")]
+    InvalidIndex(String),
+    #[error("invalid value `{1}` for `{0}`")]
+    InvalidValue(String, String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    Play {
+        entry: Option<String>,
+    },
+    Check {
+        files: Vec<String>,
+    },
+    DumpMil {
+        file: String,
+        raw: bool,
+        json: bool,
+    },
+    Render {
+        entry: Option<String>,
+        index: usize,
+        output: PathBuf,
+    },
+    Record {
+        entry: Option<String>,
+        fps: u32,
+        /// In seconds; until the story ends if unset.
+        duration: Option<f64>,
+        output: PathBuf,
+    },
+    Assets,
+    Help,
+}
+
+#[derive(Debug)]
+pub struct Cli {
+    pub config: ConfigSource,
+    pub command: Command,
+}
+
+// an override of a string key, quoted so that it is never read as JSON
+fn string_override(key: &str, value: &str) -> String {
+    format!("{}={}", key, json::to_string(value))
+}
+
+// the value of an option
+fn value<I>(args: &mut I, option: &str) -> Result<String, CliError>
+where
+    I: Iterator<Item = String>,
+{
+    args.next()
+        .ok_or_else(|| CliError::MissingValue(option.into()))
+}
+
+// the value of an option, parsed
+fn parse_value<I, T>(args: &mut I, option: &str) -> Result<T, CliError>
+where
+    I: Iterator<Item = String>,
+    T: std::str::FromStr,
+{
+    let v = value(args, option)?;
+    v.parse()
+        .map_err(|_| CliError::InvalidValue(option.into(), v))
+}
+
+impl Cli {
+    /// Parses the arguments, without the program name.
+    pub fn parse<I>(args: I) -> Result<Self, CliError>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut args = args.into_iter();
+        let mut config = ConfigSource::default();
+
+        // global options come first
+        let mut command = None;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--config" => config.path = Some(value(&mut args, &arg)?.into()),
+                "--set" => config.overrides.push(value(&mut args, &arg)?),
+                "-h" | "--help" => return Ok(Self::help(config)),
+                _ if arg.starts_with('-') => return Err(CliError::UnknownOption(arg)),
+                _ => {
+                    command = Some(arg);
+                    break;
+                }
+            }
+        }
+
+        let command = command.unwrap_or_else(|| "play".into());
+
+        let mut entry = None;
+        let mut output = None;
+        let mut fps = 30;
+        let mut duration = None;
+        let mut raw = false;
+        let mut json = false;
+        let mut positional = vec![];
+
+        while let Some(arg) = args.next() {
+            match (command.as_str(), arg.as_str()) {
+                ("play", "--entry") | ("render", "--entry") | ("record", "--entry") => {
+                    entry = Some(value(&mut args, &arg)?)
+                }
+                ("play", "--root") => {
+                    let root = value(&mut args, &arg)?;
+                    config
+                        .overrides
+                        .push(string_override("runtime.rootPath", &root));
+                }
+                ("play", "--backend") => {
+                    let backend = value(&mut args, &arg)?;
+                    config
+                        .overrides
+                        .push(string_override("runtime.backend", &backend));
+                }
+                ("render", "--output")
+                | ("render", "-o")
+                | ("record", "--output")
+                | ("record", "-o") => output = Some(PathBuf::from(value(&mut args, &arg)?)),
+                ("record", "--fps") => {
+                    fps = parse_value(&mut args, &arg)?;
+
+                    if fps == 0 {
+                        return Err(CliError::InvalidValue(arg, "0".into()));
+                    }
+                }
+                ("record", "--duration") => {
+                    let secs: f64 = parse_value(&mut args, &arg)?;
+
+                    if !secs.is_finite() || secs < 0.0 {
+                        return Err(CliError::InvalidValue(arg, secs.to_string()));
+                    }
+
+                    duration = Some(secs);
+                }
+                ("dump-mil", "--raw") => raw = true,
+                ("dump-mil", "--json") => json = true,
+                (_, "-h") | (_, "--help") => return Ok(Self::help(config)),
+                _ if arg.starts_with('-') => return Err(CliError::UnknownOption(arg)),
+                _ => positional.push(arg),
+            }
+        }
+
+        let command = match command.as_str() {
+            "play" => {
+                Self::no_positional(&positional)?;
+                Command::Play { entry }
+            }
+            "check" => Command::Check { files: positional },
+            "dump-mil" => Command::DumpMil {
+                file: Self::single(positional, "dump-mil", "a scenario file")?,
+                raw,
+                json,
+            },
+            "render" => {
+                let index = Self::single(positional, "render", "a dialogue index")?;
+                let output = output.unwrap_or_else(|| format!("dialogue{:05}.png", index).into());
+
+                Command::Render {
+                    entry,
+                    index: index.parse().map_err(|_| CliError::InvalidIndex(index))?,
+                    output,
+                }
+            }
+            "record" => {
+                Self::no_positional(&positional)?;
+
+                Command::Record {
+                    entry,
+                    fps,
+                    duration,
+                    output: output.unwrap_or_else(|| "record.y4m".into()),
+                }
+            }
+            "assets" => {
+                Self::no_positional(&positional)?;
+                Command::Assets
+            }
+            "help" => Command::Help,
+            _ => return Err(CliError::UnknownCommand(command)),
+        };
+
+        Ok(Self { config, command })
+    }
+
+    fn help(config: ConfigSource) -> Self {
+        Self {
+            config,
+            command: Command::Help,
+        }
+    }
+
+    fn no_positional(positional: &[String]) -> Result<(), CliError> {
+        match positional.first() {
+            Some(arg) => Err(CliError::UnexpectedArgument(arg.clone())),
+            None => Ok(()),
+        }
+    }
+
+    fn single(
+        positional: Vec<String>,
+        command: &'static str,
+        what: &'static str,
+    ) -> Result<String, CliError> {
+        let mut positional = positional.into_iter();
+
+        match (positional.next(), positional.next()) {
+            (Some(arg), None) => Ok(arg),
+            (Some(_), Some(extra)) => Err(CliError::UnexpectedArgument(extra)),
+            (None, _) => Err(CliError::MissingArgument(command, what)),
+        }
+    }
+}
+
+impl Command {
+    /// Whether the command runs without a window.
+    pub fn is_headless(&self) -> bool {
+        !matches!(self, Command::Play { .. })
+    }
+
+    /// Runs a headless command; returns `false` if it has found a problem.
+    pub fn run_tool(self) -> bool {
+        match self {
+            Command::Check { files } => check::run(&files),
+            Command::DumpMil { file, raw, json } => dump_mil::run(&file, raw, json),
+            Command::Render {
+                entry,
+                index,
+                output,
+            } => render::run(entry.as_deref(), index, &output),
+            Command::Record {
+                entry,
+                fps,
+                duration,
+                output,
+            } => record::run(entry.as_deref(), fps, duration, &output),
+            Command::Assets => assets::run(),
+            Command::Help => {
+                println!("{}", USAGE);
+                true
+            }
+            Command::Play { .. } => unreachable!("`play` is not a headless command"),
+        }
+    }
+}
+
+#[test]
+fn test_cli_parse() {
+    let parse = |args: &[&str]| Cli::parse(args.iter().map(|s| s.to_string()));
+
+    let cli = parse(&[]).unwrap();
+    assert_eq!(cli.command, Command::Play { entry: None });
+
+    let cli = parse(&[
+        "--set",
+        "runtime.debugConsole=true",
+        "play",
+        "--root",
+        "./data",
+        "--entry",
+        "a.TXT",
+    ])
+    .unwrap();
+    assert_eq!(
+        cli.command,
+        Command::Play {
+            entry: Some("a.TXT".into())
+        }
+    );
+    assert_eq!(
+        cli.config.overrides,
+        ["runtime.debugConsole=true", r#"runtime.rootPath="./data""#]
+    );
+
+    let cli = parse(&["render", "12", "-o", "out.png"]).unwrap();
+    assert_eq!(
+        cli.command,
+        Command::Render {
+            entry: None,
+            index: 12,
+            output: "out.png".into(),
+        }
+    );
+
+    let cli = parse(&["record", "--fps", "60", "--duration", "2.5", "-o", "frames"]).unwrap();
+    assert_eq!(
+        cli.command,
+        Command::Record {
+            entry: None,
+            fps: 60,
+            duration: Some(2.5),
+            output: "frames".into(),
+        }
+    );
+
+    assert!(matches!(
+        parse(&["record", "--fps", "0"]),
+        Err(CliError::InvalidValue(_, _))
+    ));
+
+    for secs in &["-1", "NaN", "inf"] {
+        assert!(matches!(
+            parse(&["record", "--duration", secs]),
+            Err(CliError::InvalidValue(_, _))
+        ));
+    }
+    assert!(matches!(
+        parse(&["dump-mil", "--raw"]),
+        Err(CliError::MissingArgument("dump-mil", _))
+    ));
+    assert!(matches!(
+        parse(&["play", "--fast"]),
+        Err(CliError::UnknownOption(_))
+    ));
+    assert!(matches!(
+        parse(&["render", "first"]),
+        Err(CliError::InvalidIndex(_))
+    ));
+}
+
+#[test]
+fn test_cli_backend() {
+    use crate::config::{Backend, ConfigError, EngineConfig};
+
+    let backend = |args: &[&str]| {
+        let cli = Cli::parse(args.iter().map(|s| s.to_string())).unwrap();
+        EngineConfig::load(&cli.config).map(|config| config.backend)
+    };
+
+    // the file picks vulkano, and the command line overrides it
+    let file = ["--config", "ReizeiinTohka.json"];
+    assert_eq!(backend(&file).unwrap(), Backend::Vulkano);
+    assert_eq!(
+        backend(&[&file[..], &["play", "--backend", "cpu"]].concat()).unwrap(),
+        Backend::Cpu
+    );
+    assert_eq!(
+        backend(&[&file[..], &["--set", "runtime.backend=cpu"]].concat()).unwrap(),
+        Backend::Cpu
+    );
+    assert!(matches!(
+        backend(&[&file[..], &["play", "--backend", "opengl"]].concat()),
+        Err(ConfigError::InvalidValue { .. })
+    ));
+}
@@ -0,0 +1,129 @@
+//! `record`: plays a scenario headless and writes out every frame.
+//!
+//! The story runs on a virtual clock at a fixed frame rate, advancing lines
+//! by the auto mode timing and taking the first option of every choice. The
+//! frames go to a Y4M stream if the output ends in `.y4m`, or to a numbered
+//! PNG sequence in the output directory otherwise.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::config;
+use crate::constants::{self, GAME_WINDOW_HEIGHT, GAME_WINDOW_WIDTH};
+use crate::format::y4m::Y4mWriter;
+use crate::game::auto::AutoModeTiming;
+use crate::game::headless::HeadlessPlayer;
+use crate::renderer::cpu::image::Image;
+use crate::script::runtime::js::JsRuntime;
+
+enum FrameSink {
+    Png { dir: PathBuf, count: usize },
+    Y4m(Y4mWriter<BufWriter<File>>),
+}
+
+impl FrameSink {
+    fn create(output: &Path, fps: u32) -> std::io::Result<Self> {
+        let is_y4m = output
+            .extension()
+            .map(|ext| ext.to_string_lossy().eq_ignore_ascii_case("y4m"))
+            .unwrap_or_default();
+
+        if !is_y4m {
+            std::fs::create_dir_all(output)?;
+
+            return Ok(FrameSink::Png {
+                dir: output.into(),
+                count: 0,
+            });
+        }
+
+        let file = BufWriter::new(File::create(output)?);
+        let writer = Y4mWriter::new(
+            file,
+            GAME_WINDOW_WIDTH as usize,
+            GAME_WINDOW_HEIGHT as usize,
+            fps,
+        )?;
+
+        Ok(FrameSink::Y4m(writer))
+    }
+
+    fn write(&mut self, image: &Image) -> std::io::Result<()> {
+        match self {
+            FrameSink::Png { dir, count } => {
+                image.save_png(dir.join(format!("frame{:06}.png", count)))?;
+                *count += 1;
+                Ok(())
+            }
+            FrameSink::Y4m(writer) => writer.write_frame(&image.rgba_buffer),
+        }
+    }
+
+    fn finish(self) -> std::io::Result<()> {
+        match self {
+            FrameSink::Png { .. } => Ok(()),
+            FrameSink::Y4m(writer) => writer.into_inner().flush(),
+        }
+    }
+}
+
+/// Records from `entry` until the story ends, or for `duration` seconds.
+pub fn run(entry: Option<&str>, fps: u32, duration: Option<f64>, output: &Path) -> bool {
+    let entry = entry.unwrap_or_else(|| config::get_entry_scenario());
+
+    let mut player = match HeadlessPlayer::with_loader(entry, JsRuntime::new()) {
+        Ok(player) => player,
+        Err(err) => {
+            eprintln!("{}: {}", entry, err);
+            return false;
+        }
+    };
+
+    player.set_auto_timing(AutoModeTiming::from_config());
+
+    if config::find_asset(constants::FONT_PATH).is_some() {
+        player.set_show_text(true);
+    } else {
+        log::warn!("font not found; the dialogue is not drawn");
+    }
+
+    let mut sink = match FrameSink::create(output, fps) {
+        Ok(sink) => sink,
+        Err(err) => {
+            eprintln!("{}: {}", output.display(), err);
+            return false;
+        }
+    };
+
+    let frame_duration = Duration::from_secs(1) / fps;
+    let limit = duration.map(Duration::from_secs_f64);
+    let mut frames = 0;
+
+    while !player.is_finished() && limit.map_or(true, |limit| player.elapsed() < limit) {
+        let image = player.step(frame_duration);
+
+        if let Err(err) = sink.write(image) {
+            eprintln!("{}: {}", output.display(), err);
+            return false;
+        }
+
+        frames += 1;
+    }
+
+    if let Err(err) = sink.finish() {
+        eprintln!("{}: {}", output.display(), err);
+        return false;
+    }
+
+    println!(
+        "{} frames ({:.2}s at {} fps) -> {}",
+        frames,
+        player.elapsed().as_secs_f64(),
+        fps,
+        output.display()
+    );
+
+    true
+}
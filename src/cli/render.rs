@@ -0,0 +1,160 @@
+//! `render`: composites a dialogue line to a PNG.
+//!
+//! The story is stepped through without waits up to the line, taking the
+//! first option of every choice, and the frame is drawn by the CPU compositor
+//! on a headless surface.
+
+use std::path::Path;
+
+use crate::config;
+use crate::constants::{self, GAME_WINDOW_HEIGHT, GAME_WINDOW_WIDTH};
+use crate::game::screen::cpu::CpuCompositor;
+use crate::game::screen::{Frame, TextBox};
+use crate::renderer::cpu::headless::HeadlessSurface;
+use crate::renderer::cpu::image::Image;
+use crate::script::mil::command::{self, Command, LayerCommand, RendererCommand, RuntimeCommand};
+use crate::script::runtime::backlog::LogEntry;
+use crate::script::runtime::interpreter::Interpreter;
+use crate::script::runtime::js::JsRuntime;
+use crate::script::runtime::savedata::LayerState;
+
+/// Runs a program without waiting, tracking what is on the screen.
+struct Stepper {
+    js: JsRuntime,
+    interp: Interpreter,
+    layers: Vec<LayerState>,
+}
+
+impl Stepper {
+    fn new(scenario: &str) -> std::io::Result<Self> {
+        let mut js = JsRuntime::new();
+        let mut interp = Interpreter::new();
+
+        interp.set_chain(config::get_scenario_chain());
+        interp.load(&mut js, scenario)?;
+
+        Ok(Self {
+            js,
+            interp,
+            layers: vec![],
+        })
+    }
+
+    /// Runs up to the dialogue line `index`, counted from zero.
+    fn run_to(&mut self, index: usize) -> Option<LogEntry> {
+        let mut count = 0;
+
+        while let Some(command) = self.interp.next(&mut self.js) {
+            match command {
+                Command::LayerCommand { layer_no, command } => {
+                    let index = match command::layer_index(layer_no) {
+                        Some(index) => index,
+                        None => {
+                            log::error!("no such layer: {}; dropped {:?}", layer_no, command);
+                            continue;
+                        }
+                    };
+
+                    if self.layers.len() <= index {
+                        self.layers.resize_with(index + 1, Default::default);
+                    }
+
+                    self.layers[index].apply(&command);
+                }
+                Command::RendererCommand(RendererCommand::Dialogue(name, text)) => {
+                    if count == index {
+                        return Some(LogEntry {
+                            name,
+                            text,
+                            voice: None,
+                        });
+                    }
+
+                    count += 1;
+                }
+                Command::RuntimeCommand(RuntimeCommand::Choice(options)) => {
+                    if let Some(option) = options.first() {
+                        log::info!("choosing `{}`", option.text);
+                        self.interp.branch(&option.label);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        None
+    }
+}
+
+fn composite(layers: &[LayerState], dialogue: &LogEntry) -> Image {
+    let mut compositor = CpuCompositor::new(layers.len());
+
+    for (layer_no, state) in layers.iter().enumerate() {
+        for command in state.restore(layer_no as i32) {
+            if let Command::LayerCommand { command, .. } = command {
+                compositor.send(layer_no, command);
+            }
+        }
+
+        // no transition while composited at once
+        compositor.send(layer_no, LayerCommand::FinalizeAnimation);
+    }
+
+    let mut frame = Frame::default();
+
+    if config::find_asset(constants::FONT_PATH).is_some() {
+        frame.texts.push(TextBox::new(
+            constants::DIALOGUE_OFFSET,
+            constants::DIALOGUE_SIZE,
+            format!(
+                "{}\n{}",
+                dialogue.name.as_deref().unwrap_or_default(),
+                dialogue.text
+            ),
+        ));
+    } else {
+        log::warn!("font not found; the dialogue is not drawn");
+    }
+
+    let mut surface = HeadlessSurface::new(GAME_WINDOW_WIDTH as usize, GAME_WINDOW_HEIGHT as usize);
+    surface
+        .step(|target| compositor.draw(target, &frame))
+        .clone()
+}
+
+pub fn run(entry: Option<&str>, index: usize, output: &Path) -> bool {
+    let entry = entry.unwrap_or_else(|| config::get_entry_scenario());
+
+    let dialogue = Stepper::new(entry).map(|mut stepper| {
+        let dialogue = stepper.run_to(index);
+        dialogue.map(|dialogue| (dialogue, stepper.layers))
+    });
+
+    let (dialogue, layers) = match dialogue {
+        Ok(Some(dialogue)) => dialogue,
+        Ok(None) => {
+            eprintln!("{}: the story ends before line {}", entry, index);
+            return false;
+        }
+        Err(err) => {
+            eprintln!("{}: {}", entry, err);
+            return false;
+        }
+    };
+
+    let screen = composite(&layers, &dialogue);
+
+    if let Err(err) = screen.save_png(output) {
+        eprintln!("{}: {}", output.display(), err);
+        return false;
+    }
+
+    println!(
+        "{}「{}」 -> {}",
+        dialogue.name.as_deref().unwrap_or_default(),
+        dialogue.text,
+        output.display()
+    );
+
+    true
+}
@@ -1,4 +1,16 @@
-use miniserde::{json, json::Value};
+//! Engine configuration.
+//!
+//! Built from layered sources, each overriding the previous one: built-in
+//! defaults, the configuration file (`ReizeiinTohka.json`, or the one given by
+//! the `TOHKA_CONFIG` environment variable or the command line), then
+//! `key=value` overrides from the command line.
+//!
+//! Keys are dotted paths such as `runtime.auto.baseDelay`; objects in the file
+//! are flattened, so `{ "backend.vulkano": { "lruCache": 20 } }` sets
+//! `backend.vulkano.lruCache`.
+
+use miniserde::json::{self, Number, Value};
+use thiserror::Error;
 
 use crate::constants;
 use constants::NKTS_CONFIG_DEFAULT_PATH;
@@ -6,42 +18,377 @@ use constants::NKTS_CONFIG_ENV;
 
 use lazy_static::*;
 
-use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("failed to read {0:?}: {1}")]
+    Io(PathBuf, std::io::Error),
+    #[error("{0:?} is not a JSON object")]
+    Parse(PathBuf),
+    #[error("unknown key `{0}`")]
+    UnknownKey(String),
+    #[error("`{key}` must be {expected}")]
+    InvalidValue { key: String, expected: &'static str },
+    #[error("invalid override `{0}`; expected `key=value`")]
+    InvalidOverride(String),
+}
+
+/// Graphic backends.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Backend {
+    Vulkano,
+    /// Software rendering, presented through Vulkano.
+    Cpu,
+}
+
+/// How the screen is scaled to a window of another size.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ScaleMode {
+    /// As large as the window allows.
+    Fit,
+    /// By the largest whole factor the window allows; fits if there is none.
+    Integer,
+}
+
+/// Filters the CPU backend resamples frames with.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ScaleFilter {
+    Bilinear,
+    /// Lanczos with three lobes; sharper, but slower.
+    Lanczos,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AutoConfig {
+    pub base_delay: f64,
+    pub char_delay: f64,
+    pub voice_delay: f64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct VulkanoConfig {
+    /// Prefers discrete GPUs to integrated ones.
+    pub use_discrete_gpu: bool,
+    /// Number of images cached per layer.
+    pub lru_cache: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RendererConfig {
+    /// Blends colors in linear light instead of as sRGB-encoded.
+    pub linear_blending: bool,
+    pub scale_mode: ScaleMode,
+    pub scale_filter: ScaleFilter,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct EngineConfig {
+    pub title: String,
+    pub root_path: String,
+    pub save_path: String,
+    pub entry: String,
+    pub chain: Vec<String>,
+    pub passes: Vec<String>,
+    pub debug_console: bool,
+    pub backend: Backend,
+    pub auto: AutoConfig,
+    pub renderer: RendererConfig,
+    pub vulkano: VulkanoConfig,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        Self {
+            title: constants::GAME_ENGINE_FULL_NAME.into(),
+            root_path: "./blob".into(),
+            save_path: "./save".into(),
+            entry: "./testcase/02_NK_23H.TXT".into(),
+            chain: vec![],
+            passes: vec![],
+            debug_console: false,
+            backend: Backend::Vulkano,
+            auto: AutoConfig {
+                base_delay: constants::AUTO_MODE_BASE_DELAY,
+                char_delay: constants::AUTO_MODE_CHAR_DELAY,
+                voice_delay: constants::AUTO_MODE_VOICE_DELAY,
+            },
+            renderer: RendererConfig {
+                linear_blending: false,
+                scale_mode: ScaleMode::Fit,
+                scale_filter: ScaleFilter::Bilinear,
+            },
+            vulkano: VulkanoConfig {
+                use_discrete_gpu: true,
+                lru_cache: constants::LRU_CACHE_CAPACITY,
+            },
+        }
+    }
+}
+
+/// Where the configuration comes from, besides the defaults.
+#[derive(Clone, Debug, Default)]
+pub struct ConfigSource {
+    /// Configuration file; overrides `TOHKA_CONFIG`.
+    pub path: Option<PathBuf>,
+    /// `key=value` pairs; values are JSON, or strings if not valid JSON.
+    pub overrides: Vec<String>,
+}
+
+// value accessors for validation
+
+fn invalid(key: &str, expected: &'static str) -> ConfigError {
+    ConfigError::InvalidValue {
+        key: key.into(),
+        expected,
+    }
+}
+
+fn string(key: &str, v: &Value) -> Result<String, ConfigError> {
+    match v {
+        Value::String(v) => Ok(v.clone()),
+        _ => Err(invalid(key, "a string")),
+    }
+}
+
+fn strings(key: &str, v: &Value) -> Result<Vec<String>, ConfigError> {
+    match v {
+        Value::Array(values) => values.iter().map(|v| string(key, v)).collect(),
+        _ => Err(invalid(key, "an array of strings")),
+    }
+}
+
+fn bool(key: &str, v: &Value) -> Result<bool, ConfigError> {
+    match v {
+        Value::Bool(v) => Ok(*v),
+        _ => Err(invalid(key, "a boolean")),
+    }
+}
+
+fn number(key: &str, v: &Value) -> Result<f64, ConfigError> {
+    match v {
+        Value::Number(Number::U64(v)) => Ok(*v as f64),
+        Value::Number(Number::I64(v)) if *v >= 0 => Ok(*v as f64),
+        Value::Number(Number::F64(v)) if *v >= 0.0 => Ok(*v),
+        _ => Err(invalid(key, "a non-negative number")),
+    }
+}
+
+fn count(key: &str, v: &Value) -> Result<usize, ConfigError> {
+    match v {
+        Value::Number(Number::U64(v)) if *v > 0 => Ok(*v as usize),
+        Value::Number(Number::I64(v)) if *v > 0 => Ok(*v as usize),
+        _ => Err(invalid(key, "a positive integer")),
+    }
+}
+
+// flattens nested objects into dotted keys
+fn flatten(prefix: &str, value: &Value, out: &mut Vec<(String, Value)>) {
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object.iter() {
+                let key = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
 
-pub type Config = BTreeMap<String, json::Value>;
+                flatten(&key, value, out);
+            }
+        }
+        _ => out.push((prefix.into(), value.clone())),
+    }
+}
+
+impl EngineConfig {
+    /// Loads the configuration from `source` on top of the defaults.
+    pub fn load(source: &ConfigSource) -> Result<Self, ConfigError> {
+        use std::env;
+
+        let mut config = Self::default();
+
+        // only the default file may be missing
+        let path = source
+            .path
+            .clone()
+            .or_else(|| env::var_os(NKTS_CONFIG_ENV).map(PathBuf::from));
+
+        match path {
+            Some(path) => config.apply_file(&path)?,
+            None => {
+                let path = PathBuf::from(NKTS_CONFIG_DEFAULT_PATH);
+
+                if path.exists() {
+                    config.apply_file(&path)?;
+                } else {
+                    log::warn!("{:?} not found; using the defaults", path);
+                }
+            }
+        }
+
+        for item in &source.overrides {
+            config.apply_override(item)?;
+        }
+
+        Ok(config)
+    }
+
+    fn apply_file(&mut self, path: &Path) -> Result<(), ConfigError> {
+        let file =
+            std::fs::read_to_string(path).map_err(|err| ConfigError::Io(path.into(), err))?;
+        let value: Value = json::from_str(&file).map_err(|_| ConfigError::Parse(path.into()))?;
+
+        if !matches!(value, Value::Object(_)) {
+            return Err(ConfigError::Parse(path.into()));
+        }
+
+        let mut entries = vec![];
+        flatten("", &value, &mut entries);
+
+        for (key, value) in &entries {
+            self.set(key, value)?;
+        }
+
+        Ok(())
+    }
+
+    /// Applies a `key=value` override.
+    pub fn apply_override(&mut self, item: &str) -> Result<(), ConfigError> {
+        let mut split = item.splitn(2, '=');
+
+        let (key, value) = match (split.next(), split.next()) {
+            (Some(key), Some(value)) if !key.is_empty() => (key.trim(), value),
+            _ => return Err(ConfigError::InvalidOverride(item.into())),
+        };
+
+        let value = json::from_str(value).unwrap_or_else(|_| Value::String(value.into()));
+
+        self.set(key, &value)
+    }
+
+    /// Sets a key; fails if the key is unknown or the value is invalid.
+    pub fn set(&mut self, key: &str, v: &Value) -> Result<(), ConfigError> {
+        match key {
+            "runtime.title" => self.title = string(key, v)?,
+            "runtime.rootPath" => self.root_path = string(key, v)?,
+            "runtime.savePath" => self.save_path = string(key, v)?,
+            "runtime.entry" => self.entry = string(key, v)?,
+            "runtime.chain" => self.chain = strings(key, v)?,
+            "runtime.passes" => self.passes = strings(key, v)?,
+            "runtime.debugConsole" => self.debug_console = bool(key, v)?,
+            "runtime.backend" => {
+                self.backend = match string(key, v)?.as_str() {
+                    "vulkano" => Backend::Vulkano,
+                    "cpu" => Backend::Cpu,
+                    _ => return Err(invalid(key, "\"vulkano\" or \"cpu\"")),
+                }
+            }
+            "runtime.auto.baseDelay" => self.auto.base_delay = number(key, v)?,
+            "runtime.auto.charDelay" => self.auto.char_delay = number(key, v)?,
+            "runtime.auto.voiceDelay" => self.auto.voice_delay = number(key, v)?,
+            "renderer.linearBlending" => self.renderer.linear_blending = bool(key, v)?,
+            "renderer.scaleMode" => {
+                self.renderer.scale_mode = match string(key, v)?.as_str() {
+                    "fit" => ScaleMode::Fit,
+                    "integer" => ScaleMode::Integer,
+                    _ => return Err(invalid(key, "\"fit\" or \"integer\"")),
+                }
+            }
+            "renderer.scaleFilter" => {
+                self.renderer.scale_filter = match string(key, v)?.as_str() {
+                    "bilinear" => ScaleFilter::Bilinear,
+                    "lanczos" => ScaleFilter::Lanczos,
+                    _ => return Err(invalid(key, "\"bilinear\" or \"lanczos\"")),
+                }
+            }
+            "backend.vulkano.useDiscreteGpu" => self.vulkano.use_discrete_gpu = bool(key, v)?,
+            "backend.vulkano.lruCache" => self.vulkano.lru_cache = count(key, v)?,
+            _ => return Err(ConfigError::UnknownKey(key.into())),
+        }
+
+        Ok(())
+    }
+}
 
 lazy_static! {
-    pub static ref CONFIG: Config = open_config();
+    // set by `init` before the first access to `CONFIG`
+    static ref PENDING_CONFIG: Mutex<Option<EngineConfig>> = Mutex::new(None);
+
+    pub static ref CONFIG: EngineConfig = {
+        let config = PENDING_CONFIG.lock().unwrap().take();
+
+        config.unwrap_or_else(|| {
+            EngineConfig::load(&Default::default()).unwrap_or_else(|err| {
+                log::error!("invalid configuration: {}", err);
+                Default::default()
+            })
+        })
+    };
 }
 
-pub fn open_config() -> Config {
-    use std::env;
+/// Loads the configuration; must be called before anything reads `CONFIG`.
+pub fn init(source: &ConfigSource) -> Result<(), ConfigError> {
+    let config = EngineConfig::load(source)?;
+    *PENDING_CONFIG.lock().unwrap() = Some(config);
 
-    json::from_str(
-        &std::fs::read_to_string(
-            env::var(NKTS_CONFIG_ENV).unwrap_or(NKTS_CONFIG_DEFAULT_PATH.into()),
-        )
-        .unwrap(),
-    )
-    .expect("failed to parse a configuration file")
+    Ok(())
 }
 
 pub fn get_game_title() -> &'static str {
-    match CONFIG.get("runtime.title") {
-        Some(Value::String(str)) => &str,
-        _ => constants::GAME_ENGINE_FULL_NAME,
-    }
+    &CONFIG.title
 }
 
 pub fn get_root_path() -> &'static str {
-    match CONFIG.get("runtime.rootPath") {
-        Some(Value::String(str)) => &str,
-        _ => "./blob",
-    }
+    &CONFIG.root_path
+}
+
+pub fn get_save_path() -> &'static str {
+    &CONFIG.save_path
+}
+
+/// The scenario the game starts from.
+pub fn get_entry_scenario() -> &'static str {
+    &CONFIG.entry
+}
+
+/// Scenarios in the order played; each one continues to the next at its end.
+pub fn get_scenario_chain() -> Vec<&'static str> {
+    CONFIG.chain.iter().map(String::as_str).collect()
+}
+
+/// Whether the JavaScript debug console is read from stdin.
+pub fn is_debug_console_enabled() -> bool {
+    CONFIG.debug_console
+}
+
+/// Whether colors are blended in linear light, by either backend.
+pub fn is_linear_blending_enabled() -> bool {
+    CONFIG.renderer.linear_blending
+}
+
+/// How the screen is scaled to the window.
+pub fn get_scale_mode() -> ScaleMode {
+    CONFIG.renderer.scale_mode
+}
+
+/// The filter the CPU backend scales frames to the window with.
+pub fn get_scale_filter() -> ScaleFilter {
+    CONFIG.renderer.scale_filter
+}
+
+/// Scripts of the MIL passes written in JavaScript, in the order applied.
+pub fn get_pass_scripts() -> Vec<&'static str> {
+    CONFIG.passes.iter().map(String::as_str).collect()
 }
 
 use std::path::{Path, PathBuf};
 
+pub fn persistent_data_path() -> PathBuf {
+    let mut path = PathBuf::from(get_save_path());
+    path.push(constants::PERSISTENT_DATA_FILENAME);
+    path
+}
+
 pub fn find_asset<P>(path: P) -> Option<PathBuf>
 where
     P: AsRef<Path>,
@@ -67,3 +414,52 @@ where
 
     Some(output_path)
 }
+
+#[test]
+fn test_engine_config() {
+    let config = EngineConfig::load(&ConfigSource {
+        path: Some("ReizeiinTohka.json".into()),
+        overrides: vec![
+            "runtime.title=nkts".into(),
+            "backend.vulkano.lruCache=5".into(),
+        ],
+    })
+    .unwrap();
+
+    // the nested object in the file
+    assert!(config.vulkano.use_discrete_gpu);
+    assert_eq!(config.vulkano.lru_cache, 5);
+    assert_eq!(config.title, "nkts");
+    assert_eq!(config.auto.base_delay, 1000.0);
+
+    let mut config = EngineConfig::default();
+    assert!(matches!(
+        config.apply_override("runtime.auto.charDelay=fast"),
+        Err(ConfigError::InvalidValue { key, .. }) if key == "runtime.auto.charDelay"
+    ));
+    assert!(matches!(
+        config.apply_override("runtime.rootpath=./blob"),
+        Err(ConfigError::UnknownKey(key)) if key == "runtime.rootpath"
+    ));
+    assert!(matches!(
+        config.apply_override("runtime.backend=\"opengl\""),
+        Err(ConfigError::InvalidValue { .. })
+    ));
+
+    config.apply_override("runtime.backend=cpu").unwrap();
+    assert_eq!(config.backend, Backend::Cpu);
+
+    assert!(!config.renderer.linear_blending);
+    config
+        .apply_override("renderer.linearBlending=true")
+        .unwrap();
+    assert!(config.renderer.linear_blending);
+
+    config.apply_override("renderer.scaleMode=integer").unwrap();
+    config
+        .apply_override("renderer.scaleFilter=lanczos")
+        .unwrap();
+    assert_eq!(config.renderer.scale_mode, ScaleMode::Integer);
+    assert_eq!(config.renderer.scale_filter, ScaleFilter::Lanczos);
+    assert!(config.apply_override("renderer.scaleMode=stretch").is_err());
+}
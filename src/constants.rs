@@ -5,12 +5,39 @@ pub(crate) static GAME_ENGINE_NAME: &str = "ReizeiinTohka";
 pub(crate) static NKTS_CONFIG_ENV: &str = "TOHKA_CONFIG";
 pub(crate) static NKTS_CONFIG_DEFAULT_PATH: &str = "ReizeiinTohka.json";
 
+// global savedata (read text, unlocked CGs, preferences)
+pub(crate) static PERSISTENT_DATA_FILENAME: &str = "global.json";
+
 // nukitashi uses 1600x900 as a global resolution
 pub(crate) const GAME_WINDOW_WIDTH: u32 = 1600;
 pub(crate) const GAME_WINDOW_HEIGHT: u32 = 900;
 
 pub(crate) const TOTAL_LAYERS: i32 = 25;
 
+// layers of the screen; scripts number them from zero
+pub(crate) const LAYER_COUNT: usize = 30;
+
+// dialogue box
+pub(crate) const DIALOGUE_OFFSET: (i32, i32) = (380, 640);
+pub(crate) const DIALOGUE_SIZE: (i32, i32) = (900, 300);
+pub(crate) const DIALOGUE_FONT_HEIGHT: f32 = 44.0;
+
+// auto mode timing (in milliseconds)
+pub(crate) const AUTO_MODE_BASE_DELAY: f64 = 1000.0;
+pub(crate) const AUTO_MODE_CHAR_DELAY: f64 = 80.0;
+pub(crate) const AUTO_MODE_VOICE_DELAY: f64 = 500.0;
+
+// number of dialogue lines kept in the backlog
+pub(crate) const BACKLOG_CAPACITY: usize = 200;
+
+// rewind snapshots are taken every this many lines
+pub(crate) const REWIND_SNAPSHOT_INTERVAL: usize = 10;
+pub(crate) const REWIND_SNAPSHOT_CAPACITY: usize = 50;
+
+// limits of the JS runtime; timeout per call in milliseconds, heap in bytes
+pub(crate) const JS_EXECUTION_TIMEOUT: u64 = 5000;
+pub(crate) const JS_HEAP_LIMIT: usize = 64 << 20;
+
 // font
 pub(crate) static FONT_PATH: &str = "NUKITASHI_D.WAR/ROUNDED-X-MGENPLUS-1M.TTF";
 pub(crate) static LRU_CACHE_CAPACITY: usize = 20;
@@ -89,10 +89,3 @@ pub fn load_emotbl<P: AsRef<Path>>(path: P) -> std::io::Result<Emotbl> {
 
     Ok(res)
 }
-
-#[test]
-fn test_emotbl() {
-    let emotbl = load_emotbl("./blob/NUKITASHI_T.WAR/EMOTBL.BIN").unwrap();
-
-    println!("{:#?}", emotbl);
-}
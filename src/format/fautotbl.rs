@@ -78,37 +78,3 @@ pub fn load_face_map<P: AsRef<Path>>(path: P) -> std::io::Result<(Vec<String>, V
 
     Ok((face_filenames, face_names))
 }
-
-#[test]
-fn test_fautotbl() {
-    let (a, b) = load_face_map("./blob/NUKITASHI_T.WAR/FAUTOTBL.BIN").unwrap();
-
-    assert_eq!(a.len(), b.len());
-
-    assert_eq!(
-        a,
-        [
-            "ASANE", "FUMINO", "HINAMI", "HITOURA", "IKUKO", "JUN", "KOUKI", "MISAKI", "NANASE",
-            "RAN", "REI", "SAKIMORI", "TESHIMA", "TOUKA"
-        ]
-    );
-    assert_eq!(
-        b,
-        [
-            "麻沙音",
-            "文乃",
-            "ヒナミ",
-            "仁浦",
-            "郁子",
-            "淳之介",
-            "光姫",
-            "美岬",
-            "奈々瀬",
-            "蘭",
-            "礼",
-            "防人老人",
-            "手嶋",
-            "桐香"
-        ]
-    );
-}
@@ -4,3 +4,4 @@ pub mod movtbl;
 pub mod oggtbl;
 pub mod s25;
 pub mod voitbl;
+pub mod y4m;
@@ -1,2 +1,109 @@
 // Use s25 crate.
 pub use s25::{Result, S25Archive, S25Image};
+
+const S25_MAGIC: &[u8; 4] = b"S25\0";
+// longest run of a row command, in pixels
+const MAX_RUN: usize = 0x7ff;
+// method of the row commands; ABGR without compression
+const METHOD_ABGR: u16 = 4;
+
+/// An image to be encoded into an S25 archive.
+pub struct Entry<'a> {
+    pub entry_no: usize,
+    pub offset: (i32, i32),
+    pub width: usize,
+    pub height: usize,
+    /// RGBA pixels, row by row.
+    pub rgba_buffer: &'a [u8],
+}
+
+/// Encodes images into an S25 archive, without compression.
+///
+/// Meant for test fixtures; `s25::S25Writer` swaps the channels of its
+/// input, so the rows are written here.
+pub fn encode(entries: &[Entry]) -> Vec<u8> {
+    let total_entries = entries.iter().map(|e| e.entry_no + 1).max().unwrap_or(0);
+
+    let mut buf = S25_MAGIC.to_vec();
+    push_i32(&mut buf, total_entries as i32);
+
+    let table = buf.len();
+    buf.resize(table + total_entries * 4, 0);
+
+    for entry in entries {
+        assert_eq!(entry.rgba_buffer.len(), entry.width * entry.height * 4);
+
+        let offset = buf.len();
+        buf[table + entry.entry_no * 4..][..4].copy_from_slice(&(offset as i32).to_le_bytes());
+
+        push_i32(&mut buf, entry.width as i32);
+        push_i32(&mut buf, entry.height as i32);
+        push_i32(&mut buf, entry.offset.0);
+        push_i32(&mut buf, entry.offset.1);
+        push_i32(&mut buf, 0); // not incremental
+
+        let rows = buf.len();
+        buf.resize(rows + entry.height * 4, 0);
+
+        for (y, row) in entry
+            .rgba_buffer
+            .chunks(entry.width * 4)
+            .take(entry.height)
+            .enumerate()
+        {
+            let row_offset = buf.len();
+            buf[rows + y * 4..][..4].copy_from_slice(&(row_offset as i32).to_le_bytes());
+
+            let runs = (entry.width + MAX_RUN - 1) / MAX_RUN;
+            push_i16(&mut buf, (runs * 2 + entry.width * 4) as i16);
+
+            for run in row.chunks(MAX_RUN * 4) {
+                let count = (run.len() / 4) as u16;
+                buf.extend_from_slice(&((METHOD_ABGR << 13) | count).to_le_bytes());
+
+                for p in run.chunks(4) {
+                    buf.extend_from_slice(&[p[3], p[2], p[1], p[0]]);
+                }
+            }
+        }
+    }
+
+    buf
+}
+
+fn push_i16(buf: &mut Vec<u8>, v: i16) {
+    buf.extend_from_slice(&v.to_le_bytes());
+}
+
+fn push_i32(buf: &mut Vec<u8>, v: i32) {
+    buf.extend_from_slice(&v.to_le_bytes());
+}
+
+#[test]
+fn test_encode_roundtrip() {
+    let width = 3000;
+    let rgba_buffer: Vec<u8> = (0..width * 2)
+        .flat_map(|i| vec![i as u8, (i >> 8) as u8, 0x80, (i % 7) as u8 * 40])
+        .collect();
+
+    let bytes = encode(&[Entry {
+        entry_no: 2,
+        offset: (10, -20),
+        width,
+        height: 2,
+        rgba_buffer: &rgba_buffer,
+    }]);
+
+    let mut archive = S25Archive::from_raw_bytes(&bytes).unwrap();
+    assert_eq!(archive.total_entries(), 3);
+    assert!(archive.load_image(0).is_err());
+
+    let image = archive.load_image(2).unwrap();
+    assert_eq!(image.metadata.width, width as i32);
+    assert_eq!(image.metadata.height, 2);
+    assert_eq!(
+        (image.metadata.offset_x, image.metadata.offset_y),
+        (10, -20)
+    );
+    assert_eq!(image.rgba_buffer(), rgba_buffer);
+}
@@ -0,0 +1,118 @@
+//! YUV4MPEG2 writer.
+//!
+//! Uncompressed 4:2:0 video that encoders such as ffmpeg read as they are.
+//! The pixels are converted with BT.601 in the limited range; alpha is
+//! dropped, since composited frames are opaque.
+
+use std::io::Write;
+
+pub struct Y4mWriter<W: Write> {
+    writer: W,
+    width: usize,
+    height: usize,
+    frame: Vec<u8>,
+}
+
+impl<W: Write> Y4mWriter<W> {
+    /// Writes the stream header; `fps` is in frames per second.
+    pub fn new(mut writer: W, width: usize, height: usize, fps: u32) -> std::io::Result<Self> {
+        writeln!(
+            writer,
+            "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C420jpeg",
+            width, height, fps
+        )?;
+
+        Ok(Self {
+            writer,
+            width,
+            height,
+            frame: vec![],
+        })
+    }
+
+    /// Writes a frame of RGBA pixels, row by row.
+    pub fn write_frame(&mut self, rgba: &[u8]) -> std::io::Result<()> {
+        assert_eq!(rgba.len(), self.width * self.height * 4);
+
+        let (width, height) = (self.width, self.height);
+        let (chroma_width, chroma_height) = ((width + 1) / 2, (height + 1) / 2);
+
+        self.frame.clear();
+        self.frame.extend_from_slice(b"FRAME\n");
+
+        for p in rgba.chunks(4) {
+            let (r, g, b) = (p[0] as i32, p[1] as i32, p[2] as i32);
+            self.frame
+                .push((((66 * r + 129 * g + 25 * b + 128) >> 8) + 16) as u8);
+        }
+
+        // chroma of each 2x2 block, clamped at the edges
+        let mut u = Vec::with_capacity(chroma_width * chroma_height);
+        let mut v = Vec::with_capacity(chroma_width * chroma_height);
+
+        for cy in 0..chroma_height {
+            for cx in 0..chroma_width {
+                let (mut r, mut g, mut b) = (0, 0, 0);
+
+                for (dx, dy) in &[(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    let x = (cx * 2 + dx).min(width - 1);
+                    let y = (cy * 2 + dy).min(height - 1);
+                    let p = &rgba[(x + y * width) * 4..][..3];
+
+                    r += p[0] as i32;
+                    g += p[1] as i32;
+                    b += p[2] as i32;
+                }
+
+                let (r, g, b) = (r / 4, g / 4, b / 4);
+                u.push((((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128) as u8);
+                v.push((((112 * r - 94 * g - 18 * b + 128) >> 8) + 128) as u8);
+            }
+        }
+
+        self.frame.extend_from_slice(&u);
+        self.frame.extend_from_slice(&v);
+
+        self.writer.write_all(&self.frame)
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+#[test]
+fn test_y4m_writer() {
+    let mut writer = Y4mWriter::new(vec![], 3, 2, 30).unwrap();
+
+    // white, black, red; then all blue
+    let mut rgba = vec![];
+    for p in &[
+        [0xff, 0xff, 0xff, 0xff],
+        [0x00, 0x00, 0x00, 0xff],
+        [0xff, 0x00, 0x00, 0xff],
+        [0x00, 0x00, 0xff, 0xff],
+        [0x00, 0x00, 0xff, 0xff],
+        [0x00, 0x00, 0xff, 0xff],
+    ] {
+        rgba.extend_from_slice(p);
+    }
+
+    writer.write_frame(&rgba).unwrap();
+    writer.write_frame(&rgba).unwrap();
+
+    let stream = writer.into_inner();
+    let header = b"YUV4MPEG2 W3 H2 F30:1 Ip A1:1 C420jpeg\n";
+    assert!(stream.starts_with(header));
+
+    let frame_len = 6 + 3 * 2 + 2 * 2;
+    assert_eq!(stream.len(), header.len() + frame_len * 2);
+
+    let frame = &stream[header.len()..][..frame_len];
+    assert_eq!(&frame[..6], b"FRAME\n");
+    // luma in 16..=235
+    assert_eq!(&frame[6..12], &[235, 16, 82, 41, 41, 41]);
+    // U then V; the right block is half red, half blue, as the edge column
+    // is repeated
+    assert_eq!(&frame[12..16], &[184, 165, 119, 175]);
+}
@@ -0,0 +1,77 @@
+//! Auto mode timing.
+
+use std::time::{Duration, Instant};
+
+use crate::{config, constants};
+
+/// Timing constants of the auto mode, in milliseconds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AutoModeTiming {
+    /// Delay before advancing a line without voice.
+    pub base_delay: f64,
+    /// Additional delay per character of a line without voice.
+    pub char_delay: f64,
+    /// Delay after the voice line finishes.
+    pub voice_delay: f64,
+}
+
+impl Default for AutoModeTiming {
+    fn default() -> Self {
+        Self {
+            base_delay: constants::AUTO_MODE_BASE_DELAY,
+            char_delay: constants::AUTO_MODE_CHAR_DELAY,
+            voice_delay: constants::AUTO_MODE_VOICE_DELAY,
+        }
+    }
+}
+
+impl AutoModeTiming {
+    pub fn from_config() -> Self {
+        let auto = &config::CONFIG.auto;
+
+        Self {
+            base_delay: auto.base_delay,
+            char_delay: auto.char_delay,
+            voice_delay: auto.voice_delay,
+        }
+    }
+
+    /// Returns the time to advance from a line.
+    ///
+    /// `voice_end` is the time the voice of the line finishes, if voiced.
+    pub fn deadline(&self, now: Instant, chars: usize, voice_end: Option<Instant>) -> Instant {
+        match voice_end {
+            Some(voice_end) => voice_end.max(now) + Self::millis(self.voice_delay),
+            None => now + Self::millis(self.base_delay + self.char_delay * chars as f64),
+        }
+    }
+
+    fn millis(ms: f64) -> Duration {
+        Duration::from_secs_f64(ms.max(0.0) / 1000.0)
+    }
+}
+
+#[test]
+fn test_auto_mode_deadline() {
+    let timing = AutoModeTiming {
+        base_delay: 1000.0,
+        char_delay: 100.0,
+        voice_delay: 500.0,
+    };
+
+    let now = Instant::now();
+
+    assert_eq!(
+        timing.deadline(now, 5, None),
+        now + Duration::from_millis(1500)
+    );
+    assert_eq!(
+        timing.deadline(now, 5, Some(now + Duration::from_secs(3))),
+        now + Duration::from_millis(3500)
+    );
+    // voice already finished
+    assert_eq!(
+        timing.deadline(now + Duration::from_secs(1), 5, Some(now)),
+        now + Duration::from_millis(1500)
+    );
+}
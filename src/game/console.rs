@@ -0,0 +1,63 @@
+//! Debug console.
+//!
+//! Reads JavaScript from stdin on a separate thread; the lines are evaluated
+//! by the game against the live state, with the same `engine` API as scenes.
+
+use std::io::{BufRead, Write};
+use std::sync::mpsc::{self, Receiver};
+
+pub struct DebugConsole {
+    rx: Receiver<String>,
+}
+
+impl DebugConsole {
+    pub fn spawn() -> Self {
+        let (tx, rx) = mpsc::channel();
+
+        std::thread::spawn(move || {
+            let stdin = std::io::stdin();
+
+            prompt();
+
+            for line in stdin.lock().lines() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(err) => {
+                        log::error!("debug console: {}", err);
+                        break;
+                    }
+                };
+
+                if line.trim().is_empty() {
+                    prompt();
+                    continue;
+                }
+
+                if tx.send(line).is_err() {
+                    // the game has quit
+                    break;
+                }
+            }
+        });
+
+        log::info!("debug console started; type JavaScript to evaluate");
+
+        Self { rx }
+    }
+
+    /// Takes a line typed in, if any.
+    pub fn poll(&self) -> Option<String> {
+        self.rx.try_recv().ok()
+    }
+
+    /// Prints the result of a line, then the prompt for the next one.
+    pub fn print(&self, output: &str) {
+        println!("{}", output);
+        prompt();
+    }
+}
+
+fn prompt() {
+    print!("> ");
+    let _ = std::io::stdout().flush();
+}
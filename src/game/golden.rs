@@ -0,0 +1,465 @@
+//! Golden-image tests.
+//!
+//! Plays a program headless on synthetic S25 fixtures and compares frames,
+//! captured at dialogue lines or timestamps, to the PNGs in `tests/golden`.
+//! A mismatch leaves the actual frame and a diff image in `target/golden`.
+//!
+//! Set `NKTS_UPDATE_GOLDEN=1` to (re)write the golden images instead.
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use thiserror::Error;
+
+use super::auto::AutoModeTiming;
+use super::headless::HeadlessPlayer;
+use super::screen::cpu::CpuCompositor;
+use super::screen::Frame;
+
+use crate::constants::{GAME_WINDOW_HEIGHT, GAME_WINDOW_WIDTH};
+use crate::format::s25::{self, Entry};
+use crate::renderer::cpu::headless::HeadlessSurface;
+use crate::renderer::cpu::image::Image;
+use crate::script::mil::command::{
+    BlendMode, Command, LayerCommand, RendererCommand, RuntimeCommand,
+};
+
+const UPDATE_GOLDEN_ENV: &str = "NKTS_UPDATE_GOLDEN";
+// largest difference allowed per channel; frames match exactly here, but
+// float math may round differently on other platforms
+const TOLERANCE: u8 = 1;
+
+#[derive(Debug, Error)]
+pub enum GoldenError {
+    #[error("{0} is never reached")]
+    NotReached(String),
+    #[error("failed to access {0:?}: {1}")]
+    Io(PathBuf, std::io::Error),
+    #[error("{path:?} is {expected:?}, but the frame is {actual:?}")]
+    SizeMismatch {
+        path: PathBuf,
+        expected: (usize, usize),
+        actual: (usize, usize),
+    },
+    #[error("{pixels} pixels differ from {path:?}; see {diff:?}")]
+    Mismatch {
+        path: PathBuf,
+        pixels: usize,
+        diff: PathBuf,
+    },
+}
+
+/// When a frame is captured.
+#[derive(Clone, Copy, Debug)]
+pub enum Capture {
+    /// The first frame the dialogue line is shown in, counted from zero.
+    Line(usize),
+    /// The first frame at or after the time.
+    At(Duration),
+}
+
+impl Capture {
+    fn name(&self) -> String {
+        match self {
+            Capture::Line(line) => format!("line{}", line),
+            Capture::At(time) => format!("{}ms", time.as_millis()),
+        }
+    }
+
+    fn is_reached(&self, line: Option<usize>, time: Duration) -> bool {
+        match *self {
+            Capture::Line(n) => line == Some(n),
+            Capture::At(t) => time >= t,
+        }
+    }
+}
+
+pub struct GoldenTest {
+    name: String,
+    player: HeadlessPlayer,
+    captures: Vec<Capture>,
+    frame_duration: Duration,
+    timeout: Duration,
+}
+
+impl GoldenTest {
+    pub fn new(name: &str, player: HeadlessPlayer) -> Self {
+        Self {
+            name: name.into(),
+            player,
+            captures: vec![],
+            frame_duration: Duration::from_secs(1) / 30,
+            timeout: Duration::from_secs(60),
+        }
+    }
+
+    pub fn capture(mut self, capture: Capture) -> Self {
+        self.captures.push(capture);
+        self
+    }
+
+    pub fn frame_rate(mut self, fps: u32) -> Self {
+        self.frame_duration = Duration::from_secs(1) / fps;
+        self
+    }
+
+    pub fn run(mut self) -> Result<(), GoldenError> {
+        let mut pending = std::mem::take(&mut self.captures);
+
+        while !pending.is_empty() {
+            let time = self.player.elapsed();
+
+            if self.player.is_finished() || self.timeout < time {
+                let name = format!("{}-{}", self.name, pending[0].name());
+                return Err(GoldenError::NotReached(name));
+            }
+
+            self.player.step(self.frame_duration);
+
+            let line = self.player.line();
+            let (reached, rest) = pending
+                .into_iter()
+                .partition::<Vec<_>, _>(|c| c.is_reached(line, time));
+            pending = rest;
+
+            for capture in reached {
+                self.compare(&capture, self.player.image())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn path(&self, capture: &Capture, suffix: &str) -> PathBuf {
+        let filename = format!("{}-{}{}.png", self.name, capture.name(), suffix);
+
+        if suffix.is_empty() {
+            golden_dir().join(filename)
+        } else {
+            output_dir().join(filename)
+        }
+    }
+
+    fn compare(&self, capture: &Capture, actual: &Image) -> Result<(), GoldenError> {
+        let path = self.path(capture, "");
+
+        if std::env::var_os(UPDATE_GOLDEN_ENV).is_some() {
+            return save(actual, &path);
+        }
+
+        let expected = match Image::load_png(&path) {
+            Ok(expected) => expected,
+            Err(err) => {
+                save(actual, &self.path(capture, ".actual"))?;
+                return Err(GoldenError::Io(path, err));
+            }
+        };
+
+        if (expected.width, expected.height) != (actual.width, actual.height) {
+            return Err(GoldenError::SizeMismatch {
+                path,
+                expected: (expected.width, expected.height),
+                actual: (actual.width, actual.height),
+            });
+        }
+
+        let (pixels, diff) = diff(&expected, actual, TOLERANCE);
+
+        if pixels == 0 {
+            return Ok(());
+        }
+
+        save(actual, &self.path(capture, ".actual"))?;
+
+        let diff_path = self.path(capture, ".diff");
+        save(&diff, &diff_path)?;
+
+        Err(GoldenError::Mismatch {
+            path,
+            pixels,
+            diff: diff_path,
+        })
+    }
+}
+
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden")
+}
+
+fn output_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("target/golden")
+}
+
+fn save(image: &Image, path: &Path) -> Result<(), GoldenError> {
+    let io_err = |err| GoldenError::Io(path.into(), err);
+
+    std::fs::create_dir_all(path.parent().unwrap()).map_err(io_err)?;
+    image.save_png(path).map_err(io_err)
+}
+
+/// Counts the pixels with a channel off by more than `tolerance`.
+///
+/// The diff image shows them in red, over the expected frame dimmed.
+fn diff(expected: &Image, actual: &Image, tolerance: u8) -> (usize, Image) {
+    let mut image = Image::new(expected.width, expected.height);
+    let mut pixels = 0;
+
+    for ((e, a), d) in expected
+        .rgba_buffer
+        .chunks(4)
+        .zip(actual.rgba_buffer.chunks(4))
+        .zip(image.rgba_buffer.chunks_mut(4))
+    {
+        let off = e.iter().zip(a).any(|(&e, &a)| {
+            let delta = if e < a { a - e } else { e - a };
+            delta > tolerance
+        });
+
+        if off {
+            pixels += 1;
+            d.copy_from_slice(&[0xff, 0x00, 0x00, 0xff]);
+        } else {
+            let luma = (e[0] as u32 * 3 + e[1] as u32 * 6 + e[2] as u32) / 10;
+            let v = (luma / 4) as u8;
+            d.copy_from_slice(&[v, v, v, 0xff]);
+        }
+    }
+
+    (pixels, image)
+}
+
+// fixtures
+
+/// A directory of synthetic archives standing in for the game assets.
+struct Fixtures {
+    root: PathBuf,
+}
+
+impl Fixtures {
+    fn new(name: &str) -> Self {
+        let root =
+            std::env::temp_dir().join(format!("nkts-golden-{}-{}", name, std::process::id()));
+
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+
+        Self { root }
+    }
+
+    fn add(&self, filename: &str, entries: &[(usize, (i32, i32), &Image)]) {
+        let entries: Vec<_> = entries
+            .iter()
+            .map(|&(entry_no, offset, image)| Entry {
+                entry_no,
+                offset,
+                width: image.width,
+                height: image.height,
+                rgba_buffer: &image.rgba_buffer,
+            })
+            .collect();
+
+        std::fs::write(self.root.join(filename), s25::encode(&entries)).unwrap();
+    }
+
+    fn player(&self, mut player: HeadlessPlayer) -> HeadlessPlayer {
+        player.set_root(&self.root);
+        // a second per line, whatever its length
+        player.set_auto_timing(AutoModeTiming {
+            base_delay: 1000.0,
+            char_delay: 0.0,
+            voice_delay: 0.0,
+        });
+
+        player
+    }
+}
+
+impl Drop for Fixtures {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.root);
+    }
+}
+
+fn gradient(width: usize, height: usize) -> Image {
+    let mut image = Image::new(width, height);
+
+    for (i, p) in image.rgba_buffer.chunks_mut(4).enumerate() {
+        let (x, y) = (i % width, i / width);
+        p.copy_from_slice(&[
+            (x * 255 / width) as u8,
+            (y * 255 / height) as u8,
+            0x40,
+            0xff,
+        ]);
+    }
+
+    image
+}
+
+// a disc with a translucent rim
+fn sprite(size: usize) -> Image {
+    let mut image = Image::new(size, size);
+    let r = size as f64 / 2.0;
+
+    for (i, p) in image.rgba_buffer.chunks_mut(4).enumerate() {
+        let (x, y) = ((i % size) as f64 + 0.5 - r, (i / size) as f64 + 0.5 - r);
+        let d = (x * x + y * y).sqrt();
+
+        let alpha = if d < r * 0.75 {
+            0xff
+        } else if d < r {
+            0x80
+        } else {
+            0x00
+        };
+
+        p.copy_from_slice(&[0xe0, 0x60, 0x90, alpha]);
+    }
+
+    image
+}
+
+#[test]
+fn test_golden_layer_delay() {
+    let fixtures = Fixtures::new("layer_delay");
+    fixtures.add("BG.S25", &[(0, (400, 225), &gradient(800, 450))]);
+    fixtures.add("CHR.S25", &[(0, (0, 0), &sprite(200))]);
+
+    let source = "\
+$L_CHR,0,b\\bg.s25,0,0,0
+$L_CHR,1,b\\chr.s25,500,300,0
+
+first line
+
+$L_DELAY,1,T,500
+$L_CHR,1,b\\chr.s25,900,300,0
+$WAIT,1000
+
+second line
+
+";
+
+    let player = HeadlessPlayer::from_rio("layer_delay.txt", source).unwrap();
+
+    // the sprite moves 500ms after the first line is over
+    GoldenTest::new("layer_delay", fixtures.player(player))
+        .frame_rate(20)
+        .capture(Capture::Line(0))
+        .capture(Capture::At(Duration::from_millis(1250)))
+        .capture(Capture::At(Duration::from_millis(1750)))
+        .capture(Capture::Line(1))
+        .run()
+        .unwrap();
+}
+
+#[test]
+fn test_golden_opacity() {
+    let fixtures = Fixtures::new("opacity");
+    fixtures.add("BG.S25", &[(0, (400, 225), &gradient(800, 450))]);
+    fixtures.add(
+        "CHR.S25",
+        &[(0, (0, 0), &sprite(200)), (100, (150, 50), &sprite(100))],
+    );
+
+    let layer = |layer_no, command| Command::LayerCommand { layer_no, command };
+
+    let program = vec![
+        layer(0, LayerCommand::Load("BG.S25".into(), vec![0])),
+        layer(1, LayerCommand::Load("CHR.S25".into(), vec![0, 0])),
+        layer(1, LayerCommand::SetPosition(700.0, 300.0)),
+        layer(1, LayerCommand::SetOpacity(0.5)),
+        Command::RendererCommand(RendererCommand::Dialogue(None, "translucent".into())),
+        Command::RuntimeCommand(RuntimeCommand::WaitUntilUserEvent),
+    ];
+
+    let player = HeadlessPlayer::new("opacity.txt", program);
+
+    GoldenTest::new("opacity", fixtures.player(player))
+        .capture(Capture::Line(0))
+        .run()
+        .unwrap();
+}
+
+#[test]
+fn test_partial_redraw() {
+    let fixtures = Fixtures::new("partial_redraw");
+    fixtures.add("BG.S25", &[(0, (400, 225), &gradient(800, 450))]);
+    fixtures.add("CHR.S25", &[(0, (0, 0), &sprite(200))]);
+
+    let (width, height) = (GAME_WINDOW_WIDTH as usize, GAME_WINDOW_HEIGHT as usize);
+
+    // redrawn where damaged, into frames kept by the surface, and drawn
+    // entirely into a fresh image each frame
+    let mut partial = CpuCompositor::new(2);
+    let mut full = CpuCompositor::new(2);
+    partial.set_root(&fixtures.root);
+    full.set_root(&fixtures.root);
+
+    let mut surface = HeadlessSurface::new(width, height);
+
+    let hidden = Frame {
+        hide_layers: true,
+        ..Frame::default()
+    };
+    let steps = vec![
+        (
+            vec![
+                (0, LayerCommand::Load("BG.S25".into(), vec![0])),
+                (1, LayerCommand::Load("CHR.S25".into(), vec![0])),
+                (1, LayerCommand::SetPosition(500.0, 300.0)),
+            ],
+            Frame::default(),
+        ),
+        (vec![], Frame::default()),
+        (
+            vec![(1, LayerCommand::SetPosition(700.0, 250.0))],
+            Frame::default(),
+        ),
+        (vec![(1, LayerCommand::SetOpacity(0.5))], Frame::default()),
+        (vec![], hidden.clone()),
+        (vec![(1, LayerCommand::SetPosition(300.0, 400.0))], hidden),
+        (vec![], Frame::default()),
+        (vec![(1, LayerCommand::SetBlurRate(4, 4))], Frame::default()),
+        (
+            vec![(1, LayerCommand::SetBlendMode(BlendMode::Multiply))],
+            Frame::default(),
+        ),
+        (vec![(1, LayerCommand::Unload)], Frame::default()),
+        (vec![], Frame::default()),
+    ];
+
+    let start = Instant::now();
+
+    for (i, (commands, frame)) in steps.into_iter().enumerate() {
+        for (layer_no, command) in commands {
+            partial.send(layer_no, command.clone());
+            full.send(layer_no, command);
+        }
+
+        let now = start + Duration::from_millis(100 * i as u64);
+
+        let actual = surface.step(|target| partial.draw_at(target, &frame, now));
+
+        let mut expected = Image::new(width, height);
+        full.draw_at(&mut expected, &frame, now);
+
+        assert_eq!(diff(&expected, actual, 0).0, 0, "frame {}", i);
+    }
+}
+
+#[test]
+fn test_golden_diff() {
+    let expected = gradient(4, 4);
+    let mut actual = expected.clone();
+
+    actual.rgba_buffer[0] = actual.rgba_buffer[0].wrapping_add(2);
+    assert_eq!(diff(&expected, &actual, 2).0, 0);
+
+    actual.rgba_buffer[4] = actual.rgba_buffer[4].wrapping_add(3);
+    actual.rgba_buffer[63] = 0;
+
+    let (pixels, image) = diff(&expected, &actual, 2);
+    assert_eq!(pixels, 2);
+    assert_eq!(&image.rgba_buffer[4..8], &[0xff, 0, 0, 0xff]);
+    assert_eq!(&image.rgba_buffer[60..64], &[0xff, 0, 0, 0xff]);
+}
@@ -0,0 +1,357 @@
+//! Headless playback.
+//!
+//! Plays a program on a virtual clock and composites every frame on the CPU
+//! into an in-memory image. Lines advance by the auto mode timing and choices
+//! take their first option, so that a run is the same every time.
+
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use super::auto::AutoModeTiming;
+use super::screen::cpu::CpuCompositor;
+use super::screen::{Frame, TextBox};
+
+use crate::config;
+use crate::constants::{self, GAME_WINDOW_HEIGHT, GAME_WINDOW_WIDTH, LAYER_COUNT};
+use crate::renderer::cpu::headless::HeadlessSurface;
+use crate::renderer::cpu::image::Image;
+use crate::script::mil::command::{self, Command, RendererCommand, RuntimeCommand};
+use crate::script::runtime::backlog::LogEntry;
+use crate::script::runtime::interpreter::{Host, Interpreter};
+use crate::script::runtime::variable::Variables;
+
+/// A clock that only moves when told to.
+pub struct Clock {
+    origin: Instant,
+    elapsed: Duration,
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock {
+    pub fn new() -> Self {
+        Self {
+            origin: Instant::now(),
+            elapsed: Duration::from_secs(0),
+        }
+    }
+
+    pub fn now(&self) -> Instant {
+        self.origin + self.elapsed
+    }
+
+    /// Time since the clock started.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    pub fn advance(&mut self, delta: Duration) {
+        self.elapsed += delta;
+    }
+}
+
+// without a loader, calls stay in the program
+fn no_loader(scenario: &str, _: &Variables) -> std::io::Result<Vec<Command>> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        format!("no loader for the scenario: {}", scenario),
+    ))
+}
+
+pub struct HeadlessPlayer {
+    interp: Interpreter,
+    // loads the scenarios called
+    loader: Box<dyn Host>,
+    compositor: CpuCompositor,
+    surface: HeadlessSurface,
+    clock: Clock,
+    auto_timing: AutoModeTiming,
+    wait_until: Option<Instant>,
+    advance_at: Option<Instant>,
+    dialogue: Option<LogEntry>,
+    // index of the line shown, counted from zero
+    line: Option<usize>,
+    show_text: bool,
+}
+
+impl HeadlessPlayer {
+    /// Plays `program`; `scenario` is its name, which calls resolve against.
+    pub fn new(scenario: &str, program: Vec<Command>) -> Self {
+        Self {
+            interp: Interpreter::with_program(scenario, program),
+            loader: Box::new(no_loader),
+            compositor: CpuCompositor::new(LAYER_COUNT),
+            surface: HeadlessSurface::new(GAME_WINDOW_WIDTH as usize, GAME_WINDOW_HEIGHT as usize),
+            clock: Clock::new(),
+            auto_timing: AutoModeTiming::default(),
+            wait_until: None,
+            advance_at: None,
+            dialogue: None,
+            line: None,
+            show_text: false,
+        }
+    }
+
+    /// Plays `scenario` and those it calls or continues to in the configured
+    /// chain, as loaded by `loader`.
+    pub fn with_loader<H>(scenario: &str, loader: H) -> std::io::Result<Self>
+    where
+        H: Host + 'static,
+    {
+        let mut player = Self::new(scenario, vec![]);
+        player.loader = Box::new(loader);
+        player.interp.set_chain(config::get_scenario_chain());
+        player.interp.load(&mut *player.loader, scenario)?;
+
+        Ok(player)
+    }
+
+    /// Plays a RioScript source, without any pass applied.
+    pub fn from_rio(scenario: &str, source: &str) -> std::io::Result<Self> {
+        use crate::script::rio::parser::Parser;
+        use crate::script::rio::transpiler::Transpiler;
+
+        let script = Parser::from_raw_bytes(source.as_bytes()).parse()?;
+        Ok(Self::new(scenario, Transpiler::new(script).transpile()))
+    }
+
+    /// Looks up the archives in `root` instead of the configured root.
+    pub fn set_root(&mut self, root: &Path) {
+        self.compositor.set_root(root);
+    }
+
+    pub fn set_auto_timing(&mut self, timing: AutoModeTiming) {
+        self.auto_timing = timing;
+    }
+
+    /// Draws the dialogue; needs the font of the game.
+    pub fn set_show_text(&mut self, show_text: bool) {
+        self.show_text = show_text;
+    }
+
+    /// Index of the line shown, counted from zero.
+    pub fn line(&self) -> Option<usize> {
+        self.line
+    }
+
+    pub fn dialogue(&self) -> Option<&LogEntry> {
+        self.dialogue.as_ref()
+    }
+
+    /// Time played so far.
+    pub fn elapsed(&self) -> Duration {
+        self.clock.elapsed()
+    }
+
+    /// The program has run out.
+    pub fn is_finished(&self) -> bool {
+        self.interp.is_finished()
+    }
+
+    /// The last frame drawn.
+    pub fn image(&self) -> &Image {
+        self.surface.image()
+    }
+
+    /// Runs the program up to the current time and draws a frame, then moves
+    /// the clock forward by `frame_duration`.
+    pub fn step(&mut self, frame_duration: Duration) -> &Image {
+        self.exec();
+
+        let now = self.clock.now();
+        let frame = self.frame();
+        let compositor = &mut self.compositor;
+
+        self.surface
+            .step(|target| compositor.draw_at(target, &frame, now));
+        self.clock.advance(frame_duration);
+
+        self.surface.image()
+    }
+
+    fn exec(&mut self) {
+        let now = self.clock.now();
+
+        if let Some(wait_until) = self.wait_until {
+            if now < wait_until {
+                return;
+            }
+
+            self.wait_until = None;
+        }
+
+        if let Some(advance_at) = self.advance_at {
+            if now < advance_at {
+                return;
+            }
+
+            self.advance_at = None;
+        }
+
+        while let Some(command) = self.interp.next(&mut *self.loader) {
+            match command {
+                Command::RuntimeCommand(RuntimeCommand::WaitUntilUserEvent) => {
+                    let chars = self
+                        .dialogue
+                        .as_ref()
+                        .map(|d| d.text.chars().count())
+                        .unwrap_or_default();
+
+                    self.advance_at = Some(self.auto_timing.deadline(now, chars, None));
+                    return;
+                }
+                Command::RuntimeCommand(RuntimeCommand::Wait(duration)) => {
+                    self.wait_until = Some(now + Duration::from_secs_f64(duration / 1000.0));
+                    return;
+                }
+                Command::RendererCommand(RendererCommand::Dialogue(name, text)) => {
+                    self.line = Some(self.line.map_or(0, |line| line + 1));
+                    self.dialogue = Some(LogEntry {
+                        name,
+                        text,
+                        voice: None,
+                    });
+                }
+                Command::LayerCommand { layer_no, command } => {
+                    match command::layer_index(layer_no) {
+                        Some(index) => self.compositor.send(index, command),
+                        None => log::error!("no such layer: {}; dropped {:?}", layer_no, command),
+                    }
+                }
+                Command::RuntimeCommand(RuntimeCommand::Choice(options)) => {
+                    if let Some(option) = options.first() {
+                        log::info!("choosing `{}`", option.text);
+                        self.interp.branch(&option.label);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn frame(&self) -> Frame {
+        let mut frame = Frame::default();
+
+        if let (true, Some(dialogue)) = (self.show_text, &self.dialogue) {
+            frame.texts.push(TextBox::new(
+                constants::DIALOGUE_OFFSET,
+                constants::DIALOGUE_SIZE,
+                format!(
+                    "{}\n{}",
+                    dialogue.name.as_deref().unwrap_or_default(),
+                    dialogue.text
+                ),
+            ));
+        }
+
+        frame
+    }
+}
+
+#[test]
+fn test_headless_player_timing() {
+    use crate::script::mil::command::RuntimeCommand::{Wait, WaitUntilUserEvent};
+
+    let dialogue =
+        |text: &str| Command::RendererCommand(RendererCommand::Dialogue(None, text.into()));
+
+    let mut player = HeadlessPlayer::new(
+        "test.txt",
+        vec![
+            dialogue("a"),
+            Command::RuntimeCommand(WaitUntilUserEvent),
+            Command::RuntimeCommand(Wait(500.0)),
+            dialogue("bb"),
+            Command::RuntimeCommand(WaitUntilUserEvent),
+        ],
+    );
+
+    player.set_auto_timing(AutoModeTiming {
+        base_delay: 1000.0,
+        char_delay: 100.0,
+        voice_delay: 0.0,
+    });
+
+    let frame = Duration::from_millis(100);
+    let mut shown = vec![];
+
+    while !player.is_finished() {
+        let elapsed = player.elapsed();
+        player.step(frame);
+
+        if shown.last().and_then(|&(line, _)| line) != player.line() {
+            shown.push((player.line(), elapsed.as_millis()));
+        }
+    }
+
+    // the first line for 1100ms, then a wait of 500ms
+    assert_eq!(shown, vec![(Some(0), 0), (Some(1), 1600)]);
+    assert_eq!(player.elapsed(), Duration::from_millis(2900));
+}
+
+#[test]
+fn test_headless_player_calls() {
+    let dialogue =
+        |text: &str| Command::RendererCommand(RendererCommand::Dialogue(None, text.into()));
+    let wait = || Command::RuntimeCommand(RuntimeCommand::WaitUntilUserEvent);
+
+    let mut player =
+        HeadlessPlayer::with_loader("a/MAIN.TXT", move |scenario: &str, _: &Variables| {
+            Ok(match scenario {
+                "a/MAIN.TXT" => vec![
+                    Command::RuntimeCommand(RuntimeCommand::Call {
+                        scenario: "SUB".into(),
+                        label: None,
+                    }),
+                    dialogue("main"),
+                    wait(),
+                ],
+                "a/SUB.TXT" => vec![dialogue("sub"), wait()],
+                _ => return Err(std::io::ErrorKind::NotFound.into()),
+            })
+        })
+        .unwrap();
+
+    let mut lines = vec![];
+
+    while !player.is_finished() {
+        player.step(Duration::from_secs(1));
+
+        if let Some(dialogue) = player.dialogue() {
+            if lines.last() != Some(&dialogue.text) {
+                lines.push(dialogue.text.clone());
+            }
+        }
+    }
+
+    assert_eq!(lines, ["sub", "main"]);
+}
+
+#[test]
+fn test_headless_player_layer_out_of_range() {
+    use crate::script::mil::command::LayerCommand;
+
+    let layer = |layer_no: i32| Command::LayerCommand {
+        layer_no,
+        command: LayerCommand::SetOpacity(0.5),
+    };
+
+    let mut player = HeadlessPlayer::new(
+        "test.txt",
+        vec![
+            layer(LAYER_COUNT as i32),
+            layer(-1),
+            layer(0),
+            Command::RendererCommand(RendererCommand::Dialogue(None, "a".into())),
+            Command::RuntimeCommand(RuntimeCommand::WaitUntilUserEvent),
+        ],
+    );
+
+    // the commands to no layer are dropped, and the rest runs
+    player.step(Duration::from_millis(100));
+    assert_eq!(player.line(), Some(0));
+}
@@ -1,29 +1,89 @@
+pub mod auto;
+pub mod console;
+#[cfg(test)]
+mod golden;
+pub mod headless;
 pub mod scene;
+pub mod screen;
+pub mod skip;
+
+use crate::script::mil::command::{
+    Command as MilCommand, LayerCommand, MmCommand, RendererCommand, RuntimeCommand,
+};
+
+use std::time::{Duration, Instant};
+
+use crate::audio::Mixer;
+use crate::constants::LAYER_COUNT;
+use crate::script::mil::command::{self, SavedataCommand};
+use crate::script::runtime::backlog::{Backlog, LogEntry};
+use crate::script::runtime::interpreter::{Host, Interpreter};
+use crate::script::runtime::js::{JsError, JsRuntime};
+use crate::script::runtime::persistent::PersistentData;
+use crate::script::runtime::rewind::RewindHistory;
+use crate::script::runtime::savedata::{LayerState, SaveSlot, Savedata};
+use crate::script::runtime::variable::Variables;
+use crate::utils::viewport::Letterbox;
+
+use auto::AutoModeTiming;
+use console::DebugConsole;
+use scene::backlog::BacklogScene;
+use scene::choice::ChoiceScene;
+use screen::{Frame, Screen, TextBox};
+use skip::{SkipKeys, SkipMode};
+
+pub struct Game<S: Screen> {
+    // opened by `execute`
+    screen: Option<S>,
+    interp: Interpreter,
+    // state mirrored for savedata
+    layer_states: Vec<LayerState>,
+    dialogue: Option<LogEntry>,
+    backlog: Backlog,
+    backlog_scene: Option<BacklogScene>,
+    choice_scene: Option<ChoiceScene>,
+    rewind: RewindHistory,
+    // cursor position in the window (physical pixels)
+    cursor: (f64, f64),
+    persistent: PersistentData,
+    mixer: Mixer,
+    js: JsRuntime,
+    console: Option<DebugConsole>,
+    waiting: bool,
+    wait_until: Option<Instant>,
+    // skip mode
+    skip_keys: SkipKeys,
+    line_read: bool,
+    // auto mode
+    auto_mode: bool,
+    auto_timing: AutoModeTiming,
+    auto_deadline: Option<Instant>,
+    line_chars: usize,
+}
 
-use crate::renderer::vulkano::layer::LayerRenderer;
-use crate::renderer::Renderer;
-use crate::script::mil::command::{Command as MilCommand, RendererCommand, RuntimeCommand};
-
-use std::sync::Arc;
-use vulkano::device::Queue;
-use vulkano::framebuffer::RenderPassAbstract;
+// What the interpreter sees of the game.
+struct ScriptHost<'a> {
+    js: &'a mut JsRuntime,
+    persistent: &'a mut PersistentData,
+}
 
-use crate::renderer::vulkano::text::Text;
+impl Host for ScriptHost<'_> {
+    fn load(&mut self, scenario: &str, variables: &Variables) -> std::io::Result<Vec<MilCommand>> {
+        self.js.load(scenario, variables)
+    }
 
-pub struct Game {
-    layers: Vec<LayerRenderer>,
-    // face_layer: LayerRenderer,
-    text_layer: Text,
-    text_update: bool,
-    commands: Vec<MilCommand>,
-    queue: Option<Arc<Queue>>,
-    waiting: bool,
+    fn persistent(&mut self) -> Option<&mut PersistentData> {
+        Some(self.persistent)
+    }
 }
 
-use winit::event::{ElementState, Event, WindowEvent};
+use winit::dpi::PhysicalSize;
+use winit::event::{
+    ElementState, Event, KeyboardInput, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent,
+};
 use winit::event_loop::{ControlFlow, EventLoop};
 
-impl Game {
+impl<S: Screen> Game<S> {
     fn platform_specific_setup() {
         #[cfg(target_os = "macos")]
         unsafe {
@@ -33,79 +93,433 @@ impl Game {
     }
 }
 
-impl Game {
+impl<S: Screen> Game<S> {
     pub fn new() -> Self {
+        use crate::config;
+
         Self::platform_specific_setup();
 
+        let mut interp = Interpreter::new();
+        interp.set_chain(config::get_scenario_chain());
+
         Game {
-            layers: vec![],
-            commands: vec![],
-            text_layer: Text::new((380, 640), (900, 300)),
-            text_update: false,
+            screen: None,
+            interp,
+            layer_states: vec![LayerState::default(); LAYER_COUNT],
+            dialogue: None,
+            backlog: Backlog::new(crate::constants::BACKLOG_CAPACITY),
+            backlog_scene: None,
+            choice_scene: None,
+            rewind: RewindHistory::new(
+                crate::constants::REWIND_SNAPSHOT_INTERVAL,
+                crate::constants::REWIND_SNAPSHOT_CAPACITY,
+            ),
+            cursor: (0.0, 0.0),
+            persistent: PersistentData::open(config::persistent_data_path()),
+            mixer: Mixer::new(),
+            js: JsRuntime::new(),
+            console: if config::is_debug_console_enabled() {
+                Some(DebugConsole::spawn())
+            } else {
+                None
+            },
             waiting: false,
-            queue: None,
+            wait_until: None,
+            skip_keys: SkipKeys::default(),
+            line_read: false,
+            auto_mode: false,
+            auto_timing: AutoModeTiming::from_config(),
+            auto_deadline: None,
+            line_chars: 0,
         }
     }
 
-    pub fn load_script(&mut self) {
-        use encoding_rs::SHIFT_JIS;
+    /// Starts the story from `entry`, or the one configured.
+    pub fn load_script(&mut self, entry: Option<&str>) {
+        use crate::config;
 
-        use crate::script::rio::parser::Parser;
-        use crate::script::rio::transpiler::Transpiler;
+        self.load_scenario(entry.unwrap_or_else(|| config::get_entry_scenario()));
+    }
 
-        let script = std::fs::read("./testcase/02_NK_23H.TXT").unwrap();
-        let (script, _, _) = SHIFT_JIS.decode(&script);
+    fn load_scenario(&mut self, scenario: &str) {
+        let mut host = ScriptHost {
+            js: &mut self.js,
+            persistent: &mut self.persistent,
+        };
 
-        let mut parser = Parser::from_raw_bytes(script.as_bytes());
-        let script = parser.parse().unwrap();
+        // the story ends at once
+        if let Err(err) = self.interp.load(&mut host, scenario) {
+            log::error!("failed to load {}: {}", scenario, err);
+        }
+    }
 
-        let tr = Transpiler::new(script);
-        let script = tr.transpile();
+    // Runs the interpreter up to the next command for the game.
+    fn next_command(&mut self) -> Option<MilCommand> {
+        let mut host = ScriptHost {
+            js: &mut self.js,
+            persistent: &mut self.persistent,
+        };
 
-        use crate::script::mil::pass::prefetch::PrefetchPass;
-        use crate::script::mil::pass::Pass;
+        self.interp.next(&mut host)
+    }
+
+    /// Returns the active skip mode, if any.
+    pub fn skip_mode(&self) -> Option<SkipMode> {
+        self.skip_keys.mode(self.persistent.preferences.skip_unread)
+    }
+
+    fn can_skip_line(&self) -> bool {
+        self.skip_mode()
+            .map(|mode| mode.can_skip(self.line_read))
+            .unwrap_or_default()
+    }
 
-        let prefetch = PrefetchPass::new();
-        let mut script = prefetch.process(script);
+    pub fn toggle_skip(&mut self) {
+        self.skip_keys.toggle();
+        log::debug!("skip mode: {:?}", self.skip_mode());
+    }
 
-        script.reverse();
+    pub fn toggle_auto(&mut self) {
+        self.auto_mode = !self.auto_mode;
+        log::debug!("auto mode: {}", self.auto_mode);
 
-        self.commands = script;
+        if self.auto_mode && self.waiting {
+            self.schedule_auto_advance();
+        } else {
+            self.auto_deadline = None;
+        }
+    }
+
+    fn schedule_auto_advance(&mut self) {
+        let now = Instant::now();
+        let voice_end = self.mixer.voice().and_then(|v| v.end());
+
+        self.auto_deadline = Some(self.auto_timing.deadline(now, self.line_chars, voice_end));
+    }
+
+    /// Leaves the current line.
+    pub fn advance(&mut self) {
+        self.waiting = false;
+        self.auto_deadline = None;
+        self.mixer.stop_voice();
     }
 
     pub fn exec_script(&mut self) {
-        if self.waiting {
+        if self.backlog_scene.is_some() || self.choice_scene.is_some() {
+            // the story is paused while the backlog or a choice is open
             return;
         }
 
-        while let Some(cmd) = self.commands.pop() {
+        let skipping = self.skip_mode().is_some();
+
+        if skipping {
+            // cut all the delays and animations short
+            self.finalize_animations();
+        }
+
+        if let Some(wait_until) = self.wait_until {
+            if !skipping && Instant::now() < wait_until {
+                return;
+            }
+
+            self.wait_until = None;
+        }
+
+        if self.waiting {
+            let auto_advance = self
+                .auto_deadline
+                .map(|deadline| deadline <= Instant::now())
+                .unwrap_or_default();
+
+            if !auto_advance && !self.can_skip_line() {
+                return;
+            }
+
+            self.advance();
+        }
+
+        while let Some(cmd) = self.next_command() {
             match cmd {
                 MilCommand::RuntimeCommand(RuntimeCommand::WaitUntilUserEvent) => {
+                    self.record_line();
+
+                    if self.can_skip_line() {
+                        // advance one line per frame
+                        return;
+                    }
+
+                    if skipping {
+                        // stop at the unread line
+                        log::debug!("skip stopped at an unread line");
+                        self.skip_keys.cancel();
+                    }
+
                     self.waiting = true;
+
+                    if self.auto_mode {
+                        self.schedule_auto_advance();
+                    }
+
+                    return;
+                }
+                MilCommand::RuntimeCommand(RuntimeCommand::Wait(duration)) => {
+                    if skipping {
+                        continue;
+                    }
+
+                    self.wait_until =
+                        Some(Instant::now() + Duration::from_secs_f64(duration / 1000.0));
                     return;
                 }
-                MilCommand::RendererCommand(r) => {
-                    self.visit_renderer_command(r);
+                MilCommand::MmCommand(MmCommand::PlayVoice(filename)) => {
+                    if skipping {
+                        log::debug!("voice suppressed: {}", filename);
+                        self.mixer.stop_voice();
+                    } else {
+                        self.mixer.play_voice(&filename, Instant::now());
+                    }
+                }
+                MilCommand::SavedataCommand(s) => {
+                    if self.visit_savedata_command(s) {
+                        // the program has been replaced
+                        return;
+                    }
+                }
+                MilCommand::RuntimeCommand(RuntimeCommand::Choice(options)) => {
+                    // stay on the choice, so that it is shown again on load
+                    self.interp.stay();
+                    self.skip_keys.cancel();
+                    self.choice_scene = Some(ChoiceScene::new(options));
+                    return;
+                }
+                _ => self.visit_command(cmd),
+            }
+        }
+    }
+
+    /// Executes a command left by the interpreter which neither waits nor
+    /// plays a voice.
+    fn visit_command(&mut self, command: MilCommand) {
+        match command {
+            MilCommand::RendererCommand(r) => {
+                self.visit_renderer_command(r);
+            }
+            MilCommand::LayerCommand { layer_no, command } => {
+                if let LayerCommand::Load(filename, _) = &command {
+                    self.persistent.unlock_cg(filename);
+                }
+
+                self.send_layer_command(layer_no, command);
+            }
+            MilCommand::MmCommand(MmCommand::PlayMusic {
+                filename,
+                is_looped,
+            }) => {
+                self.persistent.unlock_music(&filename);
+                self.mixer.play_music(&filename, is_looped);
+            }
+            MilCommand::MmCommand(MmCommand::FadeMusic(duration)) => {
+                self.mixer.fade_music(duration);
+            }
+            _ => {
+                log::debug!("skipped command: {:?}", command);
+            }
+        }
+    }
+
+    /// Picks an option of the choice shown.
+    pub fn choose(&mut self, index: usize) {
+        let label = match self.choice_scene.as_ref().and_then(|s| s.label(index)) {
+            Some(label) => label.to_owned(),
+            None => return,
+        };
+
+        self.choice_scene = None;
+
+        // the pick is not recorded; rewinding stops at the choice
+        self.rewind.clear();
+
+        self.interp.choose(&label);
+    }
+
+    /// Evaluates the lines typed in the debug console.
+    fn poll_console(&mut self) {
+        while let Some(line) = self.console.as_ref().and_then(|c| c.poll()) {
+            let output = match self.eval_console(&line) {
+                Ok(output) => output,
+                Err(err) => format!("error: {}", err),
+            };
+
+            if let Some(console) = &self.console {
+                console.print(&output);
+            }
+        }
+    }
+
+    fn eval_console(&mut self, line: &str) -> Result<String, JsError> {
+        let debug = self.js.debug_mut();
+        debug.pc = self.interp.position();
+        debug.program_len = self.interp.program_len();
+        debug.layers = self.layer_states.clone();
+        debug.jump = None;
+
+        self.js.set_flags(self.interp.variables().numbers());
+        self.js.take_commands();
+
+        let result = self.js.execute("<console>", line);
+
+        // commands triggered from the console run at once
+        for command in self.js.take_commands() {
+            let mut host = ScriptHost {
+                js: &mut self.js,
+                persistent: &mut self.persistent,
+            };
+
+            let command = match self.interp.exec(&mut host, command) {
+                Some(command) => command,
+                None => continue,
+            };
+
+            match command {
+                MilCommand::RuntimeCommand(RuntimeCommand::WaitUntilUserEvent)
+                | MilCommand::RuntimeCommand(RuntimeCommand::Wait(_)) => {}
+                MilCommand::MmCommand(MmCommand::PlayVoice(filename)) => {
+                    self.mixer.play_voice(&filename, Instant::now());
+                }
+                MilCommand::SavedataCommand(s) => {
+                    self.visit_savedata_command(s);
+                }
+                _ => self.visit_command(command),
+            }
+        }
+
+        if let Some(pc) = self.js.debug_mut().jump.take() {
+            self.jump(pc);
+        }
+
+        result
+    }
+
+    /// Moves the program counter; the layers are kept as they are.
+    fn jump(&mut self, pc: usize) {
+        self.interp.seek(pc);
+        self.waiting = false;
+        self.wait_until = None;
+        self.auto_deadline = None;
+        self.mixer.stop_voice();
+
+        // the history no longer leads to the current line
+        self.rewind.clear();
+
+        log::debug!("jumped to {}", self.interp.position());
+    }
+
+    fn record_line(&mut self) {
+        let snapshot = if self.rewind.wants_snapshot() {
+            Some(Savedata {
+                waiting: true,
+                ..self.snapshot()
+            })
+        } else {
+            None
+        };
+
+        self.rewind.push_line(snapshot);
+    }
+
+    /// Goes back to the previous line.
+    pub fn rewind(&mut self) {
+        if self.backlog_scene.is_some() {
+            return;
+        }
+
+        let rewind = match self.rewind.rewind() {
+            Some(rewind) => rewind,
+            None => {
+                log::debug!("no line to rewind to");
+                return;
+            }
+        };
+
+        self.skip_keys.cancel();
+        self.restore(rewind.snapshot);
+        self.replay(rewind.replay_lines);
+    }
+
+    // Runs the program through `lines` lines without waiting.
+    fn replay(&mut self, mut lines: usize) {
+        let mut voice = None;
+
+        if lines > 0 {
+            // the voice of the snapshot is not the one of the line
+            self.mixer.stop_voice();
+        }
+
+        while lines > 0 {
+            let cmd = match self.next_command() {
+                Some(cmd) => cmd,
+                None => break,
+            };
+
+            match cmd {
+                MilCommand::RuntimeCommand(RuntimeCommand::WaitUntilUserEvent) => {
+                    lines -= 1;
+
+                    if lines > 0 {
+                        voice = None;
+                    }
                 }
-                MilCommand::LayerCommand { layer_no, command } => {
-                    self.layers[layer_no as usize].send(command);
+                MilCommand::RuntimeCommand(RuntimeCommand::Wait(_)) => {}
+                MilCommand::MmCommand(MmCommand::PlayVoice(filename)) => {
+                    voice = Some(filename);
                 }
-                _ => {
-                    log::debug!("skipped command: {:?}", cmd);
+                MilCommand::SavedataCommand(SavedataCommand::AddLogEntry {
+                    name,
+                    text,
+                    voice,
+                    ..
+                }) => {
+                    self.backlog.push(LogEntry { name, text, voice });
                 }
+                MilCommand::SavedataCommand(_) | MilCommand::RuntimeCommand(_) => {}
+                _ => self.visit_command(cmd),
+            }
+        }
+
+        self.finalize_animations();
+
+        if let Some(voice) = voice {
+            self.mixer.play_voice(&voice, Instant::now());
+
+            if let Some(dialogue) = &mut self.dialogue {
+                dialogue.voice = Some(voice);
             }
         }
+
+        self.waiting = true;
+
+        if self.auto_mode {
+            self.schedule_auto_advance();
+        }
     }
 
     fn visit_renderer_command(&mut self, command: RendererCommand) {
         match command {
             RendererCommand::Dialogue(name, dialogue) => {
-                self.text_layer.write(
-                    format!("{}\n{}", name.unwrap_or_default(), dialogue),
-                    self.queue.clone().unwrap(),
-                );
+                let scenario = self.interp.scenario();
+                let ordinal = self.interp.line_ordinal(&dialogue);
+
+                self.line_read = self.persistent.is_read(scenario, &dialogue, ordinal);
+                self.line_chars = dialogue.chars().count();
+                self.persistent.mark_read(scenario, &dialogue, ordinal);
 
-                self.text_update = true;
+                let voice = self.mixer.voice().map(|v| v.filename.clone());
+
+                self.show_dialogue(LogEntry {
+                    name,
+                    text: dialogue,
+                    voice,
+                });
             }
             _ => {
                 log::debug!("skipped renderer command: {:?}", command);
@@ -113,124 +527,395 @@ impl Game {
         }
     }
 
-    pub fn execute(mut self) {
-        use crate::config;
-        use crate::renderer::vulkano::surface::VulkanoSurface;
-        use crate::renderer::{EventDelegate, RenderingSurface};
+    fn send_layer_command(&mut self, layer_no: i32, command: LayerCommand) {
+        let index = match command::layer_index(layer_no) {
+            Some(index) => index,
+            None => {
+                log::error!("no such layer: {}; dropped {:?}", layer_no, command);
+                return;
+            }
+        };
 
-        self.load_script();
+        self.layer_states[index].apply(&command);
 
-        let event_loop = EventLoop::new();
-        let mut buf = VulkanoSurface::new(&event_loop);
+        if let Some(screen) = &mut self.screen {
+            screen.send(index, command);
+        }
+    }
+
+    fn finalize_animations(&mut self) {
+        if let Some(screen) = &mut self.screen {
+            for layer_no in 0..LAYER_COUNT {
+                screen.send(layer_no, LayerCommand::FinalizeAnimation);
+            }
+        }
+    }
+
+    fn show_dialogue(&mut self, dialogue: LogEntry) {
+        self.dialogue = Some(dialogue);
+    }
+
+    /// Describes what is drawn over the layers.
+    fn frame(&mut self) -> Frame {
+        use crate::constants::{DIALOGUE_OFFSET, DIALOGUE_SIZE};
+
+        if let Some(scene) = &mut self.backlog_scene {
+            scene.update(&self.backlog);
+
+            return Frame {
+                hide_layers: true,
+                texts: scene.texts().cloned().collect(),
+            };
+        }
+
+        let mut texts = vec![];
+
+        if let Some(dialogue) = &self.dialogue {
+            texts.push(TextBox::new(
+                DIALOGUE_OFFSET,
+                DIALOGUE_SIZE,
+                format!(
+                    "{}\n{}",
+                    dialogue.name.as_deref().unwrap_or_default(),
+                    dialogue.text
+                ),
+            ));
+        }
+
+        if let Some(scene) = &self.choice_scene {
+            texts.extend(scene.texts().cloned());
+        }
+
+        Frame {
+            hide_layers: false,
+            texts,
+        }
+    }
+
+    /// Returns `true` if a savedata has been loaded.
+    fn visit_savedata_command(&mut self, command: SavedataCommand) -> bool {
+        match command {
+            SavedataCommand::AddLogEntry {
+                name, text, voice, ..
+            } => {
+                self.backlog.push(LogEntry { name, text, voice });
+                false
+            }
+            SavedataCommand::QuickSave => {
+                self.save(SaveSlot::Quick);
+                false
+            }
+            SavedataCommand::QuickLoad => self.load(SaveSlot::Quick),
+            SavedataCommand::Save(n) => {
+                self.save(SaveSlot::Slot(n));
+                false
+            }
+            SavedataCommand::Load(n) => self.load(SaveSlot::Slot(n)),
+            SavedataCommand::BackupSave => {
+                self.save(SaveSlot::Backup);
+                false
+            }
+            SavedataCommand::BackupLoadIfAvailable => {
+                SaveSlot::Backup.path().exists() && self.load(SaveSlot::Backup)
+            }
+        }
+    }
 
-        buf.set_title(config::get_game_title());
+    fn snapshot(&self) -> Savedata {
+        Savedata {
+            scenario: self.interp.scenario().into(),
+            position: self.interp.position(),
+            waiting: self.waiting,
+            layers: self.layer_states.clone(),
+            dialogue: self.dialogue.clone(),
+            music: self.mixer.music().map(String::from),
+            backlog: self.backlog.to_vec(),
+            variables: self.interp.variables().to_json(),
+            call_stack: self.interp.call_stack().to_vec(),
+        }
+    }
 
-        // create layer renderer
-        self.layers
-            .resize_with(30, || LayerRenderer::new(buf.format()));
+    pub fn save(&mut self, slot: SaveSlot) {
+        let savedata = self.snapshot();
+
+        match savedata.write(slot.path()) {
+            Ok(_) => log::info!("saved to {:?}", slot),
+            Err(err) => log::error!("failed to save to {:?}: {}", slot, err),
+        }
+    }
 
-        use crate::renderer::vulkano::layer::LayerRenderingContext;
-        use crate::renderer::vulkano::pipeline;
+    /// Loads a savedata; returns `false` if the slot is not available.
+    pub fn load(&mut self, slot: SaveSlot) -> bool {
+        let savedata = match Savedata::read(slot.path()) {
+            Ok(savedata) => savedata,
+            Err(err) => {
+                log::error!("failed to load {:?}: {}", slot, err);
+                return false;
+            }
+        };
 
-        let render_pass = pipeline::create_render_pass(buf.device.clone(), buf.format())
-            as Arc<dyn RenderPassAbstract + Sync + Send>;
-        let pipeline =
-            pipeline::create_pict_layer_pipeline(buf.device.clone(), render_pass.clone());
-        let pipeline_text =
-            pipeline::create_text_layer_pipeline(buf.device.clone(), render_pass.clone());
+        // the history belongs to the previous playthrough
+        self.rewind.clear();
+        self.restore(savedata);
 
-        self.queue = Some(buf.graphical_queue.clone());
+        log::info!("loaded {:?}", slot);
+        true
+    }
 
-        let ctx = LayerRenderingContext {
-            render_pass,
-            pipeline: pipeline.clone(),
+    fn restore(&mut self, savedata: Savedata) {
+        let mut host = ScriptHost {
+            js: &mut self.js,
+            persistent: &mut self.persistent,
         };
 
-        // for benchmark
+        if let Err(err) = self.interp.restore(
+            &mut host,
+            &savedata.scenario,
+            savedata.position,
+            Variables::from_json(&savedata.variables),
+            savedata.call_stack,
+        ) {
+            log::error!("failed to load {}: {}", savedata.scenario, err);
+        }
 
-        event_loop.run(move |event, _evt_loop, control_flow| {
-            buf.handle_event(&event, control_flow);
+        self.wait_until = None;
+        self.auto_deadline = None;
+        self.skip_keys.cancel();
+        self.backlog_scene = None;
+        self.choice_scene = None;
+        self.mixer.stop_voice();
+
+        for (layer_no, state) in savedata.layers.iter().enumerate() {
+            for command in state.restore(layer_no as i32) {
+                if let MilCommand::LayerCommand { layer_no, command } = command {
+                    self.send_layer_command(layer_no, command);
+                }
+            }
+        }
 
-            match event {
-                Event::WindowEvent {
-                    event: WindowEvent::CloseRequested,
-                    ..
-                } => {
-                    *control_flow = ControlFlow::Exit;
+        match savedata.dialogue {
+            Some(dialogue) => {
+                if let Some(voice) = &dialogue.voice {
+                    self.mixer.play_voice(voice, Instant::now());
                 }
-                Event::WindowEvent {
-                    event: WindowEvent::Resized(_),
-                    ..
-                } => {
-                    use winit::dpi::PhysicalSize;
 
-                    let window = buf.surface.window();
+                self.show_dialogue(dialogue);
+            }
+            None => self.dialogue = None,
+        }
+
+        match &savedata.music {
+            Some(music) => self.mixer.play_music(music, true),
+            None => self.mixer.fade_music(0.0),
+        }
+
+        self.backlog.restore(savedata.backlog);
+        self.waiting = savedata.waiting;
+
+        if self.waiting && self.auto_mode {
+            self.schedule_auto_advance();
+        }
+    }
+
+    fn open_backlog(&mut self) {
+        if self.backlog.is_empty() {
+            return;
+        }
+
+        self.backlog_scene = Some(BacklogScene::new());
+        self.skip_keys.cancel();
+        self.auto_deadline = None;
+    }
 
-                    let size = window.inner_size();
-                    let width = size.width;
-                    let height = size.height;
+    fn close_backlog(&mut self) {
+        self.backlog_scene = None;
 
-                    let resized_height = (width as f64 * (9.0 / 16.0)) as u32;
+        if self.waiting && self.auto_mode {
+            self.schedule_auto_advance();
+        }
+    }
 
-                    if height != resized_height {
-                        window.set_inner_size(PhysicalSize::new(width, resized_height));
+    fn handle_window_event(&mut self, event: &WindowEvent, window_size: PhysicalSize<u32>) {
+        // window coordinates to game coordinates, as the screen is letterboxed
+        let letterbox = Letterbox::for_window((window_size.width, window_size.height));
+
+        match event {
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor = (position.x, position.y);
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let lines = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y as f64,
+                    MouseScrollDelta::PixelDelta(p) => p.y,
+                };
+
+                if lines > 0.0 {
+                    match &mut self.backlog_scene {
+                        Some(scene) => {
+                            scene.scroll(1, &self.backlog);
+                        }
+                        None => self.open_backlog(),
+                    }
+                } else if lines < 0.0 {
+                    let backlog = &self.backlog;
+                    let keep_open = self
+                        .backlog_scene
+                        .as_mut()
+                        .map(|scene| scene.scroll(-1, backlog));
+
+                    if keep_open == Some(false) {
+                        self.close_backlog();
                     }
                 }
-                Event::WindowEvent {
-                    event:
-                        WindowEvent::MouseInput {
-                            state: ElementState::Released,
-                            ..
-                        },
-                    ..
-                } => {
-                    self.waiting = false;
-                    buf.surface.window().request_redraw();
+            }
+            WindowEvent::MouseInput {
+                state: ElementState::Released,
+                button,
+                ..
+            } => {
+                if self.backlog_scene.is_some() {
+                    match button {
+                        MouseButton::Left => {
+                            let (x, y) = letterbox.to_screen(self.cursor);
+
+                            let voice = self
+                                .backlog_scene
+                                .as_ref()
+                                .and_then(|scene| scene.voice_at((x as i32, y as i32)))
+                                .map(String::from);
+
+                            if let Some(voice) = voice {
+                                self.mixer.play_voice(&voice, Instant::now());
+                            }
+                        }
+                        MouseButton::Right => self.close_backlog(),
+                        _ => {}
+                    }
+
+                    return;
                 }
-                Event::RedrawRequested(_) => {
-                    use vulkano::sync::GpuFuture;
 
-                    self.exec_script();
+                if self.choice_scene.is_some() {
+                    if let MouseButton::Left = button {
+                        let (x, y) = letterbox.to_screen(self.cursor);
 
-                    let mut target = buf.draw_begin(&ctx).unwrap();
+                        let index = self
+                            .choice_scene
+                            .as_ref()
+                            .and_then(|scene| scene.option_at((x as i32, y as i32)));
 
-                    if self.text_update {
-                        self.text_layer
-                            .load_gpu(buf.graphical_queue.clone(), pipeline_text.clone());
-                        self.text_update = false;
+                        if let Some(index) = index {
+                            self.choose(index);
+                        }
                     }
 
-                    if let Some(future) = self.text_layer.take_future() {
-                        target.future = Box::new(target.future.join(future));
-                    }
+                    return;
+                }
 
-                    target
-                        .command_buffer
-                        .begin_render_pass(
-                            target.framebuffer.clone(),
-                            false,
-                            vec![[0.0, 0.0, 0.0, 1.0].into()],
-                        )
-                        .unwrap();
-
-                    for l in &mut self.layers {
-                        l.update(buf.graphical_queue.clone(), pipeline.clone());
-                        target.future =
-                            Box::new(target.future.join(l.take_future(buf.device.clone())));
-                        l.render(&mut target, &ctx);
-                    }
+                // the line has been read through; save the read state
+                self.flush_persistent_data();
+
+                // clicking cancels the skip mode
+                self.skip_keys.cancel();
 
-                    self.text_layer.draw(
-                        &mut target.command_buffer,
-                        pipeline_text.clone(),
-                        &mut target.dynamic_state,
-                    );
+                if self.waiting {
+                    self.advance();
+                }
+            }
+            WindowEvent::ModifiersChanged(modifiers) => {
+                // skip while Ctrl is held
+                self.skip_keys
+                    .set_modifiers(modifiers.ctrl(), modifiers.shift());
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(key),
+                        ..
+                    },
+                ..
+            } => match key {
+                VirtualKeyCode::S => self.toggle_skip(),
+                VirtualKeyCode::A => self.toggle_auto(),
+                VirtualKeyCode::F5 => self.save(SaveSlot::Quick),
+                VirtualKeyCode::F9 => {
+                    self.load(SaveSlot::Quick);
+                }
+                VirtualKeyCode::Back => self.rewind(),
+                VirtualKeyCode::Escape if self.backlog_scene.is_some() => self.close_backlog(),
+                VirtualKeyCode::Key1
+                | VirtualKeyCode::Key2
+                | VirtualKeyCode::Key3
+                | VirtualKeyCode::Key4
+                | VirtualKeyCode::Key5
+                | VirtualKeyCode::Key6
+                | VirtualKeyCode::Key7
+                | VirtualKeyCode::Key8
+                | VirtualKeyCode::Key9
+                    if self.backlog_scene.is_none() =>
+                {
+                    self.choose(*key as usize - VirtualKeyCode::Key1 as usize);
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    fn flush_persistent_data(&mut self) {
+        if let Err(err) = self.persistent.flush() {
+            log::error!("failed to write persistent data: {}", err);
+        }
+    }
+
+    /// Runs the game from `entry`, or the scenario configured.
+    pub fn execute(mut self, entry: Option<&str>)
+    where
+        S: 'static,
+    {
+        use crate::config;
+
+        let event_loop = EventLoop::new();
+        let screen = S::new(&event_loop, LAYER_COUNT);
+
+        screen.window().set_title(config::get_game_title());
+        self.screen = Some(screen);
 
-                    target.command_buffer.end_render_pass().unwrap();
+        self.load_script(entry);
+
+        event_loop.run(move |event, _evt_loop, control_flow| {
+            let screen = self.screen.as_mut().unwrap();
+            screen.handle_event(&event, control_flow);
+
+            match event {
+                Event::WindowEvent {
+                    event: WindowEvent::CloseRequested,
+                    ..
+                } => {
+                    self.flush_persistent_data();
+                    *control_flow = ControlFlow::Exit;
+                }
+                Event::WindowEvent { event, .. } => {
+                    let window_size = screen.window().inner_size();
+                    self.handle_window_event(&event, window_size);
+                    self.screen.as_ref().unwrap().window().request_redraw();
+                }
+                Event::RedrawRequested(_) => {
+                    self.poll_console();
+                    self.exec_script();
+
+                    if self.interp.is_finished() {
+                        self.flush_persistent_data();
+                        *control_flow = ControlFlow::Exit;
+                        return;
+                    }
 
-                    buf.draw_end(target, &ctx);
+                    let frame = self.frame();
+                    let screen = self.screen.as_mut().unwrap();
 
-                    buf.surface.window().request_redraw();
+                    screen.draw(&frame);
+                    screen.window().request_redraw();
                 }
                 _ => {}
             }
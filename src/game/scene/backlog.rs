@@ -0,0 +1,116 @@
+//! Backlog scene.
+//!
+//! Shows the dialogue history, newest at the bottom, with a button to replay
+//! the voice of each line.
+
+use super::Scene;
+
+use crate::game::screen::TextBox;
+use crate::script::runtime::backlog::Backlog;
+
+const ENTRIES_PER_PAGE: usize = 4;
+const ENTRY_TOP: i32 = 50;
+const ENTRY_HEIGHT: i32 = 200;
+const TEXT_OFFSET_X: i32 = 300;
+const TEXT_SIZE: (i32, i32) = (1100, 190);
+const VOICE_BUTTON_OFFSET_X: i32 = 200;
+const VOICE_BUTTON_SIZE: (i32, i32) = (64, 64);
+
+struct BacklogLine {
+    text: TextBox,
+    voice_button: Option<(TextBox, String)>,
+}
+
+#[derive(Default)]
+pub struct BacklogScene {
+    // number of entries scrolled back from the newest one
+    scroll: usize,
+    lines: Vec<BacklogLine>,
+    update_flag: bool,
+}
+
+impl Scene for BacklogScene {}
+
+impl BacklogScene {
+    pub fn new() -> Self {
+        Self {
+            update_flag: true,
+            ..Default::default()
+        }
+    }
+
+    /// Scrolls the backlog; positive `delta` goes back to older lines.
+    ///
+    /// Returns `false` when scrolled down past the newest line, i.e. the
+    /// scene should be closed.
+    pub fn scroll(&mut self, delta: isize, backlog: &Backlog) -> bool {
+        let max_scroll = backlog.len().saturating_sub(ENTRIES_PER_PAGE) as isize;
+        let scroll = self.scroll as isize + delta;
+
+        if scroll < 0 {
+            return false;
+        }
+
+        let scroll = scroll.min(max_scroll).max(0) as usize;
+
+        if scroll != self.scroll {
+            self.scroll = scroll;
+            self.update_flag = true;
+        }
+
+        true
+    }
+
+    /// Returns the voice whose replay button is at `(x, y)`.
+    pub fn voice_at(&self, point: (i32, i32)) -> Option<&str> {
+        self.lines
+            .iter()
+            .filter_map(|l| l.voice_button.as_ref())
+            .find(|(button, _)| button.contains(point))
+            .map(|(_, voice)| voice.as_str())
+    }
+
+    pub fn update(&mut self, backlog: &Backlog) {
+        if !self.update_flag {
+            return;
+        }
+
+        self.update_flag = false;
+        self.lines.clear();
+
+        for i in 0..ENTRIES_PER_PAGE {
+            let entry = match backlog.get_latest(self.scroll + i) {
+                Some(entry) => entry,
+                None => break,
+            };
+
+            // the newest line at the bottom
+            let y = ENTRY_TOP + (ENTRIES_PER_PAGE - 1 - i) as i32 * ENTRY_HEIGHT;
+
+            let text = TextBox::new(
+                (TEXT_OFFSET_X, y),
+                TEXT_SIZE,
+                format!(
+                    "{}\n{}",
+                    entry.name.as_deref().unwrap_or_default(),
+                    entry.text
+                ),
+            );
+
+            let voice_button = entry.voice.as_ref().map(|voice| {
+                let button =
+                    TextBox::new((VOICE_BUTTON_OFFSET_X, y), VOICE_BUTTON_SIZE, "▶".into());
+
+                (button, voice.clone())
+            });
+
+            self.lines.push(BacklogLine { text, voice_button });
+        }
+    }
+
+    pub fn texts(&self) -> impl Iterator<Item = &TextBox> {
+        self.lines.iter().flat_map(|l| {
+            std::iter::once(&l.text).chain(l.voice_button.as_ref().map(|(button, _)| button))
+        })
+    }
+}
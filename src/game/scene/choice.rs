@@ -0,0 +1,60 @@
+//! Choice scene.
+//!
+//! Shows the options of a choice over the current screen; the story resumes
+//! from the label of the option picked.
+
+use super::Scene;
+
+use crate::constants::{GAME_WINDOW_HEIGHT, GAME_WINDOW_WIDTH};
+use crate::game::screen::TextBox;
+use crate::script::mil::command::ChoiceOption;
+
+const OPTION_SIZE: (i32, i32) = (800, 80);
+const OPTION_SPACING: i32 = 100;
+
+pub struct ChoiceScene {
+    options: Vec<ChoiceOption>,
+    texts: Vec<TextBox>,
+}
+
+impl Scene for ChoiceScene {}
+
+impl ChoiceScene {
+    pub fn new(options: Vec<ChoiceOption>) -> Self {
+        let texts = options
+            .iter()
+            .enumerate()
+            .map(|(i, option)| {
+                TextBox::new(
+                    Self::option_offset(i, options.len()),
+                    OPTION_SIZE,
+                    format!("{}. {}", i + 1, option.text),
+                )
+            })
+            .collect();
+
+        Self { options, texts }
+    }
+
+    // offset of the option, centered in the screen
+    fn option_offset(i: usize, count: usize) -> (i32, i32) {
+        let height = count as i32 * OPTION_SPACING;
+        let x = (GAME_WINDOW_WIDTH as i32 - OPTION_SIZE.0) / 2;
+        let y = (GAME_WINDOW_HEIGHT as i32 - height) / 2 + i as i32 * OPTION_SPACING;
+
+        (x, y)
+    }
+
+    /// Returns the index of the option at `(x, y)`.
+    pub fn option_at(&self, point: (i32, i32)) -> Option<usize> {
+        self.texts.iter().position(|t| t.contains(point))
+    }
+
+    pub fn label(&self, index: usize) -> Option<&str> {
+        self.options.get(index).map(|o| o.label.as_str())
+    }
+
+    pub fn texts(&self) -> impl Iterator<Item = &TextBox> {
+        self.texts.iter()
+    }
+}
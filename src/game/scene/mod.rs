@@ -1,3 +1,5 @@
+pub mod backlog;
+pub mod choice;
 pub mod scenario;
 
 pub trait Scene {}
@@ -0,0 +1,236 @@
+//! CPU screen.
+
+use super::{Frame, Screen, TextBox};
+
+use crate::constants::DIALOGUE_FONT_HEIGHT;
+use crate::renderer::common::text;
+use crate::renderer::cpu::damage::{Damage, Rect};
+use crate::renderer::cpu::image::Image;
+use crate::renderer::cpu::layer::LayerRenderer;
+use crate::renderer::cpu::utils::{self, BlendSource};
+use crate::renderer::cpu::{CpuBackend, CpuRenderingTarget, CpuSurface};
+use crate::renderer::{EventDelegate, RenderingSurface};
+use crate::script::mil::command::LayerCommand;
+
+use winit::event::Event;
+use winit::event_loop::{ControlFlow, EventLoop};
+use winit::window::Window;
+
+use std::collections::VecDeque;
+use std::path::Path;
+use std::time::Instant;
+
+// frames of damage kept for targets that hold an older frame
+const DAMAGE_HISTORY: usize = 4;
+
+/// Composites the layers and the texts of a frame on the CPU, into a window
+/// or a headless surface alike.
+///
+/// Only the regions changed since the target was last drawn into are drawn
+/// again, if the target tells its age.
+pub struct CpuCompositor {
+    layers: Vec<LayerRenderer>,
+    // texts of the last frame, kept rasterized while unchanged
+    texts: Vec<(TextBox, Image)>,
+    hide_layers: bool,
+    // damage of the last frames, the latest first
+    history: VecDeque<Damage>,
+}
+
+impl CpuCompositor {
+    pub fn new(layer_count: usize) -> Self {
+        let mut layers = vec![];
+        layers.resize_with(layer_count, LayerRenderer::new);
+
+        Self {
+            layers,
+            texts: vec![],
+            hide_layers: false,
+            history: VecDeque::new(),
+        }
+    }
+
+    /// Forwards a command to the layer.
+    pub fn send(&mut self, layer_no: usize, command: LayerCommand) {
+        match self.layers.get_mut(layer_no) {
+            Some(layer) => layer.send(command),
+            None => log::error!("no such layer: {}; dropped {:?}", layer_no, command),
+        }
+    }
+
+    /// Looks up the archives of the layers in `root` instead of the
+    /// configured root.
+    pub fn set_root(&mut self, root: &Path) {
+        for l in &mut self.layers {
+            l.root = Some(root.to_path_buf());
+        }
+    }
+
+    fn text_rect(text_box: &TextBox) -> Rect {
+        let (x, y) = text_box.offset;
+        let (width, height) = text_box.size;
+
+        Rect::new(
+            x as isize,
+            y as isize,
+            width.max(0) as usize,
+            height.max(0) as usize,
+        )
+    }
+
+    fn update_texts(&mut self, texts: &[TextBox], damage: &mut Damage) {
+        for (text_box, _) in self.texts.iter().skip(texts.len()) {
+            damage.add(Self::text_rect(text_box));
+        }
+
+        self.texts.truncate(texts.len());
+
+        for (i, text_box) in texts.iter().enumerate() {
+            match self.texts.get(i) {
+                Some((t, _)) if t == text_box => continue,
+                Some((t, _)) => damage.add(Self::text_rect(t)),
+                None => {}
+            }
+
+            damage.add(Self::text_rect(text_box));
+
+            let (width, height) = (text_box.size.0 as usize, text_box.size.1 as usize);
+            let mut image = Image::new(width, height);
+
+            text::write_text_in_box(
+                text::create_font(),
+                DIALOGUE_FONT_HEIGHT,
+                &text_box.text,
+                (width, height),
+                &mut image.rgba_buffer,
+            );
+
+            if i < self.texts.len() {
+                self.texts[i] = (text_box.clone(), image);
+            } else {
+                self.texts.push((text_box.clone(), image));
+            }
+        }
+    }
+
+    pub fn draw<T>(&mut self, target: &mut T, frame: &Frame)
+    where
+        T: CpuRenderingTarget,
+    {
+        self.draw_at(target, frame, Instant::now());
+    }
+
+    /// Draws the frame as of `now`, e.g. on a virtual clock.
+    pub fn draw_at<T>(&mut self, target: &mut T, frame: &Frame, now: Instant)
+    where
+        T: CpuRenderingTarget,
+    {
+        let mut damage = Damage::new();
+
+        self.update_texts(&frame.texts, &mut damage);
+
+        for l in &mut self.layers {
+            l.update_at(now);
+
+            let layer_damage = l.take_damage();
+
+            if !frame.hide_layers {
+                damage.extend(&layer_damage);
+            }
+        }
+
+        if self.hide_layers != frame.hide_layers {
+            self.hide_layers = frame.hide_layers;
+
+            for l in &self.layers {
+                damage.add(l.bounds());
+            }
+        }
+
+        self.history.push_front(damage);
+        self.history.truncate(DAMAGE_HISTORY);
+
+        // the damage since the target was drawn into; everything if unknown
+        let region = match target.age() {
+            Some(age) if age <= self.history.len() => {
+                let mut region = Damage::new();
+                for damage in self.history.iter().take(age) {
+                    region.extend(damage);
+                }
+                Some(region)
+            }
+            _ => None,
+        };
+
+        if matches!(&region, Some(region) if region.is_empty()) {
+            return;
+        }
+
+        let mut stack = vec![];
+
+        if !frame.hide_layers {
+            stack.extend(self.layers.iter().filter_map(LayerRenderer::blend_source));
+        }
+
+        for (text_box, image) in &self.texts {
+            let (x, y) = text_box.offset;
+            stack.push(BlendSource::new(
+                image.as_slice(),
+                (x as isize, y as isize),
+                1.0,
+            ));
+        }
+
+        let mut framebuffer = target.framebuffer();
+        let screen = Rect::new(0, 0, framebuffer.width, framebuffer.height);
+        let region = match region {
+            Some(region) => region.clip(screen),
+            None => Damage::from_rect(screen),
+        };
+
+        // every scanline goes through the whole stack at once, over opaque
+        // black as the window is cleared to
+        for &rect in region.rects() {
+            utils::fill_rect(&mut framebuffer, rect, [0x00, 0x00, 0x00, 0xff]);
+            utils::composite_rect(&stack, &mut framebuffer, rect);
+        }
+    }
+}
+
+pub struct CpuScreen {
+    surface: CpuSurface,
+    compositor: CpuCompositor,
+}
+
+impl EventDelegate for CpuScreen {
+    type UserEvent = ();
+
+    fn handle_event(&mut self, event: &Event<Self::UserEvent>, control_flow: &mut ControlFlow) {
+        self.surface.handle_event(event, control_flow)
+    }
+}
+
+impl Screen for CpuScreen {
+    type Backend = CpuBackend;
+
+    fn new(event_loop: &EventLoop<()>, layer_count: usize) -> Self {
+        Self {
+            surface: CpuSurface::new(event_loop),
+            compositor: CpuCompositor::new(layer_count),
+        }
+    }
+
+    fn window(&self) -> &Window {
+        self.surface.window()
+    }
+
+    fn send(&mut self, layer_no: usize, command: LayerCommand) {
+        self.compositor.send(layer_no, command);
+    }
+
+    fn draw(&mut self, frame: &Frame) {
+        let mut target = self.surface.draw_begin(&()).unwrap();
+        self.compositor.draw(&mut target, frame);
+        self.surface.draw_end(target, &());
+    }
+}
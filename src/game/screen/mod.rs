@@ -0,0 +1,84 @@
+//! Screens.
+//!
+//! A screen owns the window, the layers and everything drawn for a graphic
+//! backend. The game only talks to it through MIL layer commands and a
+//! description of the frame, so that every backend sees the same stream.
+
+pub mod cpu;
+pub mod vulkano;
+
+use crate::renderer::{EventDelegate, GraphicBackend};
+use crate::script::mil::command::LayerCommand;
+
+use winit::event_loop::EventLoop;
+use winit::window::Window;
+
+/// Text drawn over the layers, in game coordinates.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextBox {
+    pub offset: (i32, i32),
+    pub size: (i32, i32),
+    pub text: String,
+}
+
+impl TextBox {
+    pub fn new(offset: (i32, i32), size: (i32, i32), text: String) -> Self {
+        Self { offset, size, text }
+    }
+
+    pub fn contains(&self, (x, y): (i32, i32)) -> bool {
+        let (bx, by) = self.offset;
+        let (bw, bh) = self.size;
+
+        bx <= x && x < bx + bw && by <= y && y < by + bh
+    }
+}
+
+/// What is drawn in a frame besides the layers.
+#[derive(Clone, Debug, Default)]
+pub struct Frame {
+    /// The layers are hidden while the backlog is open.
+    pub hide_layers: bool,
+    pub texts: Vec<TextBox>,
+}
+
+pub trait Screen: EventDelegate<UserEvent = ()> {
+    type Backend: GraphicBackend;
+
+    /// Opens the window with `layer_count` layers.
+    fn new(event_loop: &EventLoop<()>, layer_count: usize) -> Self;
+
+    fn window(&self) -> &Window;
+
+    /// Forwards a command to the layer.
+    fn send(&mut self, layer_no: usize, command: LayerCommand);
+
+    fn draw(&mut self, frame: &Frame);
+}
+
+// opens windows, so run it on a desktop with `cargo test -- --ignored`
+#[test]
+#[ignore]
+#[cfg(target_os = "linux")]
+fn test_screen_backends() {
+    use crate::constants::LAYER_COUNT;
+    use winit::platform::unix::EventLoopExtUnix;
+
+    fn open<S: Screen>(event_loop: &EventLoop<()>) {
+        let mut screen = S::new(event_loop, LAYER_COUNT);
+        assert!(screen.window().inner_size().width > 0);
+
+        screen.send(0, LayerCommand::SetOpacity(0.5));
+        screen.send(LAYER_COUNT, LayerCommand::Unload);
+        screen.draw(&Frame {
+            hide_layers: false,
+            texts: vec![TextBox::new((0, 0), (100, 20), "text".into())],
+        });
+    }
+
+    // tests run off the main thread
+    let event_loop = EventLoop::new_any_thread();
+
+    open::<cpu::CpuScreen>(&event_loop);
+    open::<vulkano::VulkanoScreen>(&event_loop);
+}
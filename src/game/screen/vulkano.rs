@@ -0,0 +1,223 @@
+//! Vulkano screen.
+
+use super::{Frame, Screen, TextBox};
+
+use crate::renderer::vulkano::backdrop::Backdrop;
+use crate::renderer::vulkano::layer::{LayerRenderer, LayerRenderingContext};
+use crate::renderer::vulkano::pipeline;
+use crate::renderer::vulkano::surface::VulkanoSurface;
+use crate::renderer::vulkano::text::{self, Text};
+use crate::renderer::vulkano::texture_loader;
+use crate::renderer::vulkano::VulkanoBackend;
+use crate::renderer::{EventDelegate, Renderer, RenderingSurface};
+use crate::script::mil::command::{BlendMode, LayerCommand};
+
+use ::vulkano::descriptor::PipelineLayoutAbstract;
+use ::vulkano::framebuffer::{Framebuffer, RenderPassAbstract};
+use ::vulkano::pipeline::vertex::SingleBufferDefinition;
+use ::vulkano::pipeline::GraphicsPipeline;
+use ::vulkano::sync::GpuFuture;
+
+use winit::event::Event;
+use winit::event_loop::{ControlFlow, EventLoop};
+use winit::window::Window;
+
+use std::sync::Arc;
+
+type TextPipeline = Arc<
+    GraphicsPipeline<
+        SingleBufferDefinition<text::Vertex>,
+        Box<dyn PipelineLayoutAbstract + Send + Sync>,
+        Arc<dyn RenderPassAbstract + Sync + Send>,
+    >,
+>;
+
+pub struct VulkanoScreen {
+    surface: VulkanoSurface<'static>,
+    layers: Vec<LayerRenderer>,
+    ctx: LayerRenderingContext,
+    // resumes drawing after the target is copied to the backdrop
+    resuming_pass: Arc<dyn RenderPassAbstract + Sync + Send>,
+    pipeline_text: TextPipeline,
+    // texts of the last frame, kept on the GPU while unchanged
+    texts: Vec<(TextBox, Text)>,
+}
+
+impl VulkanoScreen {
+    fn update_texts(&mut self, texts: &[TextBox]) -> Option<Box<dyn GpuFuture>> {
+        let mut future: Option<Box<dyn GpuFuture>> = None;
+
+        self.texts.truncate(texts.len());
+
+        for (i, text_box) in texts.iter().enumerate() {
+            if self.texts.get(i).map(|(t, _)| t) == Some(text_box) {
+                continue;
+            }
+
+            let queue = self.surface.graphical_queue.clone();
+
+            let mut text = Text::new(text_box.offset, text_box.size);
+            text.write(&text_box.text, queue.clone());
+            text.load_gpu(queue, self.pipeline_text.clone());
+
+            if let Some(f) = text.take_future() {
+                future = Some(match future {
+                    Some(future) => Box::new(future.join(f)),
+                    None => f,
+                });
+            }
+
+            if i < self.texts.len() {
+                self.texts[i] = (text_box.clone(), text);
+            } else {
+                self.texts.push((text_box.clone(), text));
+            }
+        }
+
+        future
+    }
+}
+
+impl EventDelegate for VulkanoScreen {
+    type UserEvent = ();
+
+    fn handle_event(&mut self, event: &Event<Self::UserEvent>, control_flow: &mut ControlFlow) {
+        self.surface.handle_event(event, control_flow)
+    }
+}
+
+impl Screen for VulkanoScreen {
+    type Backend = VulkanoBackend;
+
+    fn new(event_loop: &EventLoop<()>, layer_count: usize) -> Self {
+        let surface = VulkanoSurface::new(event_loop);
+
+        let render_pass = pipeline::create_render_pass(surface.device.clone(), surface.format())
+            as Arc<dyn RenderPassAbstract + Sync + Send>;
+        let resuming_pass =
+            pipeline::create_resuming_render_pass(surface.device.clone(), surface.format());
+        let pipeline_layer = pipeline::create_pict_layer_pipeline(
+            surface.device.clone(),
+            render_pass.clone(),
+            BlendMode::Normal,
+        );
+        let blend_pipelines = BlendMode::ALL
+            .iter()
+            .copied()
+            .filter(|&mode| mode != BlendMode::Normal)
+            .map(|mode| {
+                let pipeline = pipeline::create_pict_layer_pipeline(
+                    surface.device.clone(),
+                    render_pass.clone(),
+                    mode,
+                );
+                (mode, pipeline)
+            })
+            .collect();
+        let pipeline_text =
+            pipeline::create_text_layer_pipeline(surface.device.clone(), render_pass.clone());
+
+        // textures are decoded as the target encodes, if it does
+        let format = texture_loader::texture_format(surface.is_srgb());
+
+        let mut layers = vec![];
+        layers.resize_with(layer_count, || LayerRenderer::new(format));
+
+        Self {
+            surface,
+            layers,
+            ctx: LayerRenderingContext {
+                render_pass,
+                pipeline: pipeline_layer,
+                blend_pipelines,
+                backdrop: None,
+            },
+            resuming_pass,
+            pipeline_text,
+            texts: vec![],
+        }
+    }
+
+    fn window(&self) -> &Window {
+        self.surface.surface.window()
+    }
+
+    fn send(&mut self, layer_no: usize, command: LayerCommand) {
+        match self.layers.get_mut(layer_no) {
+            Some(layer) => layer.send(command),
+            None => log::error!("no such layer: {}; dropped {:?}", layer_no, command),
+        }
+    }
+
+    fn draw(&mut self, frame: &Frame) {
+        let mut target = self.surface.draw_begin(&self.ctx).unwrap();
+
+        if let Some(future) = self.update_texts(&frame.texts) {
+            target.future = Box::new(target.future.join(future));
+        }
+
+        target
+            .command_buffer
+            .begin_render_pass(
+                target.framebuffer.clone(),
+                false,
+                vec![[0.0, 0.0, 0.0, 1.0].into()],
+            )
+            .unwrap();
+
+        // the backdrop follows the size of the swapchain
+        let dimensions = self.surface.swapchain.dimensions();
+        if self.ctx.backdrop.as_ref().map(Backdrop::dimensions) != Some(dimensions) {
+            self.ctx.backdrop = Some(Backdrop::new(
+                dimensions,
+                self.surface.format(),
+                self.surface.graphical_queue.clone(),
+                &self.ctx.pipeline_for(BlendMode::Overlay),
+            ));
+        }
+
+        for l in &mut self.layers {
+            l.update(
+                self.surface.graphical_queue.clone(),
+                self.ctx.pipeline.clone(),
+            );
+            target.future = Box::new(
+                target
+                    .future
+                    .join(l.take_future(self.surface.device.clone())),
+            );
+
+            if frame.hide_layers {
+                continue;
+            }
+
+            if l.reads_target() {
+                let image = self.surface.images[target.swapchain_no].clone();
+                let framebuffer = Arc::new(
+                    Framebuffer::start(self.resuming_pass.clone())
+                        .add(image.clone())
+                        .unwrap()
+                        .build()
+                        .unwrap(),
+                );
+
+                let backdrop = self.ctx.backdrop.as_ref().unwrap();
+                backdrop.copy(&mut target.command_buffer, image, framebuffer);
+            }
+
+            l.render(&mut target, &self.ctx);
+        }
+
+        for (_, text) in &self.texts {
+            text.draw(
+                &mut target.command_buffer,
+                self.pipeline_text.clone(),
+                &target.dynamic_state,
+            );
+        }
+
+        target.command_buffer.end_render_pass().unwrap();
+
+        self.surface.draw_end(target, &self.ctx);
+    }
+}
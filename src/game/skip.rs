@@ -0,0 +1,109 @@
+//! Skip mode.
+//!
+//! Ctrl skips while held, and S toggles skipping until a click, an unread
+//! line or a choice. Either skips the lines already read, or every line with
+//! Shift held, or when the preferences say so.
+
+/// Skip mode variants.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SkipMode {
+    /// Skips already-read lines, and stops at the first unread one.
+    ReadOnly,
+    /// Skips everything.
+    All,
+}
+
+impl SkipMode {
+    /// Whether the line may be skipped.
+    pub fn can_skip(self, line_read: bool) -> bool {
+        match self {
+            SkipMode::ReadOnly => line_read,
+            SkipMode::All => true,
+        }
+    }
+}
+
+/// Skip mode as asked for from the keyboard.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SkipKeys {
+    ctrl: bool,
+    shift: bool,
+    // toggled with S; whether Shift was held at the time
+    toggled: Option<bool>,
+}
+
+impl SkipKeys {
+    pub fn set_modifiers(&mut self, ctrl: bool, shift: bool) {
+        self.ctrl = ctrl;
+        self.shift = shift;
+    }
+
+    pub fn toggle(&mut self) {
+        self.toggled = match self.toggled {
+            Some(_) => None,
+            None => Some(self.shift),
+        };
+    }
+
+    /// Stops the toggled skip; Ctrl held keeps skipping.
+    pub fn cancel(&mut self) {
+        self.toggled = None;
+    }
+
+    /// Returns the active skip mode, if any; `skip_unread` is the preference
+    /// to skip unread lines as well.
+    pub fn mode(&self, skip_unread: bool) -> Option<SkipMode> {
+        let mode = |all: bool| {
+            if all || skip_unread {
+                SkipMode::All
+            } else {
+                SkipMode::ReadOnly
+            }
+        };
+
+        let held = if self.ctrl {
+            Some(mode(self.shift))
+        } else {
+            None
+        };
+
+        held.max(self.toggled.map(mode))
+    }
+}
+
+#[test]
+fn test_skip_keys() {
+    let mut keys = SkipKeys::default();
+    assert_eq!(keys.mode(false), None);
+
+    // Ctrl skips read lines, and Shift+Ctrl everything
+    keys.set_modifiers(true, false);
+    assert_eq!(keys.mode(false), Some(SkipMode::ReadOnly));
+    assert_eq!(keys.mode(true), Some(SkipMode::All));
+    keys.set_modifiers(true, true);
+    assert_eq!(keys.mode(false), Some(SkipMode::All));
+    keys.set_modifiers(false, false);
+    assert_eq!(keys.mode(false), None);
+
+    // S toggles, and Shift+S toggles skipping everything
+    keys.toggle();
+    assert_eq!(keys.mode(false), Some(SkipMode::ReadOnly));
+    keys.toggle();
+    assert_eq!(keys.mode(false), None);
+
+    keys.set_modifiers(false, true);
+    keys.toggle();
+    keys.set_modifiers(false, false);
+    assert_eq!(keys.mode(false), Some(SkipMode::All));
+
+    // cancelling leaves Ctrl held
+    keys.set_modifiers(true, false);
+    keys.cancel();
+    assert_eq!(keys.mode(false), Some(SkipMode::ReadOnly));
+    keys.set_modifiers(false, false);
+    assert_eq!(keys.mode(false), None);
+
+    assert!(SkipMode::ReadOnly.can_skip(true));
+    assert!(!SkipMode::ReadOnly.can_skip(false));
+    assert!(SkipMode::All.can_skip(false));
+}
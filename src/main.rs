@@ -1,5 +1,7 @@
 #![warn(clippy::all)]
 
+pub mod audio;
+pub mod cli;
 pub mod config;
 pub mod constants;
 pub mod format;
@@ -11,19 +13,56 @@ pub mod script;
 pub mod utils;
 
 fn main() {
+    let cli = match cli::Cli::parse(std::env::args().skip(1)) {
+        Ok(cli) => cli,
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    // tools print to stdout; keep the logs out of their way
+    let level = if cli.command.is_headless() {
+        log::LevelFilter::Warn
+    } else {
+        log::LevelFilter::Debug
+    };
+
     // #[cfg(debug_assertions)]
     {
         use simplelog::*;
-        let logger = TermLogger::new(LevelFilter::Debug, Config::default(), TerminalMode::Mixed);
+        let logger = TermLogger::new(level, Config::default(), TerminalMode::Mixed);
         let _ = CombinedLogger::init(vec![logger]);
     }
 
-    platform::setup_panic_handler();
+    if !cli.command.is_headless() {
+        platform::setup_panic_handler();
+    }
     
     log::debug!("？？？「幾重にも辛酸を舐め、七難八苦を超え、艱難辛苦の果て、満願成就に至る——」");
 
+    if let Err(err) = config::init(&cli.config) {
+        eprintln!("invalid configuration: {}", err);
+        std::process::exit(1);
+    }
+
     script::runtime::init();
 
-    let game = game::Game::new();
-    game.execute();
+    match cli.command {
+        cli::Command::Play { entry } => match config::CONFIG.backend {
+            config::Backend::Vulkano => {
+                let game = game::Game::<game::screen::vulkano::VulkanoScreen>::new();
+                game.execute(entry.as_deref());
+            }
+            config::Backend::Cpu => {
+                let game = game::Game::<game::screen::cpu::CpuScreen>::new();
+                game.execute(entry.as_deref());
+            }
+        },
+        command => {
+            if !command.run_tool() {
+                std::process::exit(1);
+            }
+        }
+    }
 }
@@ -19,6 +19,7 @@ pub enum AnimationType {
     MoveTo(f64, f64),
     MoveBy(f64, f64),
     Opacity(f32),
+    Blur(f64, f64),
 }
 
 struct AnimationEntry {
@@ -4,6 +4,7 @@ use std::collections::VecDeque;
 use std::path::PathBuf;
 use std::time::{Duration, Instant};
 
+use crate::script::mil::command::BlendMode;
 use crate::utils::easing::Easing;
 
 use animation::{Animation, AnimationType};
@@ -18,7 +19,8 @@ pub struct LayerModel {
     // layer property
     pub origin: (f64, f64),
     pub opacity: f32,
-    pub blur_radius: (i32, i32),
+    pub blur_radius: (f64, f64),
+    pub blend_mode: BlendMode,
     // TODO: overlay
     pub overlay: Option<PathBuf>,
     pub overlay_entries: Vec<i32>,
@@ -60,6 +62,12 @@ impl AnimationType {
 
                 AnimationType::Opacity(opacity)
             }
+            (&AnimationType::Blur(x_from, y_from), &AnimationType::Blur(x_to, y_to)) => {
+                let x = x_to + ((x_from - x_to) * t);
+                let y = y_to + ((y_from - y_to) * t);
+
+                AnimationType::Blur(x, y)
+            }
             _ => unreachable!("animation type should match"),
         }
     }
@@ -73,7 +81,8 @@ pub enum LayerCommand {
     LayerDelay(Duration),
     LayerMoveTo(f64, f64),
     LayerOpacity(f32),
-    LayerBlur(i32, i32),
+    LayerBlur(f64, f64),
+    LayerBlendMode(BlendMode),
     LayerWaitDraw,
     LayerAnimate {
         duration: Duration,
@@ -87,6 +96,7 @@ impl LayerModel {
     pub fn new(layer_no: i32) -> Self {
         Self {
             layer_no,
+            opacity: 1.0,
             ..Default::default()
         }
     }
@@ -98,7 +108,7 @@ impl LayerModel {
         self.animations = animations
             .into_iter()
             .filter_map(|a| {
-                if self.finalize_mode || (a.start_time + a.duration) < now {
+                if self.finalize_mode || (a.start_time + a.duration) <= now {
                     match &a.to {
                         &AnimationType::MoveTo(x, y) => {
                             self.origin = (x, y);
@@ -106,6 +116,9 @@ impl LayerModel {
                         &AnimationType::Opacity(opacity) => {
                             self.opacity = opacity;
                         }
+                        &AnimationType::Blur(x, y) => {
+                            self.blur_radius = (x, y);
+                        }
                         _ => unreachable!("all animation should be transformed to **To format"),
                     }
 
@@ -131,6 +144,9 @@ impl LayerModel {
                     AnimationType::Opacity(opacity) => {
                         self.opacity = opacity;
                     }
+                    AnimationType::Blur(x, y) => {
+                        self.blur_radius = (x, y);
+                    }
                     _ => unreachable!("all animation should be transformed to **To format"),
                 }
 
@@ -232,6 +248,9 @@ impl LayerModel {
             Some(LayerCommand::LayerBlur(x, y)) => {
                 self.blur_radius = (x, y);
             }
+            Some(LayerCommand::LayerBlendMode(mode)) => {
+                self.blend_mode = mode;
+            }
             Some(LayerCommand::LayerDelay(t)) => {
                 if self.finalize_mode {
                     self.state = LayerState::Idle;
@@ -266,6 +285,10 @@ impl LayerModel {
                         self.opacity = opacity;
                         (AnimationType::Opacity(opacity_from), to)
                     }
+                    &AnimationType::Blur(_, _) => {
+                        let (x_from, y_from) = self.blur_radius;
+                        (AnimationType::Blur(x_from, y_from), to)
+                    }
                 };
 
                 self.animations.push(Animation {
@@ -284,3 +307,27 @@ impl LayerModel {
         }
     }
 }
+
+#[test]
+fn test_blur_animation() {
+    let mut layer = LayerModel::new(0);
+    let start = Instant::now();
+
+    layer.send(LayerCommand::LayerBlur(2.0, 0.0));
+    animation::AnimationBuilder::new()
+        .next(
+            Duration::from_secs(1),
+            AnimationType::Blur(6.0, 4.0),
+            Easing::Linear,
+        )
+        .build(&mut layer);
+
+    layer.poll(start);
+    assert_eq!(layer.blur_radius, (2.0, 0.0));
+
+    layer.poll(start + Duration::from_millis(500));
+    assert_eq!(layer.blur_radius, (4.0, 2.0));
+
+    layer.poll(start + Duration::from_secs(2));
+    assert_eq!(layer.blur_radius, (6.0, 4.0));
+}
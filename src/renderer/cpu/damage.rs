@@ -0,0 +1,205 @@
+//! Damage tracking.
+//!
+//! Regions of the screen that changed since a frame, so that only those are
+//! redrawn.
+
+/// A rectangle, from `left` and `top` inclusive to `right` and `bottom`
+/// exclusive.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rect {
+    pub left: isize,
+    pub top: isize,
+    pub right: isize,
+    pub bottom: isize,
+}
+
+impl Rect {
+    pub fn new(x: isize, y: isize, width: usize, height: usize) -> Self {
+        Self {
+            left: x,
+            top: y,
+            right: x + width as isize,
+            bottom: y + height as isize,
+        }
+    }
+
+    pub fn empty() -> Self {
+        Self::new(0, 0, 0, 0)
+    }
+
+    pub fn width(&self) -> usize {
+        (self.right - self.left).max(0) as usize
+    }
+
+    pub fn height(&self) -> usize {
+        (self.bottom - self.top).max(0) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.right <= self.left || self.bottom <= self.top
+    }
+
+    /// The smallest rectangle containing both.
+    pub fn union(&self, other: &Rect) -> Rect {
+        if self.is_empty() {
+            return *other;
+        } else if other.is_empty() {
+            return *self;
+        }
+
+        Rect {
+            left: self.left.min(other.left),
+            top: self.top.min(other.top),
+            right: self.right.max(other.right),
+            bottom: self.bottom.max(other.bottom),
+        }
+    }
+
+    /// The part in both; empty if they do not overlap.
+    pub fn intersection(&self, other: &Rect) -> Rect {
+        let rect = Rect {
+            left: self.left.max(other.left),
+            top: self.top.max(other.top),
+            right: self.right.min(other.right),
+            bottom: self.bottom.min(other.bottom),
+        };
+
+        if rect.is_empty() {
+            Rect::empty()
+        } else {
+            rect
+        }
+    }
+
+    pub fn overlaps(&self, other: &Rect) -> bool {
+        !self.intersection(other).is_empty()
+    }
+
+    /// Grows the rectangle by `dx` on the left and right, and `dy` on the top
+    /// and bottom.
+    pub fn expand(&self, dx: isize, dy: isize) -> Rect {
+        if self.is_empty() {
+            return *self;
+        }
+
+        Rect {
+            left: self.left - dx,
+            top: self.top - dy,
+            right: self.right + dx,
+            bottom: self.bottom + dy,
+        }
+    }
+}
+
+// rectangles kept apart before they are merged into their bounds, as many
+// small redraws cost more than a larger one
+const MAX_RECTS: usize = 8;
+
+/// Damaged regions, as rectangles that do not overlap.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Damage {
+    rects: Vec<Rect>,
+}
+
+impl Damage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The whole of `rect`.
+    pub fn from_rect(rect: Rect) -> Self {
+        let mut damage = Self::new();
+        damage.add(rect);
+        damage
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rects.is_empty()
+    }
+
+    pub fn rects(&self) -> &[Rect] {
+        &self.rects
+    }
+
+    /// Adds `rect`, merging it with the rectangles it overlaps.
+    pub fn add(&mut self, rect: Rect) {
+        if rect.is_empty() {
+            return;
+        }
+
+        let mut rect = rect;
+
+        // the merged rectangle may overlap others in turn
+        while let Some(i) = self.rects.iter().position(|r| r.overlaps(&rect)) {
+            rect = rect.union(&self.rects.swap_remove(i));
+        }
+
+        self.rects.push(rect);
+
+        if MAX_RECTS < self.rects.len() {
+            let bounds = self.rects.iter().fold(Rect::empty(), |b, r| b.union(r));
+            self.rects = vec![bounds];
+        }
+    }
+
+    pub fn extend(&mut self, other: &Damage) {
+        for &rect in &other.rects {
+            self.add(rect);
+        }
+    }
+
+    /// Takes the damage out, leaving nothing damaged.
+    pub fn take(&mut self) -> Damage {
+        std::mem::replace(self, Damage::new())
+    }
+
+    /// The damage within `bounds`.
+    pub fn clip(&self, bounds: Rect) -> Damage {
+        let mut damage = Damage::new();
+
+        for rect in &self.rects {
+            damage.add(rect.intersection(&bounds));
+        }
+
+        damage
+    }
+}
+
+#[test]
+fn test_damage() {
+    let mut damage = Damage::new();
+    assert!(damage.is_empty());
+
+    damage.add(Rect::new(0, 0, 10, 10));
+    damage.add(Rect::new(20, 0, 10, 10));
+    damage.add(Rect::empty());
+    assert_eq!(damage.rects().len(), 2);
+
+    // bridging both merges all three
+    damage.add(Rect::new(5, 5, 20, 2));
+    assert_eq!(damage.rects(), &[Rect::new(0, 0, 30, 10)]);
+
+    // touching edges do not overlap
+    damage.add(Rect::new(30, 0, 5, 5));
+    assert_eq!(damage.rects().len(), 2);
+
+    let clipped = damage.clip(Rect::new(0, 0, 32, 4));
+    assert_eq!(
+        clipped.rects(),
+        &[Rect::new(0, 0, 30, 4), Rect::new(30, 0, 2, 4)]
+    );
+
+    // too many apart are merged into their bounds
+    let mut damage = Damage::new();
+    for i in 0..=MAX_RECTS as isize {
+        damage.add(Rect::new(i * 10, i * 10, 5, 5));
+    }
+    let last = MAX_RECTS as isize * 10;
+    assert_eq!(
+        damage.rects(),
+        &[Rect::new(0, 0, last as usize + 5, last as usize + 5)]
+    );
+
+    assert_eq!(damage.take().rects().len(), 1);
+    assert!(damage.is_empty());
+}
@@ -9,17 +9,32 @@ use vulkano::format::Format;
 use vulkano::framebuffer::RenderPassAbstract;
 use vulkano::image::{Dimensions, ImageUsage, StorageImage};
 
+use crate::renderer::cpu::image::{ImageSlice, ImageSliceMut};
+use crate::renderer::cpu::utils::scale::Scaler;
 use crate::renderer::RenderingSurface;
+use crate::script::mil::command::BlendMode;
 
 pub struct CpuDelegate {
     pub surface: VulkanoSurface<'static>,
     context: CpuDelegateContext,
+    scaler: Scaler,
 }
 
+/// A frame at the resolution of the game.
 pub struct CpuImageBuffer {
     pub width: usize,
     pub height: usize,
     pub rgba_buffer: Vec<u8>,
+    /// Frames since drawn into, once presented.
+    pub age: Option<usize>,
+    // drawn into since resampled to the texture
+    pub(super) modified: bool,
+    output: Option<OutputTexture>,
+}
+
+// the frame resampled to the letterbox of the window
+struct OutputTexture {
+    size: (u32, u32),
     texture: StorageTexture,
     buffer: Arc<CpuAccessibleBuffer<[u8]>>,
     sets: Arc<dyn DescriptorSet + Sync + Send>,
@@ -30,18 +45,57 @@ impl CpuDelegate {
         let surface = VulkanoSurface::new(event_loop);
         let context = CpuDelegateContext::new(surface.device.clone(), surface.format());
 
-        Self { surface, context }
+        Self {
+            surface,
+            context,
+            scaler: Scaler::from_config(),
+        }
     }
 
-    pub fn draw(&mut self, framebuffer: &CpuImageBuffer) {
+    pub fn draw(&mut self, framebuffer: &mut CpuImageBuffer) {
         let mut target = self.surface.draw_begin(&self.context).unwrap();
 
-        framebuffer.load_buffer();
+        // drawn 1:1 into the letterbox, which the swapchain is set up with
+        let letterbox = self.surface.letterbox();
+        let size = (letterbox.width.max(1), letterbox.height.max(1));
+
+        if framebuffer.output.as_ref().map(|output| output.size) != Some(size) {
+            framebuffer.output = Some(self.create_output(size));
+            framebuffer.modified = true;
+        }
+
+        let output = framebuffer.output.as_ref().unwrap();
+
+        // the texture still holds the frame if it has not been drawn into
+        if framebuffer.modified {
+            framebuffer.modified = false;
+
+            match output.buffer.write() {
+                Ok(mut lock) => {
+                    let src = ImageSlice {
+                        width: framebuffer.width,
+                        height: framebuffer.height,
+                        rgba_buffer: &framebuffer.rgba_buffer,
+                    };
+                    let mut dest = ImageSliceMut {
+                        width: size.0 as usize,
+                        height: size.1 as usize,
+                        rgba_buffer: &mut lock,
+                    };
+
+                    self.scaler.scale(&src, &mut dest);
+                }
+                Err(err) => log::debug!("failed to obtain lock: {}", err),
+            }
+
+            target
+                .command_buffer
+                .copy_buffer_to_image(output.buffer.clone(), output.texture.clone())
+                .unwrap();
+        }
 
         target
             .command_buffer
-            .copy_buffer_to_image(framebuffer.buffer.clone(), framebuffer.texture.clone())
-            .unwrap()
             .begin_render_pass(
                 target.framebuffer.clone(),
                 false,
@@ -55,7 +109,7 @@ impl CpuDelegate {
                 self.context.pipeline.clone(),
                 &mut self.surface.dynamic_state,
                 self.context.vertex_buffer.clone(),
-                framebuffer.sets.clone(),
+                output.sets.clone(),
                 (),
             )
             .unwrap();
@@ -65,11 +119,19 @@ impl CpuDelegate {
         self.surface.draw_end(target, &self.context);
     }
 
-    pub fn create_framebuffer(&self, width: u32, height: u32) -> CpuImageBuffer {
+    fn create_output(&self, (width, height): (u32, u32)) -> OutputTexture {
+        // frames are sRGB-encoded already; an sRGB target encodes what it
+        // is given, so that the texture decodes them as they are sampled
+        let format = if self.surface.is_srgb() {
+            Format::R8G8B8A8Srgb
+        } else {
+            Format::R8G8B8A8Unorm
+        };
+
         let texture = create_storage_texture(
             (width, height),
             self.surface.graphical_queue.clone(),
-            Format::R8G8B8A8Unorm, //self.surface.format(),
+            format,
         );
 
         let dim = width as usize * height as usize;
@@ -100,10 +162,8 @@ impl CpuDelegate {
                 .unwrap(),
         );
 
-        CpuImageBuffer {
-            width: width as usize,
-            height: height as usize,
-            rgba_buffer: vec![0x00; dim * 4],
+        OutputTexture {
+            size: (width, height),
             texture,
             buffer,
             sets,
@@ -190,10 +250,12 @@ impl CpuDelegateContext {
         )
         .expect("failed to create buffer");
 
+        // the texture is as large as it is drawn; frames are resampled on the
+        // CPU beforehand
         let sampler = Sampler::new(
             device,
-            Filter::Linear,
-            Filter::Linear,
+            Filter::Nearest,
+            Filter::Nearest,
             MipmapMode::Nearest,
             SamplerAddressMode::ClampToEdge,
             SamplerAddressMode::ClampToEdge,
@@ -215,19 +277,20 @@ impl CpuDelegateContext {
 }
 
 impl CpuImageBuffer {
-    pub fn load_buffer(&self) {
-        let lock = self.buffer.write();
-
-        match lock {
-            Ok(mut lock) => {
-                lock.copy_from_slice(&self.rgba_buffer);
-            }
-            Err(err) => log::debug!("failed to obtain lock: {}", err),
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            rgba_buffer: vec![0x00; width * height * 4],
+            age: None,
+            modified: true,
+            output: None,
         }
     }
 
     pub fn clear(&mut self) {
         crate::utils::memset(&mut self.rgba_buffer, 0x00);
+        self.modified = true;
     }
 
     pub fn draw_image(
@@ -237,8 +300,7 @@ impl CpuImageBuffer {
         (width, height): (i32, i32),
         opacity: f32,
     ) {
-        use super::image::{ImageSlice, ImageSliceMut};
-        use super::utils;
+        use super::utils::{self, BlendSource};
 
         let src_img = ImageSlice {
             width: width as usize,
@@ -252,7 +314,9 @@ impl CpuImageBuffer {
             rgba_buffer: &mut self.rgba_buffer,
         };
 
-        utils::alpha_blend(&src_img, &mut dst_img, (x as isize, y as isize), opacity);
+        let source = BlendSource::new(src_img, (x as isize, y as isize), opacity);
+        utils::composite(&[source], &mut dst_img);
+        self.modified = true;
     }
 
     pub fn draw_image_colored(
@@ -263,8 +327,7 @@ impl CpuImageBuffer {
         opacity: f32,
         tint: [u8; 3],
     ) {
-        use super::image::{ImageSlice, ImageSliceMut};
-        use super::utils;
+        use super::utils::{self, BlendSource};
 
         let src_img = ImageSlice {
             width: width as usize,
@@ -278,13 +341,9 @@ impl CpuImageBuffer {
             rgba_buffer: &mut self.rgba_buffer,
         };
 
-        utils::alpha_blend_colored(
-            &src_img,
-            &mut dst_img,
-            (x as isize, y as isize),
-            opacity,
-            tint,
-        );
+        let source = BlendSource::new(src_img, (x as isize, y as isize), opacity).tinted(tint);
+        utils::composite(&[source], &mut dst_img);
+        self.modified = true;
     }
 }
 
@@ -325,7 +384,7 @@ where
             .triangle_strip()
             .viewports_dynamic_scissors_irrelevant(1)
             .fragment_shader(fs.main_entry_point(), ())
-            .blend_alpha_blending()
+            .blend_collective(pipeline::attachment_blend(BlendMode::Normal))
             .render_pass(Subpass::from(render_pass, 0).unwrap())
             .build(device.clone())
             .unwrap(),
@@ -0,0 +1,162 @@
+//! Windowless surface.
+//!
+//! Renders into an in-memory image instead of a window, so that the CPU
+//! backend runs without a display or a Vulkan device.
+
+use super::image::{Image, ImageSliceMut};
+use super::{CpuBackend, CpuRenderingTarget};
+use crate::renderer::{RenderingContext, RenderingSurface};
+
+use std::ops::{Deref, DerefMut};
+
+/// A frame of a headless surface, with the age of its contents.
+pub struct HeadlessFrame {
+    pub image: Image,
+    age: Option<usize>,
+}
+
+impl HeadlessFrame {
+    fn new(width: usize, height: usize) -> Self {
+        Self {
+            image: Image::new(width, height),
+            age: None,
+        }
+    }
+}
+
+impl Deref for HeadlessFrame {
+    type Target = Image;
+
+    fn deref(&self) -> &Self::Target {
+        &self.image
+    }
+}
+
+impl DerefMut for HeadlessFrame {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.image
+    }
+}
+
+impl CpuRenderingTarget for HeadlessFrame {
+    fn framebuffer(&mut self) -> ImageSliceMut {
+        self.image.framebuffer()
+    }
+
+    fn age(&self) -> Option<usize> {
+        self.age
+    }
+}
+
+pub struct HeadlessSurface {
+    // the last frame presented
+    front: HeadlessFrame,
+    back: Option<HeadlessFrame>,
+    frame_count: u64,
+}
+
+impl HeadlessSurface {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            front: HeadlessFrame::new(width, height),
+            back: None,
+            frame_count: 0,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.front.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.front.height
+    }
+
+    /// Number of frames presented so far.
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    /// The last frame presented.
+    pub fn image(&self) -> &Image {
+        &self.front.image
+    }
+
+    /// RGBA pixels of the last frame presented, row by row.
+    pub fn rgba(&self) -> &[u8] {
+        &self.front.rgba_buffer
+    }
+
+    /// Renders a frame with `draw` and presents it.
+    ///
+    /// The frame holds the one presented before the last, as a swapchain
+    /// would, and `draw` only has to redraw what changed since.
+    pub fn step<F>(&mut self, draw: F) -> &Image
+    where
+        F: FnOnce(&mut HeadlessFrame),
+    {
+        let mut target = RenderingSurface::<CpuBackend, ()>::draw_begin(self, &()).unwrap();
+        draw(&mut target);
+        RenderingSurface::<CpuBackend, ()>::draw_end(self, target, &());
+
+        &self.front.image
+    }
+}
+
+impl<Ctx> RenderingSurface<CpuBackend, Ctx> for HeadlessSurface
+where
+    Ctx: RenderingContext<CpuBackend>,
+{
+    type Target = HeadlessFrame;
+    type Future = ();
+
+    fn draw_begin(&mut self, _: &Ctx) -> Option<Self::Target> {
+        let mut buf = self
+            .back
+            .take()
+            .unwrap_or_else(|| HeadlessFrame::new(self.front.width, self.front.height));
+
+        // opaque black, as a window is cleared to, unless it holds a frame
+        if buf.age.is_none() {
+            for pixel in buf.rgba_buffer.chunks_mut(4) {
+                pixel.copy_from_slice(&[0x00, 0x00, 0x00, 0xff]);
+            }
+        }
+
+        Some(buf)
+    }
+
+    fn draw_end(&mut self, mut target: Self::Target, _: &Ctx) {
+        // drawn into again after the next frame
+        target.age = Some(2);
+
+        self.back = Some(std::mem::replace(&mut self.front, target));
+        self.frame_count += 1;
+    }
+}
+
+#[test]
+fn test_headless_surface() {
+    let mut surface = HeadlessSurface::new(4, 2);
+
+    assert_eq!(surface.frame_count(), 0);
+    assert!(surface.rgba().iter().all(|&v| v == 0));
+
+    let image = surface.step(|target| {
+        target.rgba_buffer[0..4].copy_from_slice(&[0xff, 0x00, 0x00, 0xff]);
+    });
+
+    assert_eq!(&image.rgba_buffer[0..8], &[0xff, 0, 0, 0xff, 0, 0, 0, 0xff]);
+
+    // the next frame starts from a cleared buffer
+    surface.step(|target| assert_eq!(target.age(), None));
+
+    assert_eq!(surface.frame_count(), 2);
+    assert_eq!(surface.rgba().len(), 4 * 2 * 4);
+    assert!(surface.rgba().chunks(4).all(|p| p == [0, 0, 0, 0xff]));
+
+    // then from the one before the last, as it was drawn
+    let image = surface.step(|target| assert_eq!(target.age(), Some(2)));
+
+    assert_eq!(&image.rgba_buffer[0..4], &[0xff, 0, 0, 0xff]);
+}
@@ -1,4 +1,4 @@
-use super::utils;
+use super::utils::{self, BlendSource};
 
 #[derive(Clone)]
 pub struct Image {
@@ -124,14 +124,29 @@ impl<'a> Deref for ImageSliceMut<'a> {
     }
 }
 
+/// Multiplies the colors of straight-alpha RGBA pixels by their alpha.
+pub fn premultiply(rgba_buffer: &mut [u8]) {
+    for p in rgba_buffer.chunks_exact_mut(4) {
+        let a = p[3] as u32;
+
+        for c in &mut p[..3] {
+            *c = ((*c as u32 * a + 127) / 255) as u8;
+        }
+    }
+}
+
 use s25::S25Image;
 
+/// S25 images are straight-alpha and sRGB-encoded; their colors are
+/// premultiplied here, as they are stored in the compositor, and kept
+/// sRGB-encoded.
 impl From<S25Image> for Image {
     fn from(image: S25Image) -> Self {
         let width = image.metadata.width as usize;
         let height = image.metadata.height as usize;
 
-        let rgba_buffer = image.rgba_buffer().iter().copied().collect();
+        let mut rgba_buffer = image.rgba_buffer().to_vec();
+        premultiply(&mut rgba_buffer);
 
         Self {
             width,
@@ -150,6 +165,14 @@ impl Image {
         }
     }
 
+    pub fn as_slice(&self) -> ImageSlice {
+        ImageSlice {
+            width: self.width,
+            height: self.height,
+            rgba_buffer: &self.rgba_buffer,
+        }
+    }
+
     pub fn clear(&mut self) {
         crate::utils::memset(&mut self.rgba_buffer, 0x00);
     }
@@ -176,6 +199,59 @@ impl Image {
             rgba_buffer: &mut self.rgba_buffer,
         };
 
-        utils::alpha_blend(&src_img, &mut dest_img, (x, y), 1.0);
+        utils::composite(&[BlendSource::new(src_img, (x, y), 1.0)], &mut dest_img);
+    }
+}
+
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+impl Image {
+    /// Loads an 8-bit RGB or RGBA PNG, with the pixels as they are stored.
+    pub fn load_png<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        let file = std::fs::File::open(path)?;
+        let (info, mut reader) = png::Decoder::new(file)
+            .read_info()
+            .map_err(|err| Error::new(ErrorKind::Other, err))?;
+
+        let mut buf = vec![0; info.buffer_size()];
+        reader
+            .next_frame(&mut buf)
+            .map_err(|err| Error::new(ErrorKind::Other, err))?;
+
+        let rgba_buffer = match (info.color_type, info.bit_depth) {
+            (png::ColorType::RGBA, png::BitDepth::Eight) => buf,
+            (png::ColorType::RGB, png::BitDepth::Eight) => buf
+                .chunks(3)
+                .flat_map(|p| vec![p[0], p[1], p[2], 0xff])
+                .collect(),
+            (color, depth) => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("unsupported PNG format: {:?}, {:?}", color, depth),
+                ))
+            }
+        };
+
+        Ok(Self {
+            width: info.width as usize,
+            height: info.height as usize,
+            rgba_buffer,
+        })
+    }
+
+    /// Saves the image as an RGBA PNG, with the pixels as they are; frames
+    /// are opaque, so that they are the same premultiplied or not.
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+
+        let mut encoder = png::Encoder::new(file, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::RGBA);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        encoder
+            .write_header()
+            .and_then(|mut writer| writer.write_image_data(&self.rgba_buffer))
+            .map_err(|err| Error::new(ErrorKind::Other, err))
     }
 }
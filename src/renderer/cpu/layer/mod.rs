@@ -1,15 +1,20 @@
 use crate::format::s25::S25Archive;
 use crate::renderer::Renderer;
 
+use crate::renderer::cpu::damage::{Damage, Rect};
 use crate::renderer::cpu::image::Image;
-use crate::renderer::cpu::{CpuBackend, CpuImageBuffer};
+use crate::renderer::cpu::utils::blur::{self, BlurKernel};
+use crate::renderer::cpu::utils::{self, BlendSource};
+use crate::renderer::cpu::{CpuBackend, CpuRenderingTarget};
 
 use std::path::Path;
 use std::sync::Arc;
+use std::time::Instant;
 
 use lru::LruCache;
 
 use crate::constants::{GAME_WINDOW_HEIGHT, GAME_WINDOW_WIDTH, LRU_CACHE_CAPACITY};
+use crate::model::layer::LayerModel;
 
 #[derive(Clone)]
 pub struct PictLayer {
@@ -25,9 +30,20 @@ pub struct LayerRenderer {
     pub framebuffer: Image,
     pub offset: (i32, i32),
     pub opacity: f32,
-    pub blur: Option<(usize, usize)>,
-    //
-    update_flag: bool,
+    pub blend_mode: BlendMode,
+    pub blur: Option<(f64, f64)>,
+    pub blur_kernel: BlurKernel,
+    // the framebuffer blurred, kept until the layer or the radius changes
+    blurred: Option<Image>,
+    // property state with delays and animations
+    pub model: LayerModel,
+    // directory the archives are looked up in; the configured root if unset
+    pub root: Option<PathBuf>,
+    // regions of the framebuffer to draw the entries into again
+    redraw: Damage,
+    // regions of the screen the layer changed since the damage was taken
+    damage: Damage,
+    blur_flag: bool,
 }
 
 impl LayerRenderer {
@@ -39,9 +55,16 @@ impl LayerRenderer {
             cache: LruCache::new(LRU_CACHE_CAPACITY),
             framebuffer: Image::new(GAME_WINDOW_WIDTH as usize, GAME_WINDOW_HEIGHT as usize),
             opacity: 1.0,
-            update_flag: false,
+            blend_mode: BlendMode::Normal,
+            redraw: Damage::new(),
+            damage: Damage::new(),
+            blur_flag: false,
             blur: None,
+            blur_kernel: BlurKernel::Gaussian,
+            blurred: None,
             offset: (0, 0),
+            model: LayerModel::new(0),
+            root: None,
         }
     }
 
@@ -66,8 +89,8 @@ impl LayerRenderer {
         Some(image)
     }
 
-    fn open_s25(filename: &str) -> Option<S25Archive> {
-        S25Archive::open(Self::lookup(filename.split('\\').last().unwrap())).ok()
+    fn open_s25(&self, filename: &str) -> Option<S25Archive> {
+        S25Archive::open(self.lookup(filename.split('\\').last().unwrap())).ok()
     }
 
     fn prefetch_entry(&mut self, filename: &str, entry: i32) -> Option<()> {
@@ -81,7 +104,7 @@ impl LayerRenderer {
             .map(|v| v == filename)
             .unwrap_or_default()
         {
-            let mut s25 = Self::open_s25(filename)?;
+            let mut s25 = self.open_s25(filename)?;
             s25.load_image(entry as usize)
         } else {
             self.s25.as_mut()?.load_image(entry as usize)
@@ -100,8 +123,10 @@ impl LayerRenderer {
     }
 
     pub fn load(&mut self, filename: &str, entries: &[i32]) {
+        self.invalidate();
+
         self.filename = Some(filename.into());
-        self.s25 = Self::open_s25(filename);
+        self.s25 = self.open_s25(filename);
 
         self.entries = entries
             .iter()
@@ -109,14 +134,16 @@ impl LayerRenderer {
             .filter_map(|e| self.load_entry(e))
             .collect();
 
-        self.update_flag = true;
+        self.invalidate();
     }
 
     pub fn unload(&mut self) {
+        self.invalidate();
+
         self.filename = None;
         self.s25 = None;
         self.entries = vec![];
-        self.update_flag = true;
+        self.invalidate();
     }
 
     pub fn prefetch(&mut self, filename: &str, entries: &[i32]) {
@@ -125,134 +152,189 @@ impl LayerRenderer {
         }
     }
 
+    // called before and after the entries or their position change, so that
+    // both what they covered and what they cover now are drawn again
+    fn invalidate(&mut self) {
+        let area = self.entry_bounds();
+        self.redraw.add(area);
+        self.damage.add(self.bounds());
+    }
+
     pub fn set_position(&mut self, x: i32, y: i32) {
+        self.invalidate();
         self.offset = (x, y);
-        self.update_flag = true;
+        self.invalidate();
     }
 
-    pub fn set_blur_rate(&mut self, rx: i32, ry: i32) {
-        self.blur = Some((rx as usize, ry as usize));
-        self.update_flag = true;
+    pub fn set_blur_rate(&mut self, rx: f64, ry: f64) {
+        self.damage.add(self.bounds());
+        self.blur = if 0.0 < rx || 0.0 < ry {
+            Some((rx.max(0.0), ry.max(0.0)))
+        } else {
+            None
+        };
+        self.damage.add(self.bounds());
+        self.blur_flag = true;
     }
 
     pub fn set_opacity(&mut self, opacity: f32) {
-        self.opacity = opacity;
-        self.update_flag = true;
+        if self.opacity != opacity {
+            self.opacity = opacity;
+            self.damage.add(self.bounds());
+        }
     }
 
-    pub fn update(&mut self) {
-        if !self.update_flag {
-            return;
-        }
+    fn framebuffer_rect(&self) -> Rect {
+        Rect::new(0, 0, self.framebuffer.width, self.framebuffer.height)
+    }
 
-        log::debug!("update");
+    // the area the entries cover in the framebuffer
+    fn entry_bounds(&self) -> Rect {
+        let bounds = self.entries.iter().fold(Rect::empty(), |bounds, e| {
+            bounds.union(&Rect::new(
+                (self.offset.0 + e.offset.0) as isize,
+                (self.offset.1 + e.offset.1) as isize,
+                e.image.width,
+                e.image.height,
+            ))
+        });
 
-        self.update_flag = false;
-        self.framebuffer.clear();
+        bounds.intersection(&self.framebuffer_rect())
+    }
 
-        for e in &self.entries {
-            self.framebuffer.draw_image(
-                &e.image,
-                (
-                    (self.offset.0 + e.offset.0) as isize,
-                    (self.offset.1 + e.offset.1) as isize,
-                ),
-            );
-        }
+    /// The area of the screen the layer is visible in, blur included.
+    pub fn bounds(&self) -> Rect {
+        let (rx, ry) = self.blur.unwrap_or((0.0, 0.0));
 
-        self.apply_blur();
+        self.entry_bounds()
+            .expand(rx.ceil() as isize, ry.ceil() as isize)
+            .intersection(&self.framebuffer_rect())
     }
 
-    fn apply_blur(&mut self) {
-        use crate::renderer::cpu::image::{ImageView, ImageViewMut};
+    /// Takes the regions of the screen the layer changed since the last call.
+    pub fn take_damage(&mut self) -> Damage {
+        self.damage.take()
+    }
 
-        if let Some((rx, ry)) = self.blur {
-            self.blur = None;
+    fn poll_model(&mut self, now: Instant) {
+        let origin = self.model.origin;
+        let opacity = self.model.opacity;
+        let blur_radius = self.model.blur_radius;
 
-            let (rx, ry) = (rx as isize, ry as isize);
+        self.model.poll(now);
 
-            // x blur
-            for y in 0..self.framebuffer.height {
-                for x in 0..self.framebuffer.width {
-                    let mut weight = 0;
-                    let mut color: [f32; 4] = [0f32; 4];
+        if origin != self.model.origin {
+            let (x, y) = self.model.origin;
+            self.set_position(x as i32, y as i32);
+        }
 
-                    for i in -rx..=rx {
-                        weight += 1;
+        if (opacity - self.model.opacity).abs() > std::f32::EPSILON {
+            self.set_opacity(self.model.opacity);
+        }
 
-                        if let Some(c) = self.framebuffer.get((x as isize + i) as usize, y) {
-                            color[0] += c[0] as f32 / 255.0;
-                            color[1] += c[1] as f32 / 255.0;
-                            color[2] += c[2] as f32 / 255.0;
-                            color[3] += c[3] as f32 / 255.0;
-                        }
-                    }
+        if blur_radius != self.model.blur_radius {
+            let (rx, ry) = self.model.blur_radius;
+            self.set_blur_rate(rx, ry);
+        }
 
-                    let weight = 255.0 / weight as f32;
+        // read as the layer is composited; no need to redraw the framebuffer
+        if self.blend_mode != self.model.blend_mode {
+            self.blend_mode = self.model.blend_mode;
+            self.damage.add(self.bounds());
+        }
+    }
 
-                    if let Some(target) = self.framebuffer.get_mut(x, y) {
-                        target[0] = (color[0] * weight) as u8;
-                        target[1] = (color[1] * weight) as u8;
-                        target[2] = (color[2] * weight) as u8;
-                        target[3] = (color[3] * weight) as u8;
-                    }
-                }
+    pub fn update(&mut self) {
+        self.update_at(Instant::now());
+    }
+
+    /// Updates the layer as of `now`, which drives its delays and animations.
+    pub fn update_at(&mut self, now: Instant) {
+        self.poll_model(now);
+
+        let redraw = self.redraw.take();
+
+        if !redraw.is_empty() {
+            log::debug!("update: {:?}", redraw.rects());
+
+            self.blur_flag = true;
+
+            let offset = self.offset;
+            let stack: Vec<_> = self
+                .entries
+                .iter()
+                .map(|e| {
+                    BlendSource::new(
+                        e.image.as_slice(),
+                        (
+                            (offset.0 + e.offset.0) as isize,
+                            (offset.1 + e.offset.1) as isize,
+                        ),
+                        1.0,
+                    )
+                })
+                .collect();
+
+            let mut framebuffer = self.framebuffer.framebuffer();
+
+            for &rect in redraw.rects() {
+                utils::fill_rect(&mut framebuffer, rect, [0x00; 4]);
+                utils::composite_rect(&stack, &mut framebuffer, rect);
             }
+        }
 
-            // y blur
-            for y in 0..self.framebuffer.height {
-                for x in 0..self.framebuffer.width {
-                    let mut weight = 0;
-                    let mut color: [f32; 4] = [0f32; 4];
+        if self.blur_flag {
+            self.blur_flag = false;
+            self.apply_blur();
+        }
+    }
 
-                    for i in -ry..=ry {
-                        weight += 1;
+    /// The image of the layer as it is composited; `None` if it is empty.
+    pub fn blend_source(&self) -> Option<BlendSource> {
+        if self.entries.is_empty() {
+            return None;
+        }
 
-                        if let Some(c) = self.framebuffer.get(x, (y as isize + i) as usize) {
-                            color[0] += c[0] as f32 / 255.0;
-                            color[1] += c[1] as f32 / 255.0;
-                            color[2] += c[2] as f32 / 255.0;
-                            color[3] += c[3] as f32 / 255.0;
-                        }
-                    }
+        let image = self.blurred.as_ref().unwrap_or(&self.framebuffer);
 
-                    let weight = 255.0 / weight as f32;
+        Some(BlendSource::new(image.as_slice(), (0, 0), self.opacity).with_mode(self.blend_mode))
+    }
 
-                    if let Some(target) = self.framebuffer.get_mut(x, y) {
-                        target[0] = (color[0] * weight) as u8;
-                        target[1] = (color[1] * weight) as u8;
-                        target[2] = (color[2] * weight) as u8;
-                        target[3] = (color[3] * weight) as u8;
-                    }
-                }
+    fn apply_blur(&mut self) {
+        let radius = match self.blur {
+            Some(radius) => radius,
+            None => {
+                self.blurred = None;
+                return;
             }
-        }
+        };
+
+        let blurred = self.blurred.get_or_insert_with(|| Image::new(0, 0));
+
+        blur::blur(&self.framebuffer, blurred, radius, self.blur_kernel);
     }
 }
 
-impl Renderer<CpuBackend, CpuImageBuffer> for LayerRenderer {
+impl<T> Renderer<CpuBackend, T> for LayerRenderer
+where
+    T: CpuRenderingTarget,
+{
     type Context = ();
 
-    fn render(&mut self, target: &mut CpuImageBuffer, _: &Self::Context) {
-        if self.entries.is_empty() {
-            return;
+    fn render(&mut self, target: &mut T, _: &Self::Context) {
+        if let Some(source) = self.blend_source() {
+            target.composite(&[source]);
         }
-
-        target.draw_image(
-            &self.framebuffer.rgba_buffer,
-            (0, 0),
-            (
-                self.framebuffer.width as i32,
-                self.framebuffer.height as i32,
-            ),
-            self.opacity,
-        );
     }
 }
 
 // command receiver
 
-use crate::script::mil::command::LayerCommand;
+use crate::model::layer::animation::AnimationType;
+use crate::model::layer::LayerCommand as ModelCommand;
+use crate::script::mil::command::{BlendMode, LayerCommand};
+use crate::utils::easing::Easing;
+use std::time::Duration;
 
 impl LayerRenderer {
     pub fn send(&mut self, command: LayerCommand) {
@@ -283,15 +365,29 @@ impl LayerRenderer {
             }
             LayerCommand::SetPosition(x, y) => {
                 log::debug!("position: {}, {}", x, y);
-                self.set_position(x as i32, y as i32);
+                self.model.send(ModelCommand::LayerMoveTo(x, y));
             }
             LayerCommand::SetOpacity(opacity) => {
                 log::debug!("opacity: {}", opacity);
-                self.set_opacity(opacity as f32);
+                self.model.send(ModelCommand::LayerOpacity(opacity as f32));
             }
             LayerCommand::SetBlurRate(rx, ry) => {
                 log::debug!("blur rate: ({}, {})", rx, ry);
-                self.set_blur_rate(rx, ry);
+                self.model
+                    .send(ModelCommand::LayerBlur(rx as f64, ry as f64));
+            }
+            LayerCommand::AnimateBlurRate(rx, ry, duration) => {
+                log::debug!("blur rate animation: ({}, {}), {}", rx, ry, duration);
+                self.model.send(ModelCommand::LayerAnimate {
+                    duration: Duration::from_secs_f64(duration.max(0.0) / 1000.0),
+                    to: AnimationType::Blur(rx as f64, ry as f64),
+                    easing: Easing::Linear,
+                    then: vec![],
+                });
+            }
+            LayerCommand::SetBlendMode(mode) => {
+                log::debug!("blend mode: {}", mode.name());
+                self.model.send(ModelCommand::LayerBlendMode(mode));
             }
             LayerCommand::LoadOverlay(path, entry, mode) => {
                 log::debug!("overlay: {}, {}, {}", path, entry, mode);
@@ -314,12 +410,12 @@ impl LayerRenderer {
                 log::error!("anim not supported");
             }
             LayerCommand::FinalizeAnimation => {
-                log::debug!("finalize anim");
-                log::error!("anim not supported");
+                self.model.finalize();
             }
             LayerCommand::LayerDelay(v) => {
                 log::debug!("layer delay: {}", v);
-                log::error!("anim not supported");
+                let delay = Duration::from_secs_f64(v / 1000.0);
+                self.model.send(ModelCommand::LayerDelay(delay));
             }
         }
     }
@@ -366,8 +462,12 @@ impl LayerRenderer {
         None
     }
 
-    fn lookup(filename: &str) -> PathBuf {
-        // TODO
-        Self::lookup_into(&filename.to_ascii_uppercase(), "./blob/".as_ref()).unwrap()
+    fn lookup(&self, filename: &str) -> PathBuf {
+        let root = match &self.root {
+            Some(root) => root.as_path(),
+            None => crate::config::get_root_path().as_ref(),
+        };
+
+        Self::lookup_into(&filename.to_ascii_uppercase(), root).unwrap()
     }
 }
@@ -1,12 +1,16 @@
+pub mod damage;
 pub mod delegate;
+pub mod headless;
 pub mod image;
 pub mod layer;
 pub mod utils;
 
+use crate::constants::{GAME_WINDOW_HEIGHT, GAME_WINDOW_WIDTH};
 use crate::renderer::vulkano::surface::VulkanoSurface;
 use crate::renderer::*;
 
 use winit::event_loop::EventLoop;
+use winit::window::Window;
 
 use std::collections::VecDeque;
 
@@ -14,7 +18,33 @@ pub struct CpuBackend;
 
 impl GraphicBackend for CpuBackend {}
 
+/// Rendering target of the CPU backend, i.e. an RGBA framebuffer.
+pub trait CpuRenderingTarget {
+    fn framebuffer(&mut self) -> ImageSliceMut;
+
+    /// Frames since the framebuffer was last drawn into, if it still holds
+    /// that frame; `None` if it has to be drawn entirely.
+    fn age(&self) -> Option<usize> {
+        None
+    }
+
+    /// Alpha-blends `image` at `(x, y)`.
+    fn blend(&mut self, image: &Image, (x, y): (i32, i32), opacity: f32) {
+        let source = BlendSource::new(image.as_slice(), (x as isize, y as isize), opacity);
+        self.composite(&[source]);
+    }
+
+    /// Alpha-blends the images of `stack` from the bottom up, in one pass.
+    fn composite(&mut self, stack: &[BlendSource]) {
+        utils::composite(stack, &mut self.framebuffer());
+    }
+}
+
+impl<T> RenderingTarget<CpuBackend> for T where T: CpuRenderingTarget {}
+
 use delegate::{CpuDelegate, CpuImageBuffer};
+use image::{Image, ImageSliceMut};
+use utils::BlendSource;
 
 pub struct CpuSurface {
     framebuffers: VecDeque<CpuImageBuffer>,
@@ -26,7 +56,10 @@ impl CpuSurface {
         let delegate = CpuDelegate::new(event_loop);
 
         let mut framebuffers = VecDeque::new();
-        framebuffers.resize_with(2, || delegate.create_framebuffer(1600, 900));
+        // at the resolution of the game, whatever the size of the window
+        framebuffers.resize_with(2, || {
+            CpuImageBuffer::new(GAME_WINDOW_WIDTH as usize, GAME_WINDOW_HEIGHT as usize)
+        });
 
         CpuSurface {
             framebuffers,
@@ -34,8 +67,12 @@ impl CpuSurface {
         }
     }
 
+    pub fn window(&self) -> &Window {
+        self.delegate.surface.surface.window()
+    }
+
     pub fn request_redraw(&mut self) {
-        self.delegate.surface.surface.window().request_redraw();
+        self.window().request_redraw();
     }
 }
 
@@ -60,19 +97,52 @@ where
             .pop_front()
             .expect("failed to obtain framebuffer");
 
-        for v in &mut buf.rgba_buffer {
-            *v = 0x00;
+        // kept as it is if it holds a frame, which is redrawn where damaged;
+        // otherwise opaque black, as the window is cleared to
+        if buf.age.is_none() {
+            for pixel in buf.rgba_buffer.chunks_mut(4) {
+                pixel.copy_from_slice(&[0x00, 0x00, 0x00, 0xff]);
+            }
+
+            buf.modified = true;
         }
 
         Some(buf)
     }
 
-    fn draw_end(&mut self, target: Self::Target, _: &Ctx) {
-        self.delegate.draw(&target);
+    fn draw_end(&mut self, mut target: Self::Target, _: &Ctx) {
+        self.delegate.draw(&mut target);
+
+        // drawn into again once the other framebuffers are
+        target.age = Some(self.framebuffers.len() + 1);
         self.framebuffers.push_back(target);
     }
 }
 
-impl RenderingTarget<CpuBackend> for CpuImageBuffer {}
+impl CpuRenderingTarget for CpuImageBuffer {
+    fn framebuffer(&mut self) -> ImageSliceMut {
+        self.modified = true;
+
+        ImageSliceMut {
+            width: self.width,
+            height: self.height,
+            rgba_buffer: &mut self.rgba_buffer,
+        }
+    }
+
+    fn age(&self) -> Option<usize> {
+        self.age
+    }
+}
+
+impl CpuRenderingTarget for Image {
+    fn framebuffer(&mut self) -> ImageSliceMut {
+        ImageSliceMut {
+            width: self.width,
+            height: self.height,
+            rgba_buffer: &mut self.rgba_buffer,
+        }
+    }
+}
 
 impl RenderingContext<CpuBackend> for () {}
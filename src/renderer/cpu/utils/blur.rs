@@ -0,0 +1,212 @@
+//! Separable blur.
+//!
+//! Each axis is blurred as rows, in parallel; the vertical pass runs on the
+//! transposed image so that it reads rows as well. Colors are premultiplied,
+//! so that transparent pixels do not darken the edges. Pixels outside the
+//! image are transparent.
+
+use rayon::prelude::*;
+
+use crate::renderer::cpu::image::Image;
+
+/// Weights of the blur.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BlurKernel {
+    /// Uniform over the radius, rounded to whole pixels.
+    Box,
+    /// Gaussian with a standard deviation of half the radius.
+    Gaussian,
+}
+
+// fixed-point precision of the Gaussian weights
+const WEIGHT_BITS: u32 = 16;
+
+/// Blurs `src` into `dest` by `(rx, ry)` pixels.
+pub fn blur(src: &Image, dest: &mut Image, (rx, ry): (f64, f64), kernel: BlurKernel) {
+    let (width, height) = (src.width, src.height);
+
+    dest.width = width;
+    dest.height = height;
+    dest.rgba_buffer.resize(src.rgba_buffer.len(), 0);
+
+    if width == 0 || height == 0 {
+        return;
+    }
+
+    let mut buf = src.rgba_buffer.clone();
+    let mut tmp = vec![0; buf.len()];
+
+    blur_rows(&buf, &mut tmp, width, rx, kernel);
+    transpose(&tmp, &mut buf, width, height);
+    blur_rows(&buf, &mut tmp, height, ry, kernel);
+    transpose(&tmp, &mut dest.rgba_buffer, height, width);
+}
+
+fn blur_rows(src: &[u8], dest: &mut [u8], width: usize, radius: f64, kernel: BlurKernel) {
+    let rows = src
+        .par_chunks(width * 4)
+        .zip(dest.par_chunks_mut(width * 4));
+
+    match kernel {
+        BlurKernel::Box => {
+            let radius = radius.max(0.0).round() as usize;
+            rows.for_each(|(src, dest)| box_row(src, dest, radius));
+        }
+        BlurKernel::Gaussian => {
+            let weights = gaussian_weights(radius);
+            rows.for_each(|(src, dest)| convolve_row(src, dest, &weights));
+        }
+    }
+}
+
+// box blur of a row, by running sums
+fn box_row(src: &[u8], dest: &mut [u8], radius: usize) {
+    let n = src.len() / 4;
+    let window = (radius * 2 + 1) as u32;
+    let mut sum = [0u32; 4];
+
+    let add = |sum: &mut [u32; 4], x: usize, sign: bool| {
+        for (c, s) in sum.iter_mut().enumerate() {
+            if sign {
+                *s += src[x * 4 + c] as u32;
+            } else {
+                *s -= src[x * 4 + c] as u32;
+            }
+        }
+    };
+
+    for x in 0..=radius.min(n - 1) {
+        add(&mut sum, x, true);
+    }
+
+    for x in 0..n {
+        for (c, s) in sum.iter().enumerate() {
+            dest[x * 4 + c] = ((s + window / 2) / window) as u8;
+        }
+
+        if x + radius + 1 < n {
+            add(&mut sum, x + radius + 1, true);
+        }
+
+        if x >= radius {
+            add(&mut sum, x - radius, false);
+        }
+    }
+}
+
+// weights from the center outwards, summing up to 1 over both sides
+fn gaussian_weights(radius: f64) -> Vec<u32> {
+    let taps = radius.max(0.0).ceil() as usize;
+
+    if taps == 0 {
+        return vec![1 << WEIGHT_BITS];
+    }
+
+    let sigma = radius / 2.0;
+    let weights: Vec<f64> = (0..=taps)
+        .map(|i| (-((i * i) as f64) / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let total = weights[0] + 2.0 * weights[1..].iter().sum::<f64>();
+
+    let mut weights: Vec<u32> = weights
+        .iter()
+        .map(|w| (w / total * (1 << WEIGHT_BITS) as f64).round() as u32)
+        .collect();
+
+    // rounding error goes to the center
+    let sum = weights[0] + 2 * weights[1..].iter().sum::<u32>();
+    weights[0] = (weights[0] + (1 << WEIGHT_BITS)).saturating_sub(sum);
+
+    weights
+}
+
+fn convolve_row(src: &[u8], dest: &mut [u8], weights: &[u32]) {
+    let n = src.len() / 4;
+    let taps = weights.len() - 1;
+
+    for x in 0..n {
+        let mut sum = [0u32; 4];
+
+        let lo = x.saturating_sub(taps);
+        let hi = (x + taps).min(n - 1);
+
+        for i in lo..=hi {
+            let w = weights[if i < x { x - i } else { i - x }];
+
+            for (c, s) in sum.iter_mut().enumerate() {
+                *s += src[i * 4 + c] as u32 * w;
+            }
+        }
+
+        for (c, s) in sum.iter().enumerate() {
+            dest[x * 4 + c] = ((s + (1 << (WEIGHT_BITS - 1))) >> WEIGHT_BITS).min(255) as u8;
+        }
+    }
+}
+
+// `src` is `width` pixels wide; `dest` becomes `height` pixels wide
+fn transpose(src: &[u8], dest: &mut [u8], width: usize, height: usize) {
+    dest.par_chunks_mut(height * 4)
+        .enumerate()
+        .for_each(|(x, column)| {
+            for (y, p) in column.chunks_mut(4).enumerate() {
+                p.copy_from_slice(&src[(x + y * width) * 4..][..4]);
+            }
+        });
+}
+
+#[test]
+fn test_box_blur() {
+    // a single opaque pixel in the middle of 5x3
+    let mut src = Image::new(5, 3);
+    src.rgba_buffer[(2 + 5) * 4..][..4].copy_from_slice(&[0xff, 0x00, 0x00, 0xff]);
+
+    let mut dest = Image::new(0, 0);
+    blur(&src, &mut dest, (1.0, 1.0), BlurKernel::Box);
+
+    assert_eq!((dest.width, dest.height), (5, 3));
+
+    // spread uniformly over 3x3, keeping its color
+    let alpha: Vec<_> = dest.rgba_buffer.chunks(4).map(|p| p[3]).collect();
+    assert_eq!(
+        alpha,
+        [0, 28, 28, 28, 0, 0, 28, 28, 28, 0, 0, 28, 28, 28, 0]
+    );
+    assert_eq!(&dest.rgba_buffer[4..8], &[28, 0x00, 0x00, 28]);
+
+    // nothing is kept between calls
+    let mut again = Image::new(0, 0);
+    blur(&src, &mut again, (1.0, 1.0), BlurKernel::Box);
+    assert_eq!(again.rgba_buffer, dest.rgba_buffer);
+}
+
+#[test]
+fn test_gaussian_blur() {
+    let weights = gaussian_weights(3.0);
+    assert_eq!(weights.len(), 4);
+    assert_eq!(
+        weights[0] + 2 * weights[1..].iter().sum::<u32>(),
+        1 << WEIGHT_BITS
+    );
+    assert!(weights.windows(2).all(|w| w[0] > w[1]));
+
+    // a flat opaque image stays flat away from the edges
+    let mut src = Image::new(16, 16);
+    for p in src.rgba_buffer.chunks_mut(4) {
+        p.copy_from_slice(&[0x40, 0x80, 0xc0, 0xff]);
+    }
+
+    let mut dest = Image::new(0, 0);
+    blur(&src, &mut dest, (3.0, 2.0), BlurKernel::Gaussian);
+
+    assert_eq!(
+        &dest.rgba_buffer[(8 + 8 * 16) * 4..][..4],
+        &[0x40, 0x80, 0xc0, 0xff]
+    );
+    // transparent beyond the edges, but not darkened
+    let corner = &dest.rgba_buffer[..4];
+    let straight = |c: u8| c as i32 * 255 / corner[3] as i32;
+    assert!(corner[3] < 0xff);
+    assert!((straight(corner[0]) - 0x40).abs() <= 2);
+    assert!((straight(corner[2]) - 0xc0).abs() <= 2);
+}
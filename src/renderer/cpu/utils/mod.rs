@@ -1,78 +1,225 @@
-use crate::renderer::cpu::image::{ImageView, ImageViewMut};
+//! Blending of premultiplied images.
+//!
+//! Every image is kept with its colors multiplied by alpha, as sRGB-encoded
+//! bytes; images from elsewhere are premultiplied as they are loaded, e.g.
+//! `Image::from(S25Image)`. Colors are blended as they are encoded unless
+//! linear blending is configured, in which case they are decoded to linear
+//! light, mixed, and encoded again, as the GPU does with sRGB formats.
+
+pub mod blur;
+pub mod scale;
+pub mod simd;
+
+use crate::config;
+use crate::renderer::cpu::damage::Rect;
+use crate::renderer::cpu::image::{ImageSlice, ImageSliceMut, ImageView, ImageViewMut};
+
+use crate::script::mil::command::BlendMode;
+
+use lazy_static::*;
+use rayon::prelude::*;
+
+use std::ops::Range;
+
+// opacity in 1/256ths
+fn fixed_opacity(opacity: f32) -> u32 {
+    (opacity.min(1.0).max(0.0) * 256.0) as u32
+}
+
+// the range of source pixels that fall on the destination, along an axis
+fn visible(src_len: usize, dest_len: usize, offset: isize) -> Range<usize> {
+    let start = (-offset).max(0) as usize;
+    let end = (dest_len as isize - offset).max(0) as usize;
+
+    start..src_len.min(end)
+}
+
+/// `x / 255`, rounded, for `x` up to `255 * 255`.
+#[inline]
+fn div255(x: u32) -> u32 {
+    let x = x + 128;
+    (x + (x >> 8)) >> 8
+}
+
+/// The premultiplied color of `tint` at the alpha of `src`.
+#[inline]
+fn tinted([r, g, b]: [u8; 3], src: &[u8]) -> [u8; 4] {
+    let a = src[3] as u32;
+
+    [
+        div255(r as u32 * a) as u8,
+        div255(g as u32 * a) as u8,
+        div255(b as u32 * a) as u8,
+        src[3],
+    ]
+}
+
+/// `src` scaled by `opacity`.
+#[inline]
+fn scaled(src: [u8; 4], opacity: u32) -> [u8; 4] {
+    let scale = |c: u8| ((c as u32 * opacity + 128) >> 8) as u8;
+    [scale(src[0]), scale(src[1]), scale(src[2]), scale(src[3])]
+}
+
+/// Blends a premultiplied pixel over `dest`, i.e. `src + dest * (1 - alpha)`.
+#[inline]
+fn blend_pixel(dest: &mut [u8], src: [u8; 4], opacity: u32) {
+    let src = scaled(src, opacity);
+    let rest = 255 - src[3] as u32;
+
+    for (d, s) in dest.iter_mut().zip(&src) {
+        *d = (*s as u32 + div255(*d as u32 * rest)).min(255) as u8;
+    }
+}
+
+/// Space colors are mixed in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BlendSpace {
+    /// As sRGB-encoded; fast, and how images are usually blended.
+    Gamma,
+    /// Linear light; closer to how light adds up, so that edges and
+    /// translucent colors do not darken. Colors of nearly transparent pixels
+    /// lose some precision, as they are stored premultiplied and encoded.
+    Linear,
+}
+
+impl BlendSpace {
+    /// The space set by `renderer.linearBlending`.
+    pub fn configured() -> Self {
+        if config::is_linear_blending_enabled() {
+            BlendSpace::Linear
+        } else {
+            BlendSpace::Gamma
+        }
+    }
+
+    // a channel of a premultiplied pixel, as a straight color in the space
+    fn decode(self, c: u8, a: u8) -> f32 {
+        let c = (c as f32 / a as f32).min(1.0);
+
+        match self {
+            BlendSpace::Gamma => c,
+            BlendSpace::Linear => lookup(&SRGB_TO_LINEAR, c),
+        }
+    }
+
+    // a straight color in the space, as a channel at alpha `a` in 0..=1
+    fn encode(self, c: f32, a: f32) -> u8 {
+        let c = c.min(1.0).max(0.0);
+
+        let c = match self {
+            BlendSpace::Gamma => c,
+            BlendSpace::Linear => lookup(&LINEAR_TO_SRGB, c),
+        };
+
+        (c * a * 255.0).round() as u8
+    }
+}
+
+// intervals the transfer functions are sampled at over 0..=1
+const TRANSFER_STEPS: usize = 4096;
+
+lazy_static! {
+    // sampled once, as `powf` for every channel of every pixel is slow
+    static ref SRGB_TO_LINEAR: Vec<f32> = transfer_table(srgb_to_linear);
+    static ref LINEAR_TO_SRGB: Vec<f32> = transfer_table(linear_to_srgb);
+}
+
+fn transfer_table(f: fn(f32) -> f32) -> Vec<f32> {
+    (0..=TRANSFER_STEPS)
+        .map(|i| f(i as f32 / TRANSFER_STEPS as f32))
+        .collect()
+}
+
+// `table` at `c` in 0..=1, interpolated between the samples
+fn lookup(table: &[f32], c: f32) -> f32 {
+    let x = c * TRANSFER_STEPS as f32;
+    let i = (x as usize).min(TRANSFER_STEPS - 1);
+
+    table[i] + (table[i + 1] - table[i]) * (x - i as f32)
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.003_130_8 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+// a channel of the source color mixed with the destination by `mode`, as
+// if both were opaque
+fn mix(mode: BlendMode, cb: f32, cs: f32) -> f32 {
+    match mode {
+        BlendMode::Normal => cs,
+        BlendMode::Additive => (cb + cs).min(1.0),
+        BlendMode::Multiply => cb * cs,
+        BlendMode::Screen => cb + cs - cb * cs,
+        BlendMode::Overlay => {
+            if cb < 0.5 {
+                2.0 * cb * cs
+            } else {
+                1.0 - 2.0 * (1.0 - cb) * (1.0 - cs)
+            }
+        }
+        BlendMode::Subtract => (cb - cs).max(0.0),
+    }
+}
+
+/// Blends a premultiplied pixel over `dest` by `mode`, mixing colors in
+/// `space`.
+///
+/// The source color is mixed with the destination as much as the latter is
+/// opaque, and the result is composited over it as in normal blending.
+fn blend_pixel_in(dest: &mut [u8], src: [u8; 4], opacity: u32, mode: BlendMode, space: BlendSpace) {
+    let src = scaled(src, opacity);
+
+    // nothing to mix with
+    if src[3] == 0 {
+        return;
+    } else if dest[3] == 0 || (src[3] == 255 && mode == BlendMode::Normal) {
+        dest.copy_from_slice(&src);
+        return;
+    }
+
+    let sa = src[3] as f32 / 255.0;
+    let da = dest[3] as f32 / 255.0;
+    let oa = sa + da - sa * da;
+
+    for c in 0..3 {
+        let cs = space.decode(src[c], src[3]);
+        let cb = space.decode(dest[c], dest[3]);
+
+        let co = cs * sa * (1.0 - da) + cb * da * (1.0 - sa) + mix(mode, cb, cs) * sa * da;
+        dest[c] = space.encode(co / oa, oa);
+    }
+
+    dest[3] = (oa * 255.0).round() as u8;
+}
 
 pub fn alpha_blend<I1, I2>(src: &I1, dest: &mut I2, (x, y): (isize, isize), opacity: f32)
 where
     I1: ImageView,
     I2: ImageViewMut,
 {
-    let opacity = (opacity.min(1.0).max(0.0) * 256.0) as u32;
+    let opacity = fixed_opacity(opacity);
 
-    for dy in ((-y).max(0) as usize)
-        ..src
-            .get_height()
-            .min((-y + dest.get_height() as isize) as usize)
-    {
+    for dy in visible(src.get_height(), dest.get_height(), y) {
         let py = (dy as isize + y) as usize;
 
-        for dx in ((-x).max(0) as usize)
-            ..src
-                .get_width()
-                .min((-x + dest.get_width() as isize) as usize)
-        {
+        for dx in visible(src.get_width(), dest.get_width(), x) {
             let px = (dx as isize + x) as usize;
 
-            if let (Some([dr, dg, db, da]), Some([sr, sg, sb, sa])) =
-                (dest.get_mut(px, py), src.get(dx, dy))
-            {
-                if *sa == 0 {
-                    continue;
-                } else if *sa == 255 && opacity == 255 {
-                    *dr = *sr;
-                    *dg = *sg;
-                    *db = *sb;
-                    *da = 255;
-                    continue;
-                } else if *da == 0 {
-                    *dr = *sr;
-                    *dg = *sg;
-                    *db = *sb;
-                    *da = *sa;
-                    continue;
-                }
-
-                let rsrc = *sr as u32;
-                let gsrc = *sg as u32;
-                let bsrc = *sb as u32;
-                let asrc = (*sa as u32 * opacity) >> 8;
-
-                if asrc == 255 {
-                    *dr = rsrc as u8;
-                    *dg = gsrc as u8;
-                    *db = bsrc as u8;
-                    *da = 255;
-                    continue;
-                }
-
-                let rdst = *dr as u32;
-                let gdst = *dg as u32;
-                let bdst = *db as u32;
-                let adst = *da as u32;
-
-                let oa = (255 * asrc + adst * (255 - asrc)) >> 8;
-                if oa == 0 {
-                    *da = 0;
-                    continue;
-                }
-
-                let or = ((rsrc * asrc) * 255 + rdst * adst * (255 - asrc)) / oa;
-                let og = ((gsrc * asrc) * 255 + gdst * adst * (255 - asrc)) / oa;
-                let ob = ((bsrc * asrc) * 255 + bdst * adst * (255 - asrc)) / oa;
-
-                *dr = (or >> 8) as u8;
-                *dg = (og >> 8) as u8;
-                *db = (ob >> 8) as u8;
-
-                *da = oa as u8;
+            if let (Some(d), Some(s)) = (dest.get_mut(px, py), src.get(dx, dy)) {
+                blend_pixel(d, [s[0], s[1], s[2], s[3]], opacity);
             }
         }
     }
@@ -88,226 +235,417 @@ pub fn alpha_blend_colored<I1, I2>(
     I1: ImageView,
     I2: ImageViewMut,
 {
-    let opacity = (opacity.min(1.0).max(0.0) * 256.0) as u32;
+    let opacity = fixed_opacity(opacity);
 
-    for dy in ((-y).max(0) as usize)
-        ..src
-            .get_height()
-            .min((-y + dest.get_height() as isize) as usize)
-    {
+    for dy in visible(src.get_height(), dest.get_height(), y) {
         let py = (dy as isize + y) as usize;
 
-        for dx in ((-x).max(0) as usize)
-            ..src
-                .get_width()
-                .min((-x + dest.get_width() as isize) as usize)
-        {
+        for dx in visible(src.get_width(), dest.get_width(), x) {
             let px = (dx as isize + x) as usize;
 
-            if let (Some([dr, dg, db, da]), Some([_, _, _, sa])) =
-                (dest.get_mut(px, py), src.get(dx, dy))
-            {
-                if *sa == 0 {
-                    continue;
-                } else if *sa == 255 && opacity == 255 {
-                    *dr = tint[0];
-                    *dg = tint[1];
-                    *db = tint[2];
-                    *da = 255;
-                    continue;
-                } else if *da == 0 {
-                    *dr = tint[0];
-                    *dg = tint[1];
-                    *db = tint[2];
-                    *da = *sa;
-                    continue;
-                }
+            if let (Some(d), Some(s)) = (dest.get_mut(px, py), src.get(dx, dy)) {
+                blend_pixel(d, tinted(tint, s), opacity);
+            }
+        }
+    }
+}
 
-                let rsrc = tint[0] as u32;
-                let gsrc = tint[1] as u32;
-                let bsrc = tint[2] as u32;
-                let asrc = (*sa as u32 * opacity) >> 8;
-
-                if asrc == 255 {
-                    *dr = rsrc as u8;
-                    *dg = gsrc as u8;
-                    *db = bsrc as u8;
-                    *da = 255;
-                    continue;
-                }
+/// An image to composite, at an offset and an opacity.
+#[derive(Clone, Copy)]
+pub struct BlendSource<'a> {
+    pub image: ImageSlice<'a>,
+    pub offset: (isize, isize),
+    pub opacity: f32,
+    /// Color to draw instead of that of the image, keeping its alpha.
+    pub tint: Option<[u8; 3]>,
+    pub mode: BlendMode,
+}
 
-                let rdst = *dr as u32;
-                let gdst = *dg as u32;
-                let bdst = *db as u32;
-                let adst = *da as u32;
+impl<'a> BlendSource<'a> {
+    pub fn new(image: ImageSlice<'a>, offset: (isize, isize), opacity: f32) -> Self {
+        Self {
+            image,
+            offset,
+            opacity,
+            tint: None,
+            mode: BlendMode::Normal,
+        }
+    }
 
-                let oa = (255 * asrc + adst * (255 - asrc)) >> 8;
-                if oa == 0 {
-                    *da = 0;
-                    continue;
-                }
+    pub fn tinted(self, tint: [u8; 3]) -> Self {
+        Self {
+            tint: Some(tint),
+            ..self
+        }
+    }
 
-                let or = ((rsrc * asrc) * 255 + rdst * adst * (255 - asrc)) / oa;
-                let og = ((gsrc * asrc) * 255 + gdst * adst * (255 - asrc)) / oa;
-                let ob = ((bsrc * asrc) * 255 + bdst * adst * (255 - asrc)) / oa;
+    pub fn with_mode(self, mode: BlendMode) -> Self {
+        Self { mode, ..self }
+    }
 
-                *dr = (or >> 8) as u8;
-                *dg = (og >> 8) as u8;
-                *db = (ob >> 8) as u8;
+    // blends the source over `row`, the pixels of the scanline `py` of the
+    // destination from the column `left`
+    fn blend_scanline(&self, row: &mut [u8], (left, py): (usize, usize), space: BlendSpace) {
+        let (x, y) = self.offset;
+        let x = x - left as isize;
+        let image = &self.image;
 
-                *da = oa as u8;
-            }
+        let dy = py as isize - y;
+        if dy < 0 || image.height as isize <= dy {
+            return;
+        }
+
+        let dx = visible(image.width, row.len() / 4, x);
+        if dx.end <= dx.start {
+            return;
+        }
+
+        let offset = dy as usize * image.width;
+        let src = &image.rgba_buffer[(offset + dx.start) * 4..(offset + dx.end) * 4];
+
+        let px = (dx.start as isize + x) as usize;
+        let dest = &mut row[px * 4..(px + dx.len()) * 4];
+
+        let opacity = fixed_opacity(self.opacity);
+
+        if self.mode == BlendMode::Normal && space == BlendSpace::Gamma {
+            simd::blend_scanline(src, dest, opacity, self.tint);
+            return;
+        }
+
+        for (d, s) in dest.chunks_exact_mut(4).zip(src.chunks_exact(4)) {
+            let s = match self.tint {
+                Some(tint) => tinted(tint, s),
+                None => [s[0], s[1], s[2], s[3]],
+            };
+
+            blend_pixel_in(d, s, opacity, self.mode, space);
         }
     }
 }
 
-use crate::renderer::cpu::image::{ImageSlice, ImageSliceMut};
+/// Blends `stack` over `dest` from the bottom up, in the configured space.
+///
+/// Scanlines are composited in parallel, each through the whole stack, and
+/// come out the same as blending the sources one by one.
+pub fn composite(stack: &[BlendSource], dest: &mut ImageSliceMut) {
+    composite_in(BlendSpace::configured(), stack, dest);
+}
+
+/// Blends `stack` over `dest` from the bottom up, mixing colors in `space`.
+pub fn composite_in(space: BlendSpace, stack: &[BlendSource], dest: &mut ImageSliceMut) {
+    let rect = Rect::new(0, 0, dest.width, dest.height);
+    composite_rect_in(space, stack, dest, rect);
+}
+
+/// Blends `stack` over the part of `dest` in `rect` only, in the configured
+/// space.
+pub fn composite_rect(stack: &[BlendSource], dest: &mut ImageSliceMut, rect: Rect) {
+    composite_rect_in(BlendSpace::configured(), stack, dest, rect);
+}
 
-pub fn alpha_blend_simd(
-    src: &ImageSlice,
+fn composite_rect_in(
+    space: BlendSpace,
+    stack: &[BlendSource],
     dest: &mut ImageSliceMut,
-    (x, y): (isize, isize),
-    opacity: f32,
+    rect: Rect,
 ) {
-    for dy in ((-y).max(0) as usize)
-        ..src
-            .get_height()
-            .min((-y + dest.get_height() as isize) as usize)
+    let rect = rect.intersection(&Rect::new(0, 0, dest.width, dest.height));
+
+    if rect.is_empty() || stack.is_empty() {
+        return;
+    }
+
+    let (left, top) = (rect.left as usize, rect.top as usize);
+    let (right, bottom) = (rect.right as usize, rect.bottom as usize);
+    let stride = dest.width * 4;
+
+    dest.rgba_buffer[top * stride..bottom * stride]
+        .par_chunks_mut(stride)
+        .enumerate()
+        .for_each(|(i, row)| {
+            let row = &mut row[left * 4..right * 4];
+
+            for source in stack {
+                source.blend_scanline(row, (left, top + i), space);
+            }
+        });
+}
+
+/// Fills the part of `dest` in `rect` with `color`.
+pub fn fill_rect(dest: &mut ImageSliceMut, rect: Rect, color: [u8; 4]) {
+    let rect = rect.intersection(&Rect::new(0, 0, dest.width, dest.height));
+
+    if rect.is_empty() {
+        return;
+    }
+
+    let stride = dest.width * 4;
+    let columns = rect.left as usize * 4..rect.right as usize * 4;
+
+    for row in dest.rgba_buffer[rect.top as usize * stride..rect.bottom as usize * stride]
+        .chunks_mut(stride)
     {
-        let py = (dy as isize + y) as usize;
+        for p in row[columns.clone()].chunks_mut(4) {
+            p.copy_from_slice(&color);
+        }
+    }
+}
 
-        let origin = (-x).max(0) as usize;
+#[test]
+fn test_composite_matches_serial() {
+    use crate::constants::{GAME_WINDOW_HEIGHT, GAME_WINDOW_WIDTH};
+    use crate::renderer::cpu::image::Image;
+    use std::time::Instant;
+
+    let (width, height) = (GAME_WINDOW_WIDTH as usize, GAME_WINDOW_HEIGHT as usize);
+
+    // xorshift, so that the noise is the same on every run
+    let mut state = 0x2545_f491u32;
+    let mut noise = |width, height| {
+        let mut image = Image::new(width, height);
+
+        for p in image.rgba_buffer.chunks_mut(4) {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+
+            let v = state;
+            // mostly transparent or opaque, as sprites are
+            let alpha = match v % 4 {
+                0 => 0,
+                1 => 255,
+                _ => (v >> 8) as u8,
+            };
+
+            let color = [(v >> 16) as u8, (v >> 24) as u8, (v >> 4) as u8];
+            p.copy_from_slice(&tinted(color, &[0, 0, 0, alpha]));
+        }
 
-        let width = src
-            .get_width()
-            .min((-x + dest.get_width() as isize) as usize);
+        image
+    };
 
-        let offset_src = dy * src.get_width() << 2;
-        let offset_dst = py * dest.get_width() << 2;
+    let base = noise(width, height);
 
-        unsafe {
-            alpha_blend_simd_scanline(
-                &src.rgba_buffer[offset_src..][(origin << 2)..(width << 2)],
-                &mut dest.rgba_buffer[offset_dst..][(((origin as isize + x) as usize) << 2)
-                    ..(((width as isize + x) as usize) << 2)],
-                opacity,
-            );
+    let mut layers = vec![];
+    for i in 0..8 {
+        layers.push((noise(width, height), (0, 0), 1.0 - i as f32 * 0.1, None));
+    }
+    // clipped on every side, off screen, and tinted like text
+    layers.push((noise(300, 200), (-50, -30), 1.0, None));
+    layers.push((
+        noise(300, 200),
+        (width as isize - 100, height as isize - 50),
+        0.7,
+        None,
+    ));
+    layers.push((noise(300, 200), (width as isize + 10, 0), 1.0, None));
+    layers.push((noise(600, 40), (100, 700), 0.9, Some([0xff, 0xee, 0xdd])));
+
+    let started = Instant::now();
+    let mut serial = base.clone();
+
+    for (image, offset, opacity, tint) in &layers {
+        match tint {
+            Some(tint) => alpha_blend_colored(image, &mut serial, *offset, *opacity, *tint),
+            None => alpha_blend(image, &mut serial, *offset, *opacity),
         }
     }
+
+    let serial_time = started.elapsed();
+
+    let stack: Vec<_> = layers
+        .iter()
+        .map(|(image, offset, opacity, tint)| {
+            let source = BlendSource::new(image.as_slice(), *offset, *opacity);
+            tint.map_or(source, |tint| source.tinted(tint))
+        })
+        .collect();
+
+    let started = Instant::now();
+    let mut parallel = base.clone();
+
+    composite_in(
+        BlendSpace::Gamma,
+        &stack,
+        &mut ImageSliceMut {
+            width,
+            height,
+            rgba_buffer: &mut parallel.rgba_buffer,
+        },
+    );
+
+    let parallel_time = started.elapsed();
+
+    println!(
+        "{} layers: serial {:?}, parallel {:?}",
+        layers.len(),
+        serial_time,
+        parallel_time
+    );
+
+    let mismatch = serial
+        .rgba_buffer
+        .iter()
+        .zip(&parallel.rgba_buffer)
+        .position(|(s, p)| s != p);
+
+    assert_eq!(mismatch, None);
 }
 
-unsafe fn alpha_blend_simd_scanline(src: &[u8], dst: &mut [u8], opacity: f32) {
-    use std::arch::x86_64::*;
+#[test]
+fn test_blend_modes() {
+    use crate::renderer::cpu::image::Image;
+    use crate::renderer::cpu::CpuRenderingTarget;
 
-    assert_eq!(src.len(), dst.len());
-    assert_eq!(src.len() & 0x03, 0);
-    assert_eq!(dst.len() & 0x03, 0);
+    let blend = |mode, opacity| {
+        let mut dest = Image::new(1, 1);
+        dest.rgba_buffer.copy_from_slice(&[100, 50, 20, 255]);
 
-    let opacity: __m256 = std::mem::transmute([opacity; 8]);
-    let saturated: __m256 = std::mem::transmute([1.0f32; 8]);
-    let u8max: __m256 = std::mem::transmute([255.0f32; 8]);
+        let mut src = Image::new(1, 1);
+        src.rgba_buffer.copy_from_slice(&[80, 100, 200, 255]);
 
-    for i in 0..((src.len() >> 4) + 1) {
-        let offset = i << 4;
+        let source = BlendSource::new(src.as_slice(), (0, 0), opacity).with_mode(mode);
+        composite_in(BlendSpace::Gamma, &[source], &mut dest.framebuffer());
 
-        if src.len() <= offset {
-            break;
-        }
+        dest.rgba_buffer
+    };
 
-        let mut rsrc = [0f32; 8];
-        let mut gsrc = [0f32; 8];
-        let mut bsrc = [0f32; 8];
-        let mut asrc = [0f32; 8];
+    assert_eq!(blend(BlendMode::Normal, 1.0), [80, 100, 200, 255]);
+    assert_eq!(blend(BlendMode::Additive, 1.0), [180, 150, 220, 255]);
+    assert_eq!(blend(BlendMode::Multiply, 1.0), [31, 20, 16, 255]);
+    assert_eq!(blend(BlendMode::Screen, 1.0), [149, 130, 204, 255]);
+    assert_eq!(blend(BlendMode::Overlay, 1.0), [63, 39, 31, 255]);
+    assert_eq!(blend(BlendMode::Subtract, 1.0), [20, 0, 0, 255]);
 
-        let mut rdst = [0f32; 8];
-        let mut gdst = [0f32; 8];
-        let mut bdst = [0f32; 8];
-        let mut adst = [0f32; 8];
+    // halfway between the destination and the mixed color
+    assert_eq!(blend(BlendMode::Additive, 0.5), [140, 100, 120, 255]);
 
-        for j in 0..8 {
-            let j4 = j << 2;
+    // over nothing, every mode draws the source as it is
+    for &mode in &BlendMode::ALL {
+        let mut dest = Image::new(1, 1);
+        let mut src = Image::new(1, 1);
+        src.rgba_buffer.copy_from_slice(&[80, 100, 200, 128]);
 
-            if src.len() <= (offset + j4) {
-                break;
-            }
+        let source = BlendSource::new(src.as_slice(), (0, 0), 1.0).with_mode(mode);
+        composite_in(BlendSpace::Gamma, &[source], &mut dest.framebuffer());
 
-            rsrc[j] = src[offset + j4] as f32;
-            gsrc[j] = src[offset + j4 + 1] as f32;
-            bsrc[j] = src[offset + j4 + 2] as f32;
-            asrc[j] = src[offset + j4 + 3] as f32;
+        assert_eq!(dest.rgba_buffer, [80, 100, 200, 128], "{:?}", mode);
+    }
+}
 
-            rdst[j] = dst[offset + j4] as f32;
-            gdst[j] = dst[offset + j4 + 1] as f32;
-            bdst[j] = dst[offset + j4 + 2] as f32;
-            adst[j] = dst[offset + j4 + 3] as f32;
-        }
+#[test]
+fn test_linear_blending() {
+    use crate::renderer::cpu::image::{premultiply, Image};
+    use crate::renderer::cpu::CpuRenderingTarget;
+
+    let blend = |space, src: [u8; 4]| {
+        let mut dest = Image::new(1, 1);
+        dest.rgba_buffer.copy_from_slice(&[0, 0, 0, 255]);
+
+        let mut image = Image::new(1, 1);
+        image.rgba_buffer.copy_from_slice(&src);
+
+        let source = BlendSource::new(image.as_slice(), (0, 0), 1.0);
+        composite_in(space, &[source], &mut dest.framebuffer());
+
+        dest.rgba_buffer
+    };
+
+    // white at half alpha over black gives half the light, which is brighter
+    // than half the encoded value
+    assert_eq!(
+        blend(BlendSpace::Gamma, [128, 128, 128, 128]),
+        [128, 128, 128, 255]
+    );
+    assert_eq!(
+        blend(BlendSpace::Linear, [128, 128, 128, 128]),
+        [188, 188, 188, 255]
+    );
+    // opaque colors are drawn as they are
+    assert_eq!(
+        blend(BlendSpace::Linear, [12, 34, 56, 255]),
+        [12, 34, 56, 255]
+    );
+
+    // the sampled transfer functions stay within a fraction of a level
+    for i in 0..=1000 {
+        let c = i as f32 / 1000.0;
+        assert!((lookup(&SRGB_TO_LINEAR, c) - srgb_to_linear(c)).abs() < 1e-4);
+        assert!((lookup(&LINEAR_TO_SRGB, c) - linear_to_srgb(c)).abs() < 1e-4);
+    }
 
-        let rsrc: __m256 = std::mem::transmute(rsrc);
-        let gsrc: __m256 = std::mem::transmute(gsrc);
-        let bsrc: __m256 = std::mem::transmute(bsrc);
-        let asrc: __m256 = std::mem::transmute(asrc);
-        let rdst: __m256 = std::mem::transmute(rdst);
-        let gdst: __m256 = std::mem::transmute(gdst);
-        let bdst: __m256 = std::mem::transmute(bdst);
-        let adst: __m256 = std::mem::transmute(adst);
-
-        let rsrc = _mm256_div_ps(rsrc, u8max);
-        let gsrc = _mm256_div_ps(gsrc, u8max);
-        let bsrc = _mm256_div_ps(bsrc, u8max);
-        let asrc = _mm256_div_ps(asrc, u8max);
-        let rdst = _mm256_div_ps(rdst, u8max);
-        let gdst = _mm256_div_ps(gdst, u8max);
-        let bdst = _mm256_div_ps(bdst, u8max);
-        let adst = _mm256_div_ps(adst, u8max);
-
-        let asrc = _mm256_mul_ps(asrc, opacity);
-        let asrc_inv = _mm256_sub_ps(saturated, asrc);
-
-        let oa = _mm256_add_ps(asrc, _mm256_mul_ps(adst, asrc_inv));
-
-        let or = _mm256_div_ps(
-            _mm256_add_ps(
-                _mm256_mul_ps(rsrc, asrc),
-                _mm256_mul_ps(rdst, _mm256_mul_ps(adst, asrc_inv)),
-            ),
-            oa,
-        );
-        let og = _mm256_div_ps(
-            _mm256_add_ps(
-                _mm256_mul_ps(gsrc, asrc),
-                _mm256_mul_ps(gdst, _mm256_mul_ps(adst, asrc_inv)),
-            ),
-            oa,
-        );
-        let ob = _mm256_div_ps(
-            _mm256_add_ps(
-                _mm256_mul_ps(bsrc, asrc),
-                _mm256_mul_ps(bdst, _mm256_mul_ps(adst, asrc_inv)),
-            ),
-            oa,
-        );
-
-        let or = _mm256_mul_ps(or, u8max);
-        let og = _mm256_mul_ps(og, u8max);
-        let ob = _mm256_mul_ps(ob, u8max);
-        let oa = _mm256_mul_ps(oa, u8max);
-
-        let or: [f32; 8] = std::mem::transmute(or);
-        let og: [f32; 8] = std::mem::transmute(og);
-        let ob: [f32; 8] = std::mem::transmute(ob);
-        let oa: [f32; 8] = std::mem::transmute(oa);
-
-        for j in 0..8 {
-            let j4 = j << 2;
-
-            if src.len() <= (offset + j4) {
-                break;
+    // straight-alpha pixels over an opaque background, against a float model
+    // of how the GPU blends: decoded by the texture format in linear
+    // blending, premultiplied by the shader, and encoded by the target. This
+    // only checks the CPU code against that model, not against a GPU
+    let mut state = 0x1234_5678u32;
+    let mut next = || {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        state
+    };
+
+    let (width, height) = (256, 64);
+    let mut src = Image::new(width, height);
+    let mut base = Image::new(width, height);
+
+    for (p, q) in src
+        .rgba_buffer
+        .chunks_mut(4)
+        .zip(base.rgba_buffer.chunks_mut(4))
+    {
+        let (v, w) = (next(), next());
+        p.copy_from_slice(&[v as u8, (v >> 8) as u8, (v >> 16) as u8, (v >> 24) as u8]);
+        q.copy_from_slice(&[w as u8, (w >> 8) as u8, (w >> 16) as u8, 255]);
+    }
+
+    let straight = src.clone();
+    premultiply(&mut src.rgba_buffer);
+
+    for &space in &[BlendSpace::Gamma, BlendSpace::Linear] {
+        let linear = space == BlendSpace::Linear;
+        let decode = |c: u8| {
+            let c = c as f32 / 255.0;
+            if linear {
+                srgb_to_linear(c)
+            } else {
+                c
             }
+        };
+        let encode = |c: f32| if linear { linear_to_srgb(c) } else { c };
+
+        let mut actual = base.clone();
+        let source = BlendSource::new(src.as_slice(), (0, 0), 1.0);
+        composite_in(space, &[source], &mut actual.framebuffer());
 
-            dst[offset + j4] = or[j] as u8;
-            dst[offset + j4 + 1] = og[j] as u8;
-            dst[offset + j4 + 2] = ob[j] as u8;
-            dst[offset + j4 + 3] = oa[j] as u8;
+        let mut error = 0;
+
+        for ((s, d), a) in straight
+            .rgba_buffer
+            .chunks(4)
+            .zip(base.rgba_buffer.chunks(4))
+            .zip(actual.rgba_buffer.chunks(4))
+        {
+            let sa = s[3] as f32 / 255.0;
+
+            for c in 0..3 {
+                let (cs, cb) = (decode(s[c]), decode(d[c]));
+                let expected = (encode(cs * sa + cb * (1.0 - sa)) * 255.0).round() as i32;
+
+                // nearly transparent colors are stored coarsely
+                if space == BlendSpace::Gamma || 32 <= s[3] {
+                    error = error.max((expected - a[c] as i32).abs());
+                }
+            }
         }
+
+        let tolerance = match space {
+            BlendSpace::Gamma => 1,
+            BlendSpace::Linear => 2,
+        };
+
+        assert!(error <= tolerance, "{:?} differs by {}", space, error);
     }
 }
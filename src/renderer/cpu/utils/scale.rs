@@ -0,0 +1,237 @@
+//! Resampling.
+//!
+//! Separable, like the blur: rows are resampled horizontally, then the
+//! output rows mix the rows above and below, each in parallel. The weights
+//! are computed once per size and kept, as frames keep theirs. Colors are
+//! premultiplied, so that transparent pixels do not bleed into the edges.
+
+use rayon::prelude::*;
+
+use crate::config::{self, ScaleFilter};
+use crate::renderer::cpu::image::{ImageSlice, ImageSliceMut};
+
+// fixed-point precision of the weights
+const WEIGHT_BITS: u32 = 14;
+const ONE: i32 = 1 << WEIGHT_BITS;
+
+// weights of the source pixels an output pixel mixes, from `start` on
+struct Taps {
+    start: usize,
+    weights: Vec<i32>,
+}
+
+fn support(filter: ScaleFilter) -> f64 {
+    match filter {
+        ScaleFilter::Bilinear => 1.0,
+        ScaleFilter::Lanczos => 3.0,
+    }
+}
+
+fn kernel(filter: ScaleFilter, x: f64) -> f64 {
+    use std::f64::consts::PI;
+
+    match filter {
+        ScaleFilter::Bilinear => (1.0 - x.abs()).max(0.0),
+        ScaleFilter::Lanczos if x == 0.0 => 1.0,
+        ScaleFilter::Lanczos if x.abs() < 3.0 => {
+            let px = PI * x;
+            3.0 * px.sin() * (px / 3.0).sin() / (px * px)
+        }
+        ScaleFilter::Lanczos => 0.0,
+    }
+}
+
+fn taps(src: usize, dest: usize, filter: ScaleFilter) -> Vec<Taps> {
+    let ratio = src as f64 / dest as f64;
+    // widened as it shrinks, so that every source pixel counts
+    let stretch = ratio.max(1.0);
+    let support = support(filter) * stretch;
+
+    (0..dest)
+        .map(|i| {
+            let center = (i as f64 + 0.5) * ratio;
+            let start = (center - support).floor().max(0.0) as usize;
+            let end = ((center + support).ceil() as usize).min(src);
+
+            // pixels beyond the edges are left out, and the rest weigh more
+            let weights: Vec<f64> = (start..end)
+                .map(|j| kernel(filter, (j as f64 + 0.5 - center) / stretch))
+                .collect();
+            let total: f64 = weights.iter().sum();
+
+            let mut weights: Vec<i32> = weights
+                .iter()
+                .map(|w| (w / total * ONE as f64).round() as i32)
+                .collect();
+
+            // rounding error goes to the heaviest
+            let sum: i32 = weights.iter().sum();
+            if let Some(w) = weights.iter_mut().max() {
+                *w += ONE - sum;
+            }
+
+            Taps { start, weights }
+        })
+        .collect()
+}
+
+fn store(p: &mut [u8], sum: &[i32]) {
+    let round = |v: i32| ((v + ONE / 2) >> WEIGHT_BITS).clamp(0, 255) as u8;
+    let alpha = round(sum[3]);
+
+    // the lobes of Lanczos may overshoot alpha
+    for (c, &s) in p[..3].iter_mut().zip(sum) {
+        *c = round(s).min(alpha);
+    }
+
+    p[3] = alpha;
+}
+
+fn resample_row(src: &[u8], dest: &mut [u8], columns: &[Taps]) {
+    let mut sum = [0i32; 4];
+
+    for (p, taps) in dest.chunks_mut(4).zip(columns) {
+        sum.iter_mut().for_each(|s| *s = 0);
+
+        for (i, &w) in taps.weights.iter().enumerate() {
+            for (s, &v) in sum.iter_mut().zip(&src[(taps.start + i) * 4..][..4]) {
+                *s += v as i32 * w;
+            }
+        }
+
+        store(p, &sum);
+    }
+}
+
+/// Resamples images to another size with a filter, keeping the weights
+/// while the sizes stay the same.
+pub struct Scaler {
+    filter: ScaleFilter,
+    // source and output sizes the weights are for
+    sizes: Option<((usize, usize), (usize, usize))>,
+    columns: Vec<Taps>,
+    rows: Vec<Taps>,
+    // the source resampled horizontally
+    tmp: Vec<u8>,
+}
+
+impl Scaler {
+    pub fn new(filter: ScaleFilter) -> Self {
+        Self {
+            filter,
+            sizes: None,
+            columns: vec![],
+            rows: vec![],
+            tmp: vec![],
+        }
+    }
+
+    /// With the configured filter.
+    pub fn from_config() -> Self {
+        Self::new(config::get_scale_filter())
+    }
+
+    /// Resamples `src` to the whole of `dest`.
+    pub fn scale(&mut self, src: &ImageSlice, dest: &mut ImageSliceMut) {
+        let (sw, sh) = (src.width, src.height);
+        let (dw, dh) = (dest.width, dest.height);
+
+        if sw == 0 || sh == 0 || dw == 0 || dh == 0 {
+            return;
+        }
+
+        if (sw, sh) == (dw, dh) {
+            dest.rgba_buffer[..dw * dh * 4].copy_from_slice(&src.rgba_buffer[..sw * sh * 4]);
+            return;
+        }
+
+        if self.sizes != Some(((sw, sh), (dw, dh))) {
+            self.sizes = Some(((sw, sh), (dw, dh)));
+            self.columns = taps(sw, dw, self.filter);
+            self.rows = taps(sh, dh, self.filter);
+            self.tmp.resize(dw * sh * 4, 0);
+        }
+
+        let columns = &self.columns;
+
+        self.tmp
+            .par_chunks_mut(dw * 4)
+            .zip(src.rgba_buffer[..sw * sh * 4].par_chunks(sw * 4))
+            .for_each(|(row, src)| resample_row(src, row, columns));
+
+        let tmp = &self.tmp;
+
+        dest.rgba_buffer[..dw * dh * 4]
+            .par_chunks_mut(dw * 4)
+            .zip(self.rows.par_iter())
+            .for_each(|(row, taps)| {
+                let mut sum = vec![0i32; row.len()];
+
+                for (i, &w) in taps.weights.iter().enumerate() {
+                    let src = &tmp[(taps.start + i) * dw * 4..][..dw * 4];
+
+                    for (s, &v) in sum.iter_mut().zip(src) {
+                        *s += v as i32 * w;
+                    }
+                }
+
+                for (p, sum) in row.chunks_mut(4).zip(sum.chunks(4)) {
+                    store(p, sum);
+                }
+            });
+    }
+}
+
+#[test]
+fn test_scaler() {
+    use crate::renderer::cpu::image::Image;
+    use crate::renderer::cpu::CpuRenderingTarget;
+
+    // the same size is copied
+    let mut src = Image::new(8, 4);
+    for (i, v) in src.rgba_buffer.iter_mut().enumerate() {
+        *v = if i % 4 == 3 { 0xff } else { (i * 7) as u8 };
+    }
+
+    let mut dest = Image::new(8, 4);
+    Scaler::new(ScaleFilter::Lanczos).scale(&src.as_slice(), &mut dest.framebuffer());
+    assert_eq!(dest.rgba_buffer, src.rgba_buffer);
+
+    // flat stays flat, whatever the filter
+    let mut flat = Image::new(8, 4);
+    for p in flat.rgba_buffer.chunks_mut(4) {
+        p.copy_from_slice(&[0x40, 0x80, 0xc0, 0xff]);
+    }
+
+    for &filter in &[ScaleFilter::Bilinear, ScaleFilter::Lanczos] {
+        let mut dest = Image::new(21, 9);
+        Scaler::new(filter).scale(&flat.as_slice(), &mut dest.framebuffer());
+        assert!(dest
+            .rgba_buffer
+            .chunks(4)
+            .all(|p| p == [0x40, 0x80, 0xc0, 0xff]));
+    }
+
+    // stripes halved are gray away from the edges
+    let mut stripes = Image::new(8, 2);
+    for (i, p) in stripes.rgba_buffer.chunks_mut(4).enumerate() {
+        let v = if i % 2 == 0 { 0x00 } else { 0xff };
+        p.copy_from_slice(&[v, v, v, 0xff]);
+    }
+
+    let mut dest = Image::new(4, 1);
+    Scaler::new(ScaleFilter::Bilinear).scale(&stripes.as_slice(), &mut dest.framebuffer());
+    assert!((dest.rgba_buffer[4] as i32 - 0x80).abs() <= 1);
+    assert!((dest.rgba_buffer[8] as i32 - 0x80).abs() <= 1);
+
+    // an opaque edge enlarged rings, but stays premultiplied
+    let mut edge = Image::new(4, 1);
+    edge.rgba_buffer[..8].copy_from_slice(&[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]);
+
+    let mut dest = Image::new(16, 3);
+    Scaler::new(ScaleFilter::Lanczos).scale(&edge.as_slice(), &mut dest.framebuffer());
+    assert!(dest
+        .rgba_buffer
+        .chunks(4)
+        .all(|p| p[..3].iter().all(|&c| c <= p[3])));
+}
@@ -0,0 +1,283 @@
+//! Copy of a target, for blending that reads the destination.
+//!
+//! Overlay cannot be blended by fixed functions, and a fragment shader
+//! cannot read the attachment it draws on; the target is copied here between
+//! render passes, and the overlay pipeline reads it as the set 1.
+
+use ::vulkano::command_buffer::AutoCommandBufferBuilder;
+use ::vulkano::descriptor::descriptor_set::{DescriptorSet, PersistentDescriptorSet};
+use ::vulkano::descriptor::PipelineLayoutAbstract;
+use ::vulkano::device::Queue;
+use ::vulkano::format::{ClearValue, Format};
+use ::vulkano::framebuffer::FramebufferAbstract;
+use ::vulkano::image::{Dimensions, ImageAccess, ImageUsage, StorageImage};
+use ::vulkano::pipeline::GraphicsPipeline;
+use ::vulkano::sampler::{Filter, MipmapMode, Sampler, SamplerAddressMode};
+
+use std::sync::Arc;
+
+pub struct Backdrop {
+    pub image: Arc<StorageImage<Format>>,
+    pub set: Arc<dyn DescriptorSet + Sync + Send>,
+}
+
+impl Backdrop {
+    /// Creates a backdrop for targets of `dimensions` and `format`, bound as
+    /// the set 1 of `pipeline`.
+    pub fn new<Mv, L, Rp>(
+        dimensions: [u32; 2],
+        format: Format,
+        queue: Arc<Queue>,
+        pipeline: &Arc<GraphicsPipeline<Mv, L, Rp>>,
+    ) -> Self
+    where
+        L: PipelineLayoutAbstract,
+    {
+        let device = queue.device().clone();
+
+        let image = StorageImage::with_usage(
+            device.clone(),
+            Dimensions::Dim2d {
+                width: dimensions[0],
+                height: dimensions[1],
+            },
+            format,
+            ImageUsage {
+                sampled: true,
+                transfer_destination: true,
+                ..ImageUsage::none()
+            },
+            Some(queue.family()),
+        )
+        .unwrap();
+
+        // read by pixels of the target; never filtered
+        let sampler = Sampler::new(
+            device,
+            Filter::Nearest,
+            Filter::Nearest,
+            MipmapMode::Nearest,
+            SamplerAddressMode::ClampToEdge,
+            SamplerAddressMode::ClampToEdge,
+            SamplerAddressMode::ClampToEdge,
+            0.0,
+            1.0,
+            0.0,
+            0.0,
+        )
+        .unwrap();
+
+        let layout = pipeline.layout().descriptor_set_layout(1).unwrap();
+        let set = PersistentDescriptorSet::start(layout.clone())
+            .add_sampled_image(image.clone(), sampler)
+            .unwrap()
+            .build()
+            .unwrap();
+
+        Self {
+            image,
+            set: Arc::new(set),
+        }
+    }
+
+    pub fn dimensions(&self) -> [u32; 2] {
+        self.image.dimensions().width_height()
+    }
+
+    /// Copies `target` as drawn so far, inside a render pass; the pass is
+    /// ended, and drawing resumes on `framebuffer`, of a resuming render
+    /// pass on the same target.
+    pub fn copy<I, F>(&self, builder: &mut AutoCommandBufferBuilder, target: I, framebuffer: F)
+    where
+        I: ImageAccess + Send + Sync + 'static,
+        F: FramebufferAbstract + Clone + Send + Sync + 'static,
+    {
+        let [width, height] = self.dimensions();
+
+        builder.end_render_pass().unwrap();
+        builder
+            .copy_image(
+                target,
+                [0, 0, 0],
+                0,
+                0,
+                self.image.clone(),
+                [0, 0, 0],
+                0,
+                0,
+                [width, height, 1],
+                1,
+            )
+            .unwrap();
+        builder
+            .begin_render_pass(framebuffer, false, vec![ClearValue::None])
+            .unwrap();
+    }
+}
+
+// needs a GPU, so run it with `cargo test -- --ignored`
+#[test]
+#[ignore]
+fn test_overlay_matches_cpu() {
+    use crate::constants::{GAME_WINDOW_HEIGHT, GAME_WINDOW_WIDTH};
+    use crate::format::s25::{self, S25Archive};
+    use crate::renderer::cpu::image::Image;
+    use crate::renderer::cpu::utils::{composite_in, BlendSource, BlendSpace};
+    use crate::renderer::cpu::CpuRenderingTarget;
+    use crate::renderer::vulkano::layer::pict_layer::PictLayer;
+    use crate::renderer::vulkano::offscreen::OffscreenTexture;
+    use crate::renderer::vulkano::shaders::pict_layer::vs::ty::PushConstantData;
+    use crate::renderer::vulkano::{instance, pipeline, texture_loader};
+    use crate::script::mil::command::BlendMode;
+    use crate::utils::viewport;
+    use ::vulkano::buffer::{BufferUsage, CpuAccessibleBuffer};
+    use ::vulkano::command_buffer::DynamicState;
+    use ::vulkano::device::{Device, DeviceExtensions, Features};
+    use ::vulkano::framebuffer::{Framebuffer, RenderPassAbstract};
+    use ::vulkano::instance::PhysicalDevice;
+    use ::vulkano::pipeline::viewport::Viewport;
+    use ::vulkano::sync::GpuFuture;
+
+    let (width, height) = (GAME_WINDOW_WIDTH, GAME_WINDOW_HEIGHT);
+
+    // an opaque and a translucent pixel, over channels on either side of
+    // the middle
+    let dest = [100, 200, 20, 255];
+    let pixels = [80, 100, 200, 255, 200, 30, 120, 128];
+
+    let bytes = s25::encode(&[s25::Entry {
+        entry_no: 0,
+        offset: (0, 0),
+        width: 2,
+        height: 1,
+        rgba_buffer: &pixels,
+    }]);
+    let image = S25Archive::from_raw_bytes(&bytes)
+        .unwrap()
+        .load_image(0)
+        .unwrap();
+
+    let mut expected = Image::new(2, 1);
+    for p in expected.rgba_buffer.chunks_mut(4) {
+        p.copy_from_slice(&dest);
+    }
+
+    let src = Image::from(image.clone());
+    let source = BlendSource::new(src.as_slice(), (0, 0), 1.0).with_mode(BlendMode::Overlay);
+    composite_in(BlendSpace::Gamma, &[source], &mut expected.framebuffer());
+
+    let physical = PhysicalDevice::enumerate(instance::get_instance())
+        .next()
+        .expect("no physical device available");
+    let family = physical
+        .queue_families()
+        .find(|q| q.supports_graphics())
+        .unwrap();
+    let (device, mut queues) = Device::new(
+        physical,
+        &Features::none(),
+        &DeviceExtensions::none(),
+        vec![(family, 1.0)],
+    )
+    .unwrap();
+    let queue = queues.next().unwrap();
+
+    // not sRGB, so that colors are mixed as encoded as on the CPU
+    let format = Format::R8G8B8A8Unorm;
+    let render_pass = pipeline::create_render_pass(device.clone(), format);
+    let resuming_pass = pipeline::create_resuming_render_pass(device.clone(), format);
+    let pipeline = pipeline::create_pict_layer_pipeline(
+        device.clone(),
+        render_pass.clone(),
+        BlendMode::Overlay,
+    );
+
+    let mut layer = PictLayer::empty();
+    layer.load_gpu(
+        image,
+        queue.clone(),
+        pipeline.clone(),
+        texture_loader::texture_format(false),
+    );
+
+    let target = OffscreenTexture::new((width, height), queue.clone(), format);
+    let backdrop = Backdrop::new([width, height], format, queue.clone(), &pipeline);
+
+    let framebuffer = |pass: Arc<dyn RenderPassAbstract + Send + Sync>| {
+        Arc::new(
+            Framebuffer::start(pass)
+                .add(target.texture.clone())
+                .unwrap()
+                .build()
+                .unwrap(),
+        )
+    };
+    let dyn_state = DynamicState {
+        viewports: Some(vec![Viewport {
+            origin: [0.0, 0.0],
+            dimensions: [width as f32, height as f32],
+            depth_range: 0.0..1.0,
+        }]),
+        ..DynamicState::none()
+    };
+    let readback = CpuAccessibleBuffer::from_iter(
+        device.clone(),
+        BufferUsage::all(),
+        false,
+        (0..width * height * 4).map(|_| 0u8),
+    )
+    .unwrap();
+
+    let clear = [
+        dest[0] as f32 / 255.0,
+        dest[1] as f32 / 255.0,
+        dest[2] as f32 / 255.0,
+        dest[3] as f32 / 255.0,
+    ];
+
+    let mut builder =
+        AutoCommandBufferBuilder::primary_one_time_submit(device.clone(), queue.family()).unwrap();
+    builder
+        .begin_render_pass(framebuffer(render_pass), false, vec![clear.into()])
+        .unwrap();
+    backdrop.copy(
+        &mut builder,
+        target.texture.clone(),
+        framebuffer(resuming_pass),
+    );
+    layer.draw(
+        &mut builder,
+        pipeline,
+        &dyn_state,
+        PushConstantData {
+            offset: viewport::f_point_unscaled(0.0, 0.0),
+            opacity: 1.0,
+            radius_x: 0.0,
+            radius_y: 0.0,
+        },
+        Some(backdrop.set.clone()),
+    );
+    builder.end_render_pass().unwrap();
+    builder
+        .copy_image_to_buffer(target.texture.clone(), readback.clone())
+        .unwrap();
+
+    layer
+        .take_future()
+        .unwrap()
+        .then_execute(queue, builder.build().unwrap())
+        .unwrap()
+        .then_signal_fence_and_flush()
+        .unwrap()
+        .wait(None)
+        .unwrap();
+
+    // the first row starts with the layer
+    let actual = readback.read().unwrap()[..8].to_vec();
+    let close = actual
+        .iter()
+        .zip(&expected.rgba_buffer)
+        .all(|(a, e)| (*a as i32 - *e as i32).abs() <= 1);
+
+    assert!(close, "{:?} != {:?}", actual, expected.rgba_buffer);
+}
@@ -6,6 +6,7 @@ use pict_layer::{PictLayer, Vertex};
 
 use vulkano::buffer::ImmutableBuffer;
 use vulkano::command_buffer::{AutoCommandBufferBuilder, DynamicState};
+use vulkano::descriptor::descriptor_set::DescriptorSet;
 use vulkano::descriptor::PipelineLayoutAbstract;
 use vulkano::device::{Device, Queue};
 use vulkano::format::Format;
@@ -14,11 +15,15 @@ use vulkano::pipeline::vertex::SingleBufferDefinition;
 use vulkano::pipeline::{vertex::VertexSource, GraphicsPipeline, GraphicsPipelineAbstract};
 use vulkano::sync::GpuFuture;
 
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
-use crate::constants::LRU_CACHE_CAPACITY;
+use crate::config;
 use crate::format::s25::{S25Archive, S25Image};
+use crate::model::layer::LayerModel;
+use crate::renderer::vulkano::shaders::pict_layer::vs::ty::PushConstantData;
+use crate::utils::viewport;
 
 #[derive(Clone, Copy, Eq, PartialEq)]
 pub enum OverlayMode {
@@ -49,7 +54,10 @@ pub struct LayerRenderer {
     // property
     pub offset: (i32, i32),
     pub opacity: f32,
-    pub blur: Option<(i32, i32)>,
+    pub blend_mode: BlendMode,
+    pub blur: Option<(f64, f64)>,
+    // property state with delays and animations
+    pub model: LayerModel,
     // for optimization
     update_flag: bool,
     queued_load: Option<(String, Vec<i32>)>,
@@ -68,11 +76,13 @@ impl LayerRenderer {
             s25: None,
             filename: None,
             entries: vec![],
-            cache: LruCache::new(LRU_CACHE_CAPACITY),
+            cache: LruCache::new(config::CONFIG.vulkano.lru_cache),
             opacity: 1.0,
+            blend_mode: BlendMode::Normal,
             update_flag: false,
             blur: None,
             offset: (0, 0),
+            model: LayerModel::new(0),
             queued_load: None,
             queued_prefetch: None,
             staged_prefetch: mpsc::channel(),
@@ -216,7 +226,7 @@ impl LayerRenderer {
         self.offset = (x, y);
     }
 
-    pub fn set_blur_rate(&mut self, rx: i32, ry: i32) {
+    pub fn set_blur_rate(&mut self, rx: f64, ry: f64) {
         self.blur = Some((rx, ry));
     }
 
@@ -232,6 +242,8 @@ impl LayerRenderer {
         L: PipelineLayoutAbstract,
         Rp: RenderPassAbstract,
     {
+        self.poll_model();
+
         while let Ok((f, e, i)) = self.staged_prefetch.1.try_recv() {
             self.stage_image(&f, e, i, queue.clone(), pipeline.clone());
         }
@@ -251,11 +263,42 @@ impl LayerRenderer {
         self.update_flag = false;
     }
 
+    fn poll_model(&mut self) {
+        let origin = self.model.origin;
+        let opacity = self.model.opacity;
+        let blur_radius = self.model.blur_radius;
+
+        self.model.poll(std::time::Instant::now());
+
+        if origin != self.model.origin {
+            let (x, y) = self.model.origin;
+            self.set_position(x as i32, y as i32);
+        }
+
+        if (opacity - self.model.opacity).abs() > std::f32::EPSILON {
+            self.set_opacity(self.model.opacity);
+        }
+
+        if blur_radius != self.model.blur_radius {
+            let (rx, ry) = self.model.blur_radius;
+            self.set_blur_rate(rx, ry);
+        }
+
+        self.blend_mode = self.model.blend_mode;
+    }
+
+    /// Whether drawing reads the target, i.e. it has to be copied to the
+    /// `Backdrop` first.
+    pub fn reads_target(&self) -> bool {
+        self.blend_mode == BlendMode::Overlay && !self.entries.is_empty()
+    }
+
     pub fn draw<P>(
         &self,
         builder: &mut AutoCommandBufferBuilder,
         pipeline: P,
         dyn_state: &DynamicState,
+        backdrop: Option<Arc<dyn DescriptorSet + Sync + Send>>,
     ) where
         P: GraphicsPipelineAbstract
             + VertexSource<Arc<ImmutableBuffer<[Vertex]>>>
@@ -268,6 +311,16 @@ impl LayerRenderer {
             return;
         }
 
+        let (radius_x, radius_y) = self
+            .blur
+            .map_or((0.0, 0.0), |(rx, ry)| (rx as f32, ry as f32));
+        let push_constants = PushConstantData {
+            offset: viewport::f_point_unscaled(self.offset.0 as f64, self.offset.1 as f64),
+            opacity: self.opacity,
+            radius_x,
+            radius_y,
+        };
+
         // let all the pict-layers draw
         for layer in &self.entries {
             let layer = layer.read().unwrap();
@@ -278,9 +331,8 @@ impl LayerRenderer {
                 builder,
                 pipeline.clone(),
                 dyn_state,
-                (self.offset.0 as f64, self.offset.1 as f64),
-                self.opacity,
-                self.blur.unwrap_or_default(),
+                push_constants,
+                backdrop.clone(),
             );
         }
     }
@@ -299,18 +351,35 @@ impl LayerRenderer {
     }
 }
 
+use crate::renderer::vulkano::backdrop::Backdrop;
 use crate::renderer::vulkano::{VulkanoBackend, VulkanoRenderingContext, VulkanoRenderingTarget};
 use crate::renderer::Renderer;
 
+pub type PictLayerPipeline = Arc<
+    GraphicsPipeline<
+        SingleBufferDefinition<Vertex>,
+        Box<dyn PipelineLayoutAbstract + Send + Sync>,
+        Arc<dyn RenderPassAbstract + Sync + Send>,
+    >,
+>;
+
 pub struct LayerRenderingContext {
     pub render_pass: Arc<dyn RenderPassAbstract + Sync + Send>,
-    pub pipeline: Arc<
-        GraphicsPipeline<
-            SingleBufferDefinition<Vertex>,
-            Box<dyn PipelineLayoutAbstract + Send + Sync>,
-            Arc<dyn RenderPassAbstract + Sync + Send>,
-        >,
-    >,
+    pub pipeline: PictLayerPipeline,
+    // pipelines of the blend modes other than normal
+    pub blend_pipelines: HashMap<BlendMode, PictLayerPipeline>,
+    // the target as drawn before a layer reading it; see `reads_target`
+    pub backdrop: Option<Backdrop>,
+}
+
+impl LayerRenderingContext {
+    /// The pipeline drawing with `mode`; the normal one if there is none.
+    pub fn pipeline_for(&self, mode: BlendMode) -> PictLayerPipeline {
+        self.blend_pipelines
+            .get(&mode)
+            .unwrap_or(&self.pipeline)
+            .clone()
+    }
 }
 
 impl VulkanoRenderingContext for LayerRenderingContext {
@@ -332,13 +401,27 @@ where
 
         let state = target.dynamic_state().clone();
 
-        self.draw(target.command_buffer(), ctx.pipeline.clone(), &state);
+        let (pipeline, backdrop) = match (self.blend_mode, &ctx.backdrop) {
+            (BlendMode::Overlay, Some(backdrop)) => (
+                ctx.pipeline_for(BlendMode::Overlay),
+                Some(backdrop.set.clone()),
+            ),
+            // nothing to read the target from
+            (BlendMode::Overlay, None) => (ctx.pipeline.clone(), None),
+            (mode, _) => (ctx.pipeline_for(mode), None),
+        };
+
+        self.draw(target.command_buffer(), pipeline, &state, backdrop);
     }
 }
 
 // command receiver
 
-use crate::script::mil::command::LayerCommand;
+use crate::model::layer::animation::AnimationType;
+use crate::model::layer::LayerCommand as ModelCommand;
+use crate::script::mil::command::{BlendMode, LayerCommand};
+use crate::utils::easing::Easing;
+use std::time::Duration;
 
 impl LayerRenderer {
     pub fn send(&mut self, command: LayerCommand) {
@@ -373,15 +456,29 @@ impl LayerRenderer {
             }
             LayerCommand::SetPosition(x, y) => {
                 log::debug!("position: {}, {}", x, y);
-                self.set_position(x as i32, y as i32);
+                self.model.send(ModelCommand::LayerMoveTo(x, y));
             }
             LayerCommand::SetOpacity(opacity) => {
                 log::debug!("opacity: {}", opacity);
-                self.set_opacity(opacity as f32);
+                self.model.send(ModelCommand::LayerOpacity(opacity as f32));
             }
             LayerCommand::SetBlurRate(rx, ry) => {
                 log::debug!("blur rate: ({}, {})", rx, ry);
-                self.set_blur_rate(rx, ry);
+                self.model
+                    .send(ModelCommand::LayerBlur(rx as f64, ry as f64));
+            }
+            LayerCommand::AnimateBlurRate(rx, ry, duration) => {
+                log::debug!("blur rate animation: ({}, {}), {}", rx, ry, duration);
+                self.model.send(ModelCommand::LayerAnimate {
+                    duration: Duration::from_secs_f64(duration.max(0.0) / 1000.0),
+                    to: AnimationType::Blur(rx as f64, ry as f64),
+                    easing: Easing::Linear,
+                    then: vec![],
+                });
+            }
+            LayerCommand::SetBlendMode(mode) => {
+                log::debug!("blend mode: {}", mode.name());
+                self.model.send(ModelCommand::LayerBlendMode(mode));
             }
             LayerCommand::LoadOverlay(path, entry, mode) => {
                 log::debug!("overlay: {}, {}, {}", path, entry, mode);
@@ -404,12 +501,12 @@ impl LayerRenderer {
                 log::error!("anim not supported");
             }
             LayerCommand::FinalizeAnimation => {
-                log::debug!("finalize anim");
-                log::error!("anim not supported");
+                self.model.finalize();
             }
             LayerCommand::LayerDelay(v) => {
                 log::debug!("layer delay: {}", v);
-                log::error!("anim not supported");
+                let delay = Duration::from_secs_f64(v / 1000.0);
+                self.model.send(ModelCommand::LayerDelay(delay));
             }
         }
     }
@@ -457,7 +554,7 @@ impl LayerRenderer {
     }
 
     fn lookup(filename: &str) -> PathBuf {
-        // TODO
-        Self::lookup_into(&filename.to_ascii_uppercase(), "./blob/".as_ref()).unwrap()
+        let root = crate::config::get_root_path();
+        Self::lookup_into(&filename.to_ascii_uppercase(), root.as_ref()).unwrap()
     }
 }
@@ -12,6 +12,7 @@ use vulkano::sync::GpuFuture;
 use std::sync::Arc;
 
 use crate::format::s25::S25Image;
+use crate::renderer::vulkano::shaders::pict_layer::vs::ty::PushConstantData;
 use crate::renderer::vulkano::texture_loader;
 use crate::utils::viewport;
 
@@ -137,9 +138,8 @@ impl PictLayer {
         builder: &mut AutoCommandBufferBuilder,
         pipeline: P,
         dyn_state: &DynamicState,
-        (x, y): (f64, f64),
-        opacity: f32,
-        (radius_x, radius_y): (i32, i32),
+        push_constants: PushConstantData,
+        backdrop: Option<Arc<dyn DescriptorSet + Sync + Send>>,
     ) where
         P: GraphicsPipelineAbstract
             + VertexSource<Arc<ImmutableBuffer<[Vertex]>>>
@@ -150,18 +150,16 @@ impl PictLayer {
     {
         // workaround for not-loaded pict-layers
         if self.vertex_buffer.is_some() {
+            let mut sets = vec![self.set.clone().unwrap()];
+            sets.extend(backdrop);
+
             builder
                 .draw(
                     pipeline,
                     dyn_state,
                     self.vertex_buffer.clone().unwrap(),
-                    self.set.clone().unwrap(),
-                    crate::renderer::vulkano::shaders::pict_layer::vs::ty::PushConstantData {
-                        offset: viewport::f_point_unscaled(x, y),
-                        opacity,
-                        radius_x,
-                        radius_y,
-                    },
+                    sets,
+                    push_constants,
                 )
                 .unwrap();
         }
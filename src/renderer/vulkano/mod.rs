@@ -1,3 +1,4 @@
+pub mod backdrop;
 pub mod instance;
 pub mod layer;
 pub mod pipeline;
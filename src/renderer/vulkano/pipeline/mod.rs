@@ -29,11 +29,82 @@ pub fn create_render_pass(
     )
 }
 
+/// Creates the render pass drawing on what is already on the target, e.g.
+/// after it is copied to a `Backdrop`; compatible with `create_render_pass`.
+pub fn create_resuming_render_pass(
+    device: Arc<Device>,
+    format: Format,
+) -> Arc<dyn RenderPassAbstract + Send + Sync> {
+    Arc::new(
+        vulkano::single_pass_renderpass!(
+            device,
+            attachments: {
+                color: {
+                    load: Load,
+                    store: Store,
+                    format: format,
+                    samples: 1,
+                }
+            },
+            pass: {
+                color: [color],
+                depth_stencil: {}
+            }
+        )
+        .unwrap(),
+    )
+}
+
 use crate::renderer::vulkano::layer;
+use crate::script::mil::command::BlendMode;
+
+use vulkano::pipeline::blend::{AttachmentBlend, BlendFactor, BlendOp};
+
+/// Blending of `mode`, for premultiplied colors over an opaque target.
+///
+/// None for overlay, which depends on the target color in a way
+/// fixed-function blending cannot express; its shader reads the target from
+/// a `Backdrop` and writes the blended color.
+pub fn attachment_blend(mode: BlendMode) -> AttachmentBlend {
+    let (color_op, color_source, color_destination) = match mode {
+        BlendMode::Normal => (
+            BlendOp::Add,
+            BlendFactor::One,
+            BlendFactor::OneMinusSrcAlpha,
+        ),
+        BlendMode::Additive => (BlendOp::Add, BlendFactor::One, BlendFactor::One),
+        BlendMode::Multiply => (
+            BlendOp::Add,
+            BlendFactor::DstColor,
+            BlendFactor::OneMinusSrcAlpha,
+        ),
+        BlendMode::Screen => (
+            BlendOp::Add,
+            BlendFactor::OneMinusDstColor,
+            BlendFactor::One,
+        ),
+        BlendMode::Subtract => (BlendOp::ReverseSubtract, BlendFactor::One, BlendFactor::One),
+        BlendMode::Overlay => return AttachmentBlend::pass_through(),
+    };
+
+    AttachmentBlend {
+        color_op,
+        color_source,
+        color_destination,
+        alpha_op: BlendOp::Add,
+        alpha_source: BlendFactor::One,
+        alpha_destination: BlendFactor::OneMinusSrcAlpha,
+        ..AttachmentBlend::alpha_blending()
+    }
+}
 
+/// Creates the pipeline drawing pict-layers with `mode`.
+///
+/// That of overlay takes the `Backdrop` of the target as the set 1.
 pub fn create_pict_layer_pipeline<Rp>(
     device: Arc<Device>,
     render_pass: Rp,
+    mode: BlendMode,
 ) -> Arc<
     GraphicsPipeline<
         SingleBufferDefinition<layer::pict_layer::Vertex>,
@@ -44,23 +115,35 @@ pub fn create_pict_layer_pipeline<Rp>(
 where
     Rp: RenderPassAbstract,
 {
-    use crate::renderer::vulkano::shaders::pict_layer::{fs, vs};
+    use crate::renderer::vulkano::shaders::{pict_layer, pict_layer_overlay};
 
-    let vs = vs::Shader::load(device.clone()).unwrap();
-    let fs = fs::Shader::load(device.clone()).unwrap();
+    let vs = pict_layer::vs::Shader::load(device.clone()).unwrap();
 
-    Arc::new(
-        GraphicsPipeline::start()
-            .vertex_input_single_buffer::<layer::pict_layer::Vertex>()
-            .vertex_shader(vs.main_entry_point(), ())
-            .triangle_strip()
-            .viewports_dynamic_scissors_irrelevant(1)
+    let builder = GraphicsPipeline::start()
+        .vertex_input_single_buffer::<layer::pict_layer::Vertex>()
+        .vertex_shader(vs.main_entry_point(), ())
+        .triangle_strip()
+        .viewports_dynamic_scissors_irrelevant(1);
+
+    let pipeline = if mode == BlendMode::Overlay {
+        let fs = pict_layer_overlay::fs::Shader::load(device.clone()).unwrap();
+
+        builder
             .fragment_shader(fs.main_entry_point(), ())
-            .blend_alpha_blending()
+            .blend_collective(attachment_blend(mode))
             .render_pass(Subpass::from(render_pass, 0).unwrap())
             .build(device.clone())
-            .unwrap(),
-    )
+    } else {
+        let fs = pict_layer::fs::Shader::load(device.clone()).unwrap();
+
+        builder
+            .fragment_shader(fs.main_entry_point(), ())
+            .blend_collective(attachment_blend(mode))
+            .render_pass(Subpass::from(render_pass, 0).unwrap())
+            .build(device.clone())
+    };
+
+    Arc::new(pipeline.unwrap())
 }
 
 use crate::renderer::vulkano::text;
@@ -90,7 +173,7 @@ where
             .triangle_list()
             .viewports_dynamic_scissors_irrelevant(1)
             .fragment_shader(fs.main_entry_point(), ())
-            .blend_alpha_blending()
+            .blend_collective(attachment_blend(BlendMode::Normal))
             .render_pass(Subpass::from(render_pass, 0).unwrap())
             .build(device.clone())
             .unwrap(),
@@ -1,5 +1,6 @@
 pub mod layer;
 pub mod layer_overlay;
 pub mod pict_layer;
+pub mod pict_layer_overlay;
 pub mod simple;
 pub mod text;
@@ -14,8 +14,8 @@ pub mod vs {
         layout(push_constant) uniform PushConstantData {
             vec2  offset;
             float opacity;
-            int radius_x;
-            int radius_y;
+            float radius_x;
+            float radius_y;
         } pc;
 
         void main() {
@@ -32,8 +32,6 @@ pub mod fs {
         src: "
         #version 450
 
-        #define PI 3.141592653589793
-        
         layout(set = 0, binding = 0) uniform sampler2D tex;
 
         layout(location = 0) in   vec2    tex_coords;
@@ -42,38 +40,62 @@ pub mod fs {
         layout(push_constant) uniform PushConstantData {
             vec2  offset;
             float opacity;
-            int radius_x;
-            int radius_y;
+            float radius_x;
+            float radius_y;
         } pc;
 
-        float blur_rate(float x, float y) {
-            float r = x * x + y * y;
-            return exp( - r / 2.0) / sqrt(2.0 * PI);
+        // textures are straight-alpha; colors are multiplied by alpha once
+        // sampled, i.e. decoded if the format is sRGB
+        vec4 sample_premultiplied(vec2 uv) {
+            vec4 c = texture(tex, uv);
+            return vec4(c.rgb * c.a, c.a);
         }
 
-        void main() {
-            f_color = texture(tex, tex_coords);
-            f_color.a *= pc.opacity;
+        // Gaussian weight of the tap `i` pixels away, with a standard
+        // deviation of half the radius as the CPU blur; no blur at zero
+        float blur_rate(int i, float radius) {
+            if (radius <= 0.0) {
+                return i == 0 ? 1.0 : 0.0;
+            }
 
-            float sum = 1.0;
+            float x = i / radius;
+            return exp(-2.0 * x * x);
+        }
 
-            for (int i = 1; i <= pc.radius_x; i++) {
-                for (int j = 1; j <= pc.radius_y; j++) {
-                    float rate = blur_rate(i / float(pc.radius_x), j / float(pc.radius_y));
-                    vec2 delta = vec2(i / 3200.0, j / 1800.0);
-                    vec2 delta2 = delta;
+        void main() {
+            vec2 texel = 1.0 / vec2(textureSize(tex, 0));
+
+            f_color = sample_premultiplied(tex_coords);
 
-                    delta2.x = -delta2.x;
+            float sum = 1.0;
 
-                    sum += 4.0 * rate;
-                    f_color += rate * texture(tex, tex_coords + delta);
-                    f_color += rate * texture(tex, tex_coords - delta);
-                    f_color += rate * texture(tex, tex_coords + delta2);
-                    f_color += rate * texture(tex, tex_coords - delta2);
+            // radii may be fractional, e.g. while animated, and either may
+            // be zero; taps on the axes blur a single direction
+            for (int i = 0; i <= int(ceil(pc.radius_x)); i++) {
+                for (int j = 0; j <= int(ceil(pc.radius_y)); j++) {
+                    if (i == 0 && j == 0) {
+                        continue;
+                    }
+
+                    float rate = blur_rate(i, pc.radius_x) * blur_rate(j, pc.radius_y);
+                    vec2 delta = vec2(i, j) * texel;
+
+                    sum += 2.0 * rate;
+                    f_color += rate * sample_premultiplied(tex_coords + delta);
+                    f_color += rate * sample_premultiplied(tex_coords - delta);
+
+                    // mirrored across the axes, unless on one
+                    if (i != 0 && j != 0) {
+                        vec2 delta2 = vec2(-delta.x, delta.y);
+
+                        sum += 2.0 * rate;
+                        f_color += rate * sample_premultiplied(tex_coords + delta2);
+                        f_color += rate * sample_premultiplied(tex_coords - delta2);
+                    }
                 }
             }
 
-            f_color /= sum;
+            f_color *= pc.opacity / sum;
         }
         "
     }
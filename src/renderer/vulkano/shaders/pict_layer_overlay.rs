@@ -0,0 +1,108 @@
+//! Shaders for pict-layers blended by overlay
+//!
+//! Overlay depends on the target color, so the layer is drawn without
+//! blending, from the target copied to a backdrop; the vertex shader is that
+//! of `pict_layer`.
+
+pub mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: "
+        #version 450
+
+        layout(set = 0, binding = 0) uniform sampler2D tex;
+        // the target as drawn before the layer, in pixels of the target
+        layout(set = 1, binding = 0) uniform sampler2D backdrop;
+
+        layout(location = 0) in   vec2    tex_coords;
+        layout(location = 0) out  vec4    f_color;
+
+        layout(push_constant) uniform PushConstantData {
+            vec2  offset;
+            float opacity;
+            float radius_x;
+            float radius_y;
+        } pc;
+
+        // as in the pict-layer shader
+        vec4 sample_premultiplied(vec2 uv) {
+            vec4 c = texture(tex, uv);
+            return vec4(c.rgb * c.a, c.a);
+        }
+
+        // as in the pict-layer shader
+        float blur_rate(int i, float radius) {
+            if (radius <= 0.0) {
+                return i == 0 ? 1.0 : 0.0;
+            }
+
+            float x = i / radius;
+            return exp(-2.0 * x * x);
+        }
+
+        // the premultiplied color of the layer, blurred, at its opacity
+        vec4 source() {
+            vec2 texel = 1.0 / vec2(textureSize(tex, 0));
+
+            vec4 color = sample_premultiplied(tex_coords);
+
+            float sum = 1.0;
+
+            for (int i = 0; i <= int(ceil(pc.radius_x)); i++) {
+                for (int j = 0; j <= int(ceil(pc.radius_y)); j++) {
+                    if (i == 0 && j == 0) {
+                        continue;
+                    }
+
+                    float rate = blur_rate(i, pc.radius_x) * blur_rate(j, pc.radius_y);
+                    vec2 delta = vec2(i, j) * texel;
+
+                    sum += 2.0 * rate;
+                    color += rate * sample_premultiplied(tex_coords + delta);
+                    color += rate * sample_premultiplied(tex_coords - delta);
+
+                    if (i != 0 && j != 0) {
+                        vec2 delta2 = vec2(-delta.x, delta.y);
+
+                        sum += 2.0 * rate;
+                        color += rate * sample_premultiplied(tex_coords + delta2);
+                        color += rate * sample_premultiplied(tex_coords - delta2);
+                    }
+                }
+            }
+
+            return color * (pc.opacity / sum);
+        }
+
+        // a channel of the source color mixed with the destination
+        float overlay(float cb, float cs) {
+            return cb < 0.5 ? 2.0 * cb * cs : 1.0 - 2.0 * (1.0 - cb) * (1.0 - cs);
+        }
+
+        void main() {
+            vec4 src = source();
+            vec4 dest = texelFetch(backdrop, ivec2(gl_FragCoord.xy), 0);
+
+            // nothing to mix with
+            if (src.a <= 0.0) {
+                f_color = dest;
+                return;
+            } else if (dest.a <= 0.0) {
+                f_color = src;
+                return;
+            }
+
+            vec3 cs = min(src.rgb / src.a, 1.0);
+            vec3 cb = min(dest.rgb / dest.a, 1.0);
+            vec3 mixed = vec3(overlay(cb.r, cs.r), overlay(cb.g, cs.g), overlay(cb.b, cs.b));
+
+            // mixed as much as the destination is opaque, and composited
+            // over it as in normal blending, as the CPU does
+            f_color = vec4(
+                cs * src.a * (1.0 - dest.a) + cb * dest.a * (1.0 - src.a) + mixed * src.a * dest.a,
+                src.a + dest.a - src.a * dest.a
+            );
+        }
+        "
+    }
+}
@@ -55,6 +55,7 @@ pub mod fs {
             }
 
             f_color.a = clamp(alpha, 0.0, 1.0) * clamp(text_alpha, 0.0, 1.0);
+            f_color.rgb *= f_color.a;
         }
         "
     }
@@ -18,9 +18,11 @@ use winit::window::{Window, WindowBuilder};
 use std::sync::Arc;
 
 use super::{instance, VulkanoBackend, VulkanoRenderingContext, VulkanoRenderingTarget};
+use crate::config;
 use crate::constants;
 
 use crate::renderer::{EventDelegate, RenderingContext, RenderingSurface};
+use crate::utils::viewport::Letterbox;
 
 pub struct VulkanoSurface<'a> {
     pub physical: PhysicalDevice<'a>,
@@ -75,18 +77,38 @@ impl VulkanoSurface<'static> {
         // If usage contains VK_IMAGE_USAGE_STORAGE_BIT, then the image view's format
         // features must contain VK_FORMAT_FEATURE_STORAGE_IMAGE_BIT.
         // (https://www.khronos.org/registry/vulkan/specs/1.1-extensions/html/vkspec.html#VUID-VkImageViewCreateInfo-usage-02275)
-        let (f, cs) = caps
-            .supported_formats
-            .iter()
-            .copied()
-            .find(|(f, _)| {
-                *f == Format::B8G8R8A8Srgb
-                    || *f == Format::B8G8R8Srgb
-                    || *f == Format::R8G8B8A8Srgb
-                    || *f == Format::R8G8B8Srgb
-                    || *f == Format::B8G8R8A8Unorm // fuck
+
+        // an sRGB target encodes what is written to it, so that colors are
+        // blended in linear light
+        let preferred: &[Format] = if config::is_linear_blending_enabled() {
+            &[Format::B8G8R8A8Srgb, Format::R8G8B8A8Srgb]
+        } else {
+            &[Format::B8G8R8A8Unorm, Format::R8G8B8A8Unorm]
+        };
+
+        let find = |formats: &[Format]| {
+            formats.iter().find_map(|format| {
+                caps.supported_formats
+                    .iter()
+                    .copied()
+                    .find(|(f, _)| f == format)
             })
-            .expect("no suitable format; any of B8G8R8A8Srgb, B8G8R8Srgb, R8G8B8A8Srgb, or R8G8B8Srgb should be supported");
+        };
+
+        let (f, cs) = find(preferred)
+            .or_else(|| {
+                log::warn!("no format for the configured blending; colors may blend differently");
+
+                find(&[
+                    Format::B8G8R8A8Srgb,
+                    Format::B8G8R8Srgb,
+                    Format::R8G8B8A8Srgb,
+                    Format::R8G8B8Srgb,
+                    Format::B8G8R8A8Unorm,
+                    Format::R8G8B8A8Unorm,
+                ])
+            })
+            .expect("no suitable format; any of B8G8R8A8, B8G8R8, R8G8B8A8, or R8G8B8 should be supported");
 
         let (swapchain, images) = Swapchain::new(
             device.clone(),
@@ -95,7 +117,11 @@ impl VulkanoSurface<'static> {
             f,
             surface.window().inner_size().into(),
             1,
-            ImageUsage::color_attachment(),
+            // copied to the backdrop of layers blended by overlay
+            ImageUsage {
+                transfer_source: true,
+                ..ImageUsage::color_attachment()
+            },
             &graphical_queue,
             SurfaceTransform::Identity,
             caps.supported_composite_alpha.iter().next().unwrap(),
@@ -142,13 +168,16 @@ impl<'a> VulkanoSurface<'a> {
         // hence the instance should be alive while `physical` is alive.
         // Instance has 'static lifetime parameter, so no problem here.
         //
-        // This will use discrete GPU first, which will be the optimal for most
-        // environment.
+        // This will use discrete GPU first unless `backend.vulkano.useDiscreteGpu`
+        // is off, in which case integrated GPU is preferred.
+        let preferred = if config::CONFIG.vulkano.use_discrete_gpu {
+            PhysicalDeviceType::DiscreteGpu
+        } else {
+            PhysicalDeviceType::IntegratedGpu
+        };
 
-        // TODO: let users to choose physical devices
         let physical = PhysicalDevice::enumerate(instance)
-            .filter(|p| p.ty() == PhysicalDeviceType::DiscreteGpu)
-            .next()
+            .find(|p| p.ty() == preferred)
             .or_else(|| PhysicalDevice::enumerate(instance).next())
             .expect("no physical device available");
 
@@ -227,6 +256,20 @@ impl<'a> VulkanoSurface<'a> {
         self.swapchain.format()
     }
 
+    /// Whether the swapchain is sRGB, i.e. colors are blended in linear light.
+    pub fn is_srgb(&self) -> bool {
+        matches!(
+            self.format(),
+            Format::B8G8R8A8Srgb | Format::B8G8R8Srgb | Format::R8G8B8A8Srgb | Format::R8G8B8Srgb
+        )
+    }
+
+    /// Where the screen is drawn in the window.
+    pub fn letterbox(&self) -> Letterbox {
+        let dimensions = self.swapchain.dimensions();
+        Letterbox::for_window((dimensions[0], dimensions[1]))
+    }
+
     pub fn set_title(&mut self, title: &str) {
         self.surface.window().set_title(title)
     }
@@ -281,9 +324,10 @@ where
                 };
 
             self.swapchain = new_swapchain;
+            self.images = new_images;
 
             self.framebuffers = window_size_dependent_setup(
-                &new_images,
+                &self.images,
                 context.render_pass().clone(),
                 &mut self.dynamic_state,
             );
@@ -367,10 +411,12 @@ fn window_size_dependent_setup(
     dynamic_state: &mut DynamicState,
 ) -> Vec<Arc<dyn FramebufferAbstract + Send + Sync>> {
     let dimensions = images[0].dimensions();
+    let letterbox = Letterbox::for_window((dimensions[0], dimensions[1]));
 
+    // the screen keeps its aspect ratio; the rest is left cleared
     let viewport = Viewport {
-        origin: [0.0, 0.0],
-        dimensions: [dimensions[0] as f32, dimensions[1] as f32],
+        origin: [letterbox.x as f32, letterbox.y as f32],
+        dimensions: [letterbox.width as f32, letterbox.height as f32],
         depth_range: 0.0..1.0,
     };
     dynamic_state.viewports = Some(vec![viewport]);
@@ -21,7 +21,7 @@ pub type Texture = Arc<ImmutableImage<Format>>;
 
 use crate::renderer::common::text as renderer;
 
-const FONT_HEIGHT: f32 = 44.0;
+use crate::constants::DIALOGUE_FONT_HEIGHT as FONT_HEIGHT;
 
 // Text layer
 #[derive(Default)]
@@ -106,7 +106,8 @@ impl Text {
                 width: self.size.0 as u32,
                 height: self.size.1 as u32,
             },
-            Format::R8G8B8A8Srgb, // unsigned, normalized
+            // coverage rather than colors, so that it is never decoded
+            Format::R8G8B8A8Unorm,
             queue,
         )
         .expect("failed to load text into texture");
@@ -10,6 +10,20 @@ use vulkano::sync::NowFuture;
 
 use std::sync::Arc;
 
+/// Format of the textures of S25 images.
+///
+/// Their BGRA buffers are straight-alpha and sRGB-encoded, and are uploaded
+/// as they are: with `linear`, the sRGB format decodes them as they are
+/// sampled. The shaders premultiply the colors after sampling, in the same
+/// space as they are blended.
+pub fn texture_format(linear: bool) -> Format {
+    if linear {
+        Format::B8G8R8A8Srgb
+    } else {
+        Format::B8G8R8A8Unorm
+    }
+}
+
 pub fn load_s25_image(
     image: S25Image,
     queue: Arc<Queue>,
@@ -0,0 +1,126 @@
+//! Scenario loader.
+//!
+//! Turns scenario files into MIL programs ready to run. Shared between the
+//! game and the command-line tools, so that both see the same program.
+
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use crate::config;
+use crate::script::mil::command::{Command, RuntimeCommand};
+use crate::script::mil::pass::js::JsPass;
+use crate::script::mil::pass::log_entry::LogEntryPass;
+use crate::script::mil::pass::prefetch::PrefetchPass;
+use crate::script::mil::pass::Pass;
+use crate::script::rio::command::Command as RioCommand;
+use crate::script::runtime::js::JsRuntime;
+
+/// Scenes written in JavaScript against the engine API.
+pub fn is_js_scene(scenario: &str) -> bool {
+    scenario.to_ascii_lowercase().ends_with(".js")
+}
+
+/// Reads a RioScript scenario encoded in Shift_JIS.
+pub fn read_rio_scene(scenario: &str) -> std::io::Result<Vec<RioCommand>> {
+    use encoding_rs::SHIFT_JIS;
+
+    use crate::script::rio::parser::Parser;
+
+    let script = std::fs::read(scenario)?;
+    let (script, _, _) = SHIFT_JIS.decode(&script);
+
+    let mut parser = Parser::from_raw_bytes(script.as_bytes());
+    parser.parse()
+}
+
+/// Loads a scenario without any pass applied.
+///
+/// JavaScript scenes see the flags set on `js` beforehand.
+pub fn load_scene(js: &mut JsRuntime, scenario: &str) -> std::io::Result<Vec<Command>> {
+    use crate::script::rio::transpiler::Transpiler;
+
+    if is_js_scene(scenario) {
+        let source = std::fs::read_to_string(scenario)?;
+
+        return js
+            .run_scene(scenario, &source)
+            .map_err(|err| Error::new(ErrorKind::Other, err));
+    }
+
+    let script = read_rio_scene(scenario)?;
+    Ok(Transpiler::new(script).transpile())
+}
+
+/// Applies the configured JavaScript passes, then the built-in ones.
+pub fn apply_passes(js: &mut JsRuntime, script: Vec<Command>) -> Vec<Command> {
+    let mut script = script;
+
+    for path in config::get_pass_scripts() {
+        match std::fs::read_to_string(path) {
+            Ok(source) => script = JsPass::new(js, path, source).process(script),
+            Err(err) => log::error!("failed to read JS pass {}: {}", path, err),
+        }
+    }
+
+    let script = LogEntryPass::new().process(script);
+    PrefetchPass::new().process(script)
+}
+
+/// Positions of the labels in a program.
+pub fn find_labels(script: &[Command]) -> HashMap<String, usize> {
+    script
+        .iter()
+        .enumerate()
+        .filter_map(|(i, command)| match command {
+            Command::RuntimeCommand(RuntimeCommand::Label(label)) => Some((label.clone(), i)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Resolves a scenario called from `current`; scenarios are looked up next to
+/// the caller.
+pub fn resolve_scenario(current: &str, name: &str) -> String {
+    let mut path = Path::new(current).with_file_name(name);
+
+    if path.extension().is_none() {
+        path.set_extension("TXT");
+    }
+
+    path.to_string_lossy().into_owned()
+}
+
+/// Normalizes a scenario path, so that paths to the same file compare equal;
+/// `.` and `..` are resolved without looking at the file system.
+pub fn normalize(scenario: &str) -> PathBuf {
+    use std::path::Component;
+
+    let mut path = PathBuf::new();
+
+    for component in Path::new(scenario).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match path.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    path.pop();
+                }
+                // nothing above the root
+                Some(Component::RootDir) | Some(Component::Prefix(_)) => {}
+                _ => path.push(".."),
+            },
+            component => path.push(component),
+        }
+    }
+
+    path
+}
+
+#[test]
+fn test_normalize() {
+    assert_eq!(normalize("./x.TXT"), normalize("x.TXT"));
+    assert_eq!(normalize("a/./b/../x.TXT"), Path::new("a/x.TXT"));
+    assert_eq!(normalize("../a/x.TXT"), Path::new("../a/x.TXT"));
+    assert_eq!(normalize("/../x.TXT"), Path::new("/x.TXT"));
+    assert_ne!(normalize("a/x.TXT"), normalize("b/x.TXT"));
+}
@@ -22,6 +22,74 @@ pub struct AnimationGraph {
     finalize: Vec<Command>,
 }
 
+/// How a layer is combined with the layers below it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum BlendMode {
+    Normal,
+    Additive,
+    Multiply,
+    Screen,
+    Overlay,
+    Subtract,
+}
+
+impl BlendMode {
+    pub const ALL: [BlendMode; 6] = [
+        BlendMode::Normal,
+        BlendMode::Additive,
+        BlendMode::Multiply,
+        BlendMode::Screen,
+        BlendMode::Overlay,
+        BlendMode::Subtract,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            BlendMode::Normal => "normal",
+            BlendMode::Additive => "additive",
+            BlendMode::Multiply => "multiply",
+            BlendMode::Screen => "screen",
+            BlendMode::Overlay => "overlay",
+            BlendMode::Subtract => "subtract",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|mode| mode.name() == name)
+    }
+}
+
+impl Default for BlendMode {
+    fn default() -> Self {
+        BlendMode::Normal
+    }
+}
+
+use miniserde::de::{Deserialize, Visitor};
+use miniserde::ser::{Fragment, Serialize};
+
+// saved by name
+impl Serialize for BlendMode {
+    fn begin(&self) -> Fragment {
+        Fragment::Str(self.name().into())
+    }
+}
+
+miniserde::make_place!(Place);
+
+impl Visitor for Place<BlendMode> {
+    fn string(&mut self, s: &str) -> miniserde::Result<()> {
+        self.out = Some(BlendMode::from_name(s).ok_or(miniserde::Error)?);
+        Ok(())
+    }
+}
+
+impl Deserialize for BlendMode {
+    fn begin(out: &mut Option<Self>) -> &mut dyn Visitor {
+        Place::new(out)
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum LayerCommand {
     Load(String, Vec<i32>),
@@ -30,6 +98,8 @@ pub enum LayerCommand {
     SetPosition(f64, f64),
     SetOpacity(f64),
     SetBlurRate(i32, i32),
+    AnimateBlurRate(i32, i32, f64), // radii, duration in ms
+    SetBlendMode(BlendMode),
     LoadOverlay(String, i32, i32), // filename, entry, overlay mode
     UnloadOverlay,
     SetOverlayRate(f64),
@@ -57,6 +127,29 @@ pub enum RendererCommand {
 pub enum RuntimeCommand {
     Wait(f64),
     WaitUntilUserEvent,
+    SetFlag(String, f64),
+    SetVariable(String, Expr),
+    /// Evaluates a condition for the following branches.
+    Test(Expr),
+    /// Target of branches; does nothing by itself.
+    Label(String),
+    Branch(String),
+    /// Branches if the last test has failed.
+    BranchUnless(String),
+    /// Runs another scenario, from the label if any, until `Return`.
+    Call {
+        scenario: String,
+        label: Option<String>,
+    },
+    Return,
+    /// Shows the options, and branches to the label of the one picked.
+    Choice(Vec<ChoiceOption>),
+}
+
+#[derive(Clone, Debug)]
+pub struct ChoiceOption {
+    pub text: String,
+    pub label: String,
 }
 
 #[derive(Clone, Debug)]
@@ -97,6 +190,7 @@ pub enum PassCommand {
     AddEntry,
 }
 
+use super::expr::Expr;
 use crate::script::rio::command::Command as RioCommand;
 
 #[derive(Clone, Debug)]
@@ -114,3 +208,13 @@ pub enum Command {
     // for passes
     PassCommand(PassCommand),
 }
+
+/// Index of the layer `layer_no` of a `Command::LayerCommand`, if there is
+/// such a layer.
+pub fn layer_index(layer_no: i32) -> Option<usize> {
+    use std::convert::TryFrom;
+
+    usize::try_from(layer_no)
+        .ok()
+        .filter(|&index| index < crate::constants::LAYER_COUNT)
+}
@@ -0,0 +1,466 @@
+//! Expressions over scenario variables.
+//!
+//! Used by conditions and assignments in MIL. The syntax is a small subset of
+//! C-like expressions:
+//!
+//! ```text
+//! route == 2 && !(sys.cleared_tohka || count >= 10.5)
+//! name + "さん"
+//! ```
+//!
+//! Undefined variables evaluate to `Variable::Empty`.
+
+use thiserror::Error;
+
+use std::cmp::Ordering;
+use std::fmt;
+
+pub use crate::script::rio::command::Variable;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum ExprError {
+    #[error("unexpected character `{0}` at {1}")]
+    UnexpectedChar(char, usize),
+    #[error("unexpected `{0}`")]
+    UnexpectedToken(String),
+    #[error("unexpected end of expression")]
+    UnexpectedEnd,
+    #[error("unterminated string")]
+    UnterminatedString,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UnaryOp {
+    Not,
+    Neg,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    Literal(Variable),
+    Variable(String),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+}
+
+impl Variable {
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Variable::String(s) => !s.is_empty(),
+            Variable::Decimal(v) => *v != 0,
+            Variable::Float(v) => *v != 0.0,
+            Variable::Empty => false,
+        }
+    }
+
+    /// Numeric value; strings and empty variables are `None`.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Variable::Decimal(v) => Some(*v as f64),
+            Variable::Float(v) => Some(*v),
+            _ => None,
+        }
+    }
+
+    fn from_bool(v: bool) -> Self {
+        Variable::Decimal(v as i32)
+    }
+}
+
+impl fmt::Display for Variable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Variable::String(s) => write!(f, "{:?}", s),
+            Variable::Decimal(v) => write!(f, "{}", v),
+            Variable::Float(v) if v.fract() == 0.0 && v.is_finite() => write!(f, "{:.1}", v),
+            Variable::Float(v) => write!(f, "{}", v),
+            Variable::Empty => write!(f, "empty"),
+        }
+    }
+}
+
+impl UnaryOp {
+    fn symbol(self) -> &'static str {
+        match self {
+            UnaryOp::Not => "!",
+            UnaryOp::Neg => "-",
+        }
+    }
+}
+
+impl BinaryOp {
+    fn symbol(self) -> &'static str {
+        match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Rem => "%",
+            BinaryOp::Eq => "==",
+            BinaryOp::Ne => "!=",
+            BinaryOp::Lt => "<",
+            BinaryOp::Le => "<=",
+            BinaryOp::Gt => ">",
+            BinaryOp::Ge => ">=",
+            BinaryOp::And => "&&",
+            BinaryOp::Or => "||",
+        }
+    }
+
+    fn from_symbol(symbol: &str) -> Option<Self> {
+        let op = match symbol {
+            "+" => BinaryOp::Add,
+            "-" => BinaryOp::Sub,
+            "*" => BinaryOp::Mul,
+            "/" => BinaryOp::Div,
+            "%" => BinaryOp::Rem,
+            "==" => BinaryOp::Eq,
+            "!=" => BinaryOp::Ne,
+            "<" => BinaryOp::Lt,
+            "<=" => BinaryOp::Le,
+            ">" => BinaryOp::Gt,
+            ">=" => BinaryOp::Ge,
+            "&&" => BinaryOp::And,
+            "||" => BinaryOp::Or,
+            _ => return None,
+        };
+
+        Some(op)
+    }
+
+    // higher binds tighter
+    fn precedence(self) -> u8 {
+        match self {
+            BinaryOp::Or => 1,
+            BinaryOp::And => 2,
+            BinaryOp::Eq | BinaryOp::Ne => 3,
+            BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge => 4,
+            BinaryOp::Add | BinaryOp::Sub => 5,
+            BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem => 6,
+        }
+    }
+}
+
+/// Written back in the syntax accepted by `Expr::parse`.
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expr::Literal(v) => write!(f, "{}", v),
+            Expr::Variable(name) => write!(f, "{}", name),
+            Expr::Unary(op, e) => write!(f, "{}{}", op.symbol(), e),
+            Expr::Binary(op, l, r) => write!(f, "({} {} {})", l, op.symbol(), r),
+        }
+    }
+}
+
+// tokenizer
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Literal(Variable),
+    Ident(String),
+    Op(&'static str),
+    LParen,
+    RParen,
+}
+
+const OPERATORS: &[&str] = &[
+    "==", "!=", "<=", ">=", "&&", "||", "+", "-", "*", "/", "%", "<", ">", "!",
+];
+
+fn tokenize(source: &str) -> Result<Vec<Token>, ExprError> {
+    let mut tokens = vec![];
+    let mut chars = source.char_indices().peekable();
+
+    while let Some(&(i, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '(' || c == ')' {
+            chars.next();
+            tokens.push(if c == '(' {
+                Token::LParen
+            } else {
+                Token::RParen
+            });
+        } else if c.is_ascii_digit() {
+            let mut end = i;
+            while let Some(&(j, c)) = chars.peek() {
+                if !c.is_ascii_digit() && c != '.' {
+                    break;
+                }
+
+                end = j + c.len_utf8();
+                chars.next();
+            }
+
+            let number = &source[i..end];
+            let value = match number.parse::<i32>() {
+                Ok(v) => Variable::Decimal(v),
+                Err(_) => Variable::Float(
+                    number
+                        .parse()
+                        .map_err(|_| ExprError::UnexpectedToken(number.into()))?,
+                ),
+            };
+
+            tokens.push(Token::Literal(value));
+        } else if c == '"' || c == '\'' {
+            chars.next();
+
+            let mut s = String::new();
+            loop {
+                match chars.next() {
+                    Some((_, '\\')) => match chars.next() {
+                        Some((_, 'n')) => s.push('\n'),
+                        Some((_, 't')) => s.push('\t'),
+                        Some((_, c)) => s.push(c),
+                        None => return Err(ExprError::UnterminatedString),
+                    },
+                    Some((_, q)) if q == c => break,
+                    Some((_, c)) => s.push(c),
+                    None => return Err(ExprError::UnterminatedString),
+                }
+            }
+
+            tokens.push(Token::Literal(Variable::String(s)));
+        } else if c.is_alphabetic() || c == '_' {
+            let mut end = i;
+            while let Some(&(j, c)) = chars.peek() {
+                if !c.is_alphanumeric() && c != '_' && c != '.' {
+                    break;
+                }
+
+                end = j + c.len_utf8();
+                chars.next();
+            }
+
+            tokens.push(match &source[i..end] {
+                "true" => Token::Literal(Variable::Decimal(1)),
+                "false" => Token::Literal(Variable::Decimal(0)),
+                "empty" => Token::Literal(Variable::Empty),
+                ident => Token::Ident(ident.into()),
+            });
+        } else {
+            let op = OPERATORS
+                .iter()
+                .find(|op| source[i..].starts_with(*op))
+                .ok_or(ExprError::UnexpectedChar(c, i))?;
+
+            for _ in 0..op.len() {
+                chars.next();
+            }
+
+            tokens.push(Token::Op(op));
+        }
+    }
+
+    Ok(tokens)
+}
+
+// precedence climbing parser
+
+struct ExprParser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl ExprParser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Result<Token, ExprError> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token.ok_or(ExprError::UnexpectedEnd)
+    }
+
+    fn peek_binary_op(&self, min_precedence: u8) -> Option<BinaryOp> {
+        match self.peek() {
+            Some(Token::Op(symbol)) => {
+                BinaryOp::from_symbol(symbol).filter(|op| op.precedence() >= min_precedence)
+            }
+            _ => None,
+        }
+    }
+
+    fn parse_binary(&mut self, min_precedence: u8) -> Result<Expr, ExprError> {
+        let mut lhs = self.parse_unary()?;
+
+        while let Some(op) = self.peek_binary_op(min_precedence) {
+            self.pos += 1;
+
+            // all operators are left-associative
+            let rhs = self.parse_binary(op.precedence() + 1)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+
+        Ok(lhs)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, ExprError> {
+        match self.next()? {
+            Token::Op("!") => Ok(Expr::Unary(UnaryOp::Not, Box::new(self.parse_unary()?))),
+            Token::Op("-") => Ok(Expr::Unary(UnaryOp::Neg, Box::new(self.parse_unary()?))),
+            Token::Literal(v) => Ok(Expr::Literal(v)),
+            Token::Ident(name) => Ok(Expr::Variable(name)),
+            Token::LParen => {
+                let expr = self.parse_binary(0)?;
+
+                match self.next()? {
+                    Token::RParen => Ok(expr),
+                    token => Err(ExprError::UnexpectedToken(format!("{:?}", token))),
+                }
+            }
+            token => Err(ExprError::UnexpectedToken(format!("{:?}", token))),
+        }
+    }
+}
+
+impl Expr {
+    pub fn parse(source: &str) -> Result<Self, ExprError> {
+        let mut parser = ExprParser {
+            tokens: tokenize(source)?,
+            pos: 0,
+        };
+
+        let expr = parser.parse_binary(0)?;
+
+        match parser.peek() {
+            None => Ok(expr),
+            Some(token) => Err(ExprError::UnexpectedToken(format!("{:?}", token))),
+        }
+    }
+
+    /// Evaluates the expression, looking up variables with `lookup`.
+    ///
+    /// Never fails; ill-typed operations (e.g. `"a" * 2`) evaluate to
+    /// `Variable::Empty`.
+    pub fn eval<F>(&self, lookup: &F) -> Variable
+    where
+        F: Fn(&str) -> Variable,
+    {
+        match self {
+            Expr::Literal(v) => v.clone(),
+            Expr::Variable(name) => lookup(name),
+            Expr::Unary(UnaryOp::Not, e) => Variable::from_bool(!e.eval(lookup).is_truthy()),
+            Expr::Unary(UnaryOp::Neg, e) => match e.eval(lookup) {
+                Variable::Decimal(v) => Variable::Decimal(v.wrapping_neg()),
+                Variable::Float(v) => Variable::Float(-v),
+                _ => Variable::Empty,
+            },
+            Expr::Binary(BinaryOp::And, l, r) => {
+                Variable::from_bool(l.eval(lookup).is_truthy() && r.eval(lookup).is_truthy())
+            }
+            Expr::Binary(BinaryOp::Or, l, r) => {
+                Variable::from_bool(l.eval(lookup).is_truthy() || r.eval(lookup).is_truthy())
+            }
+            Expr::Binary(op, l, r) => binary(*op, l.eval(lookup), r.eval(lookup)),
+        }
+    }
+}
+
+fn compare(l: &Variable, r: &Variable) -> Option<Ordering> {
+    match (l, r) {
+        (Variable::Decimal(l), Variable::Decimal(r)) => Some(l.cmp(r)),
+        (Variable::String(l), Variable::String(r)) => Some(l.cmp(r)),
+        (Variable::Empty, Variable::Empty) => Some(Ordering::Equal),
+        _ => l.as_f64()?.partial_cmp(&r.as_f64()?),
+    }
+}
+
+fn binary(op: BinaryOp, l: Variable, r: Variable) -> Variable {
+    use BinaryOp::*;
+
+    match op {
+        Eq => return Variable::from_bool(compare(&l, &r) == Some(Ordering::Equal)),
+        Ne => return Variable::from_bool(compare(&l, &r) != Some(Ordering::Equal)),
+        Lt | Le | Gt | Ge => {
+            let result = match compare(&l, &r) {
+                Some(ordering) => match op {
+                    Lt => ordering == Ordering::Less,
+                    Le => ordering != Ordering::Greater,
+                    Gt => ordering == Ordering::Greater,
+                    _ => ordering != Ordering::Less,
+                },
+                None => false,
+            };
+
+            return Variable::from_bool(result);
+        }
+        _ => {}
+    }
+
+    match (l, r) {
+        (Variable::String(l), r) if op == Add => match r {
+            Variable::String(r) => Variable::String(l + &r),
+            Variable::Empty => Variable::String(l),
+            r => Variable::String(format!("{}{}", l, r)),
+        },
+        (Variable::Decimal(l), Variable::Decimal(r)) => match op {
+            Add => Variable::Decimal(l.wrapping_add(r)),
+            Sub => Variable::Decimal(l.wrapping_sub(r)),
+            Mul => Variable::Decimal(l.wrapping_mul(r)),
+            Div if r != 0 => Variable::Decimal(l.wrapping_div(r)),
+            Rem if r != 0 => Variable::Decimal(l.wrapping_rem(r)),
+            _ => Variable::Empty,
+        },
+        (l, r) => match (l.as_f64(), r.as_f64()) {
+            (Some(l), Some(r)) => match op {
+                Add => Variable::Float(l + r),
+                Sub => Variable::Float(l - r),
+                Mul => Variable::Float(l * r),
+                Div => Variable::Float(l / r),
+                Rem => Variable::Float(l % r),
+                _ => Variable::Empty,
+            },
+            _ => Variable::Empty,
+        },
+    }
+}
+
+#[test]
+fn test_expr() {
+    let lookup = |name: &str| match name {
+        "route" => Variable::Decimal(2),
+        "rate" => Variable::Float(0.5),
+        "name" => Variable::String("桐香".into()),
+        _ => Variable::Empty,
+    };
+    let eval = |source: &str| Expr::parse(source).unwrap().eval(&lookup);
+
+    assert_eq!(eval("1 + 2 * 3 - 4 / 2"), Variable::Decimal(5));
+    assert_eq!(eval("(1 + 2) * 3 % 4"), Variable::Decimal(1));
+    assert_eq!(eval("route * rate"), Variable::Float(1.0));
+    assert_eq!(eval("7 / 0"), Variable::Empty);
+    assert_eq!(eval("name + \"さん\""), Variable::String("桐香さん".into()));
+
+    assert!(eval("route == 2 && rate < 1").is_truthy());
+    assert!(eval("!undefined && undefined == empty").is_truthy());
+    assert!(!eval("route != 2 || name == 'その他'").is_truthy());
+    assert!(eval("-route < -1.5").is_truthy());
+
+    // written back into an equivalent expression
+    let expr = Expr::parse("!(a || b) && c >= -1.0 + \"q\\\"\"").unwrap();
+    assert_eq!(Expr::parse(&expr.to_string()).unwrap(), expr);
+
+    assert_eq!(Expr::parse("1 +"), Err(ExprError::UnexpectedEnd));
+    assert_eq!(Expr::parse("a = 1"), Err(ExprError::UnexpectedChar('=', 2)));
+}
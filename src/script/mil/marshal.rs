@@ -0,0 +1,644 @@
+//! Conversion between MIL commands and JSON values.
+//!
+//! Every command becomes an object with a `kind` field, such as
+//! `{ "kind": "layer.setOpacity", "layer": 1, "opacity": 0.5 }`. Commands
+//! without a JSON representation (animation graphs and unsupported rio
+//! commands) are passed as `{ "kind": "opaque", "ref": n }` and resolved
+//! against the program they came from.
+
+use miniserde::json::{Array, Number, Object, Value};
+use thiserror::Error;
+
+use super::command::{
+    self, BlendMode, ChoiceOption, Command, FaceEntry, LayerCommand, MmCommand, PassCommand,
+    RendererCommand, RuntimeCommand, SavedataCommand,
+};
+use super::expr::Expr;
+
+#[derive(Debug, Error)]
+pub enum MarshalError {
+    #[error("expected an object")]
+    NotAnObject,
+    #[error("missing or invalid field `{0}`")]
+    InvalidField(&'static str),
+    #[error("unknown command kind `{0}`")]
+    UnknownKind(String),
+    #[error("invalid opaque reference {0}")]
+    InvalidReference(usize),
+    #[error("no such layer: {0}")]
+    InvalidLayer(i32),
+}
+
+/// Keeps the opaque commands seen while marshalling.
+#[derive(Clone, Debug, Default)]
+pub struct Marshaller {
+    opaque: Vec<Command>,
+}
+
+fn string(v: &str) -> Value {
+    Value::String(v.into())
+}
+
+fn opt_string(v: &Option<String>) -> Value {
+    v.as_deref().map(string).unwrap_or(Value::Null)
+}
+
+// expressions are passed in their source form
+fn expr(v: &Expr) -> Value {
+    Value::String(v.to_string())
+}
+
+fn number(v: f64) -> Value {
+    Value::Number(Number::F64(v))
+}
+
+fn int(v: i32) -> Value {
+    Value::Number(Number::I64(v as i64))
+}
+
+fn ints(v: &[i32]) -> Value {
+    Value::Array(v.iter().copied().map(int).collect())
+}
+
+fn face(v: &FaceEntry) -> Value {
+    object(
+        "face",
+        vec![
+            ("filename", string(&v.filename)),
+            ("entries", ints(&v.entries)),
+        ],
+    )
+}
+
+fn choice(v: &ChoiceOption) -> Value {
+    object(
+        "choice",
+        vec![("text", string(&v.text)), ("label", string(&v.label))],
+    )
+}
+
+fn object(kind: &str, fields: Vec<(&str, Value)>) -> Value {
+    let mut object = Object::new();
+    object.insert("kind".into(), string(kind));
+
+    for (key, value) in fields {
+        object.insert(key.into(), value);
+    }
+
+    Value::Object(object)
+}
+
+// field accessors for decoding
+struct Fields<'a>(&'a Object);
+
+impl<'a> Fields<'a> {
+    fn get(&self, key: &'static str) -> Result<&'a Value, MarshalError> {
+        self.0.get(key).ok_or(MarshalError::InvalidField(key))
+    }
+
+    fn string(&self, key: &'static str) -> Result<String, MarshalError> {
+        match self.get(key)? {
+            Value::String(v) => Ok(v.clone()),
+            _ => Err(MarshalError::InvalidField(key)),
+        }
+    }
+
+    fn opt_string(&self, key: &'static str) -> Result<Option<String>, MarshalError> {
+        match self.0.get(key) {
+            None | Some(Value::Null) => Ok(None),
+            Some(_) => self.string(key).map(Some),
+        }
+    }
+
+    fn expr(&self, key: &'static str) -> Result<Expr, MarshalError> {
+        Expr::parse(&self.string(key)?).map_err(|_| MarshalError::InvalidField(key))
+    }
+
+    fn number(&self, key: &'static str) -> Result<f64, MarshalError> {
+        as_number(self.get(key)?).ok_or(MarshalError::InvalidField(key))
+    }
+
+    fn int(&self, key: &'static str) -> Result<i32, MarshalError> {
+        as_int(self.get(key)?).ok_or(MarshalError::InvalidField(key))
+    }
+
+    fn ints(&self, key: &'static str) -> Result<Vec<i32>, MarshalError> {
+        match self.get(key)? {
+            Value::Array(values) => values
+                .iter()
+                .map(as_int)
+                .collect::<Option<_>>()
+                .ok_or(MarshalError::InvalidField(key)),
+            _ => Err(MarshalError::InvalidField(key)),
+        }
+    }
+
+    fn bool(&self, key: &'static str) -> Result<bool, MarshalError> {
+        match self.get(key)? {
+            Value::Bool(v) => Ok(*v),
+            _ => Err(MarshalError::InvalidField(key)),
+        }
+    }
+
+    fn face(&self, key: &'static str) -> Result<Option<FaceEntry>, MarshalError> {
+        match self.0.get(key) {
+            None | Some(Value::Null) => Ok(None),
+            Some(Value::Object(face)) => {
+                let face = Fields(face);
+
+                Ok(Some(FaceEntry {
+                    filename: face.string("filename")?,
+                    entries: face.ints("entries")?,
+                }))
+            }
+            Some(_) => Err(MarshalError::InvalidField(key)),
+        }
+    }
+
+    fn choices(&self, key: &'static str) -> Result<Vec<ChoiceOption>, MarshalError> {
+        let values = match self.get(key)? {
+            Value::Array(values) => values,
+            _ => return Err(MarshalError::InvalidField(key)),
+        };
+
+        values
+            .iter()
+            .map(|v| match v {
+                Value::Object(option) => {
+                    let option = Fields(option);
+
+                    Ok(ChoiceOption {
+                        text: option.string("text")?,
+                        label: option.string("label")?,
+                    })
+                }
+                _ => Err(MarshalError::InvalidField(key)),
+            })
+            .collect()
+    }
+}
+
+fn as_number(v: &Value) -> Option<f64> {
+    match v {
+        Value::Number(Number::U64(v)) => Some(*v as f64),
+        Value::Number(Number::I64(v)) => Some(*v as f64),
+        Value::Number(Number::F64(v)) => Some(*v),
+        _ => None,
+    }
+}
+
+// integers only, neither truncated nor saturated
+fn as_int(v: &Value) -> Option<i32> {
+    let v = as_number(v)?;
+
+    if v.fract() != 0.0 || v < i32::MIN as f64 || v > i32::MAX as f64 {
+        return None;
+    }
+
+    Some(v as i32)
+}
+
+impl Marshaller {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn encode_all(&mut self, commands: &[Command]) -> Array {
+        commands.iter().map(|c| self.encode(c)).collect()
+    }
+
+    pub fn decode_all(&self, values: &[Value]) -> Result<Vec<Command>, (usize, MarshalError)> {
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, v)| self.decode(v).map_err(|err| (i, err)))
+            .collect()
+    }
+
+    fn opaque(&mut self, command: &Command) -> Value {
+        self.opaque.push(command.clone());
+        object("opaque", vec![("ref", int(self.opaque.len() as i32 - 1))])
+    }
+
+    pub fn encode(&mut self, command: &Command) -> Value {
+        match command {
+            Command::LayerCommand { layer_no, command } => {
+                let (kind, mut fields) = match command {
+                    LayerCommand::Load(filename, entries) => (
+                        "layer.load",
+                        vec![("filename", string(filename)), ("entries", ints(entries))],
+                    ),
+                    LayerCommand::Unload => ("layer.unload", vec![]),
+                    LayerCommand::Prefetch(filename, entries) => (
+                        "layer.prefetch",
+                        vec![("filename", string(filename)), ("entries", ints(entries))],
+                    ),
+                    LayerCommand::SetPosition(x, y) => (
+                        "layer.setPosition",
+                        vec![("x", number(*x)), ("y", number(*y))],
+                    ),
+                    LayerCommand::SetOpacity(v) => {
+                        ("layer.setOpacity", vec![("opacity", number(*v))])
+                    }
+                    LayerCommand::SetBlurRate(x, y) => {
+                        ("layer.setBlurRate", vec![("x", int(*x)), ("y", int(*y))])
+                    }
+                    LayerCommand::AnimateBlurRate(x, y, duration) => (
+                        "layer.animateBlurRate",
+                        vec![
+                            ("x", int(*x)),
+                            ("y", int(*y)),
+                            ("duration", number(*duration)),
+                        ],
+                    ),
+                    LayerCommand::SetBlendMode(mode) => {
+                        ("layer.setBlendMode", vec![("mode", string(mode.name()))])
+                    }
+                    LayerCommand::LoadOverlay(filename, entry, mode) => (
+                        "layer.loadOverlay",
+                        vec![
+                            ("filename", string(filename)),
+                            ("entry", int(*entry)),
+                            ("mode", int(*mode)),
+                        ],
+                    ),
+                    LayerCommand::UnloadOverlay => ("layer.unloadOverlay", vec![]),
+                    LayerCommand::SetOverlayRate(v) => {
+                        ("layer.setOverlayRate", vec![("rate", number(*v))])
+                    }
+                    LayerCommand::WaitUntilAnimationIsDone => {
+                        ("layer.waitUntilAnimationIsDone", vec![])
+                    }
+                    LayerCommand::FinalizeAnimation => ("layer.finalizeAnimation", vec![]),
+                    LayerCommand::LayerDelay(v) => ("layer.delay", vec![("duration", number(*v))]),
+                    LayerCommand::LoadAnimationGraph(_) => {
+                        return self.opaque(&Command::LayerCommand {
+                            layer_no: *layer_no,
+                            command: command.clone(),
+                        });
+                    }
+                };
+
+                fields.push(("layer", int(*layer_no)));
+                object(kind, fields)
+            }
+            Command::RendererCommand(command) => match command {
+                RendererCommand::LoadOverlay(filename, entry, mode) => object(
+                    "renderer.loadOverlay",
+                    vec![
+                        ("filename", string(filename)),
+                        ("entry", int(*entry)),
+                        ("mode", int(*mode)),
+                    ],
+                ),
+                RendererCommand::UnloadOverlay => object("renderer.unloadOverlay", vec![]),
+                RendererCommand::SetOverlayRate(v) => {
+                    object("renderer.setOverlayRate", vec![("rate", number(*v))])
+                }
+                RendererCommand::PushScreen => object("renderer.pushScreen", vec![]),
+                RendererCommand::ClearFace => object("renderer.clearFace", vec![]),
+                RendererCommand::PushFace(entry) => object(
+                    "renderer.pushFace",
+                    vec![
+                        ("filename", string(&entry.filename)),
+                        ("entries", ints(&entry.entries)),
+                    ],
+                ),
+                RendererCommand::Dialogue(name, text) => object(
+                    "renderer.dialogue",
+                    vec![("name", opt_string(name)), ("text", string(text))],
+                ),
+                RendererCommand::LayerPriorityClear => {
+                    object("renderer.layerPriorityClear", vec![])
+                }
+                RendererCommand::LayerPriority(layers) => {
+                    object("renderer.layerPriority", vec![("layers", ints(layers))])
+                }
+                RendererCommand::Draw => object("renderer.draw", vec![]),
+            },
+            Command::RuntimeCommand(command) => match command {
+                RuntimeCommand::Wait(v) => object("runtime.wait", vec![("duration", number(*v))]),
+                RuntimeCommand::WaitUntilUserEvent => object("runtime.waitUntilUserEvent", vec![]),
+                RuntimeCommand::SetFlag(name, value) => object(
+                    "runtime.setFlag",
+                    vec![("name", string(name)), ("value", number(*value))],
+                ),
+                RuntimeCommand::SetVariable(name, value) => object(
+                    "runtime.setVariable",
+                    vec![("name", string(name)), ("value", expr(value))],
+                ),
+                RuntimeCommand::Test(condition) => {
+                    object("runtime.test", vec![("condition", expr(condition))])
+                }
+                RuntimeCommand::Label(label) => {
+                    object("runtime.label", vec![("label", string(label))])
+                }
+                RuntimeCommand::Branch(label) => {
+                    object("runtime.branch", vec![("label", string(label))])
+                }
+                RuntimeCommand::BranchUnless(label) => {
+                    object("runtime.branchUnless", vec![("label", string(label))])
+                }
+                RuntimeCommand::Call { scenario, label } => object(
+                    "runtime.call",
+                    vec![("scenario", string(scenario)), ("label", opt_string(label))],
+                ),
+                RuntimeCommand::Return => object("runtime.return", vec![]),
+                RuntimeCommand::Choice(options) => object(
+                    "runtime.choice",
+                    vec![(
+                        "options",
+                        Value::Array(options.iter().map(choice).collect()),
+                    )],
+                ),
+            },
+            Command::MmCommand(command) => match command {
+                MmCommand::PlayMovie(filename) => {
+                    object("mm.playMovie", vec![("filename", string(filename))])
+                }
+                MmCommand::PlaySE(channel, filename) => object(
+                    "mm.playSE",
+                    vec![("channel", int(*channel)), ("filename", string(filename))],
+                ),
+                MmCommand::PlayVoice(filename) => {
+                    object("mm.playVoice", vec![("filename", string(filename))])
+                }
+                MmCommand::PlayMusic {
+                    filename,
+                    is_looped,
+                } => object(
+                    "mm.playMusic",
+                    vec![
+                        ("filename", string(filename)),
+                        ("looped", Value::Bool(*is_looped)),
+                    ],
+                ),
+                MmCommand::FadeSE(channel, duration) => object(
+                    "mm.fadeSE",
+                    vec![("channel", int(*channel)), ("duration", number(*duration))],
+                ),
+                MmCommand::FadeMusic(duration) => {
+                    object("mm.fadeMusic", vec![("duration", number(*duration))])
+                }
+            },
+            Command::SavedataCommand(command) => match command {
+                SavedataCommand::AddLogEntry {
+                    name,
+                    face: entry,
+                    text,
+                    voice,
+                } => object(
+                    "savedata.addLogEntry",
+                    vec![
+                        ("name", opt_string(name)),
+                        ("face", entry.as_ref().map(face).unwrap_or(Value::Null)),
+                        ("text", string(text)),
+                        ("voice", opt_string(voice)),
+                    ],
+                ),
+                SavedataCommand::QuickSave => object("savedata.quickSave", vec![]),
+                SavedataCommand::QuickLoad => object("savedata.quickLoad", vec![]),
+                SavedataCommand::Save(n) => object("savedata.save", vec![("slot", int(*n))]),
+                SavedataCommand::Load(n) => object("savedata.load", vec![("slot", int(*n))]),
+                SavedataCommand::BackupSave => object("savedata.backupSave", vec![]),
+                SavedataCommand::BackupLoadIfAvailable => {
+                    object("savedata.backupLoadIfAvailable", vec![])
+                }
+            },
+            Command::PassCommand(command) => match command {
+                PassCommand::FaceAuto(v) => {
+                    object("pass.faceAuto", vec![("enabled", Value::Bool(*v))])
+                }
+                PassCommand::AddEntry => object("pass.addEntry", vec![]),
+            },
+            Command::UnsupportedCommand(_) => self.opaque(command),
+        }
+    }
+
+    pub fn decode(&self, value: &Value) -> Result<Command, MarshalError> {
+        let object = match value {
+            Value::Object(object) => object,
+            _ => return Err(MarshalError::NotAnObject),
+        };
+        let f = Fields(object);
+        let kind = f.string("kind")?;
+
+        let layer = |command| -> Result<Command, MarshalError> {
+            let layer_no = f.int("layer")?;
+
+            if command::layer_index(layer_no).is_none() {
+                return Err(MarshalError::InvalidLayer(layer_no));
+            }
+
+            Ok(Command::LayerCommand { layer_no, command })
+        };
+
+        let command = match kind.as_str() {
+            "opaque" => {
+                let index = f.int("ref")?.max(0) as usize;
+
+                return self
+                    .opaque
+                    .get(index)
+                    .cloned()
+                    .ok_or(MarshalError::InvalidReference(index));
+            }
+
+            "layer.load" => layer(LayerCommand::Load(
+                f.string("filename")?,
+                f.ints("entries")?,
+            ))?,
+            "layer.unload" => layer(LayerCommand::Unload)?,
+            "layer.prefetch" => layer(LayerCommand::Prefetch(
+                f.string("filename")?,
+                f.ints("entries")?,
+            ))?,
+            "layer.setPosition" => {
+                layer(LayerCommand::SetPosition(f.number("x")?, f.number("y")?))?
+            }
+            "layer.setOpacity" => layer(LayerCommand::SetOpacity(f.number("opacity")?))?,
+            "layer.setBlurRate" => layer(LayerCommand::SetBlurRate(f.int("x")?, f.int("y")?))?,
+            "layer.animateBlurRate" => layer(LayerCommand::AnimateBlurRate(
+                f.int("x")?,
+                f.int("y")?,
+                f.number("duration")?,
+            ))?,
+            "layer.setBlendMode" => layer(LayerCommand::SetBlendMode(
+                BlendMode::from_name(&f.string("mode")?)
+                    .ok_or(MarshalError::InvalidField("mode"))?,
+            ))?,
+            "layer.loadOverlay" => layer(LayerCommand::LoadOverlay(
+                f.string("filename")?,
+                f.int("entry")?,
+                f.int("mode")?,
+            ))?,
+            "layer.unloadOverlay" => layer(LayerCommand::UnloadOverlay)?,
+            "layer.setOverlayRate" => layer(LayerCommand::SetOverlayRate(f.number("rate")?))?,
+            "layer.waitUntilAnimationIsDone" => layer(LayerCommand::WaitUntilAnimationIsDone)?,
+            "layer.finalizeAnimation" => layer(LayerCommand::FinalizeAnimation)?,
+            "layer.delay" => layer(LayerCommand::LayerDelay(f.number("duration")?))?,
+
+            "renderer.loadOverlay" => Command::RendererCommand(RendererCommand::LoadOverlay(
+                f.string("filename")?,
+                f.int("entry")?,
+                f.int("mode")?,
+            )),
+            "renderer.unloadOverlay" => Command::RendererCommand(RendererCommand::UnloadOverlay),
+            "renderer.setOverlayRate" => {
+                Command::RendererCommand(RendererCommand::SetOverlayRate(f.number("rate")?))
+            }
+            "renderer.pushScreen" => Command::RendererCommand(RendererCommand::PushScreen),
+            "renderer.clearFace" => Command::RendererCommand(RendererCommand::ClearFace),
+            "renderer.pushFace" => Command::RendererCommand(RendererCommand::PushFace(FaceEntry {
+                filename: f.string("filename")?,
+                entries: f.ints("entries")?,
+            })),
+            "renderer.dialogue" => Command::RendererCommand(RendererCommand::Dialogue(
+                f.opt_string("name")?,
+                f.string("text")?,
+            )),
+            "renderer.layerPriorityClear" => {
+                Command::RendererCommand(RendererCommand::LayerPriorityClear)
+            }
+            "renderer.layerPriority" => {
+                Command::RendererCommand(RendererCommand::LayerPriority(f.ints("layers")?))
+            }
+            "renderer.draw" => Command::RendererCommand(RendererCommand::Draw),
+
+            "runtime.wait" => Command::RuntimeCommand(RuntimeCommand::Wait(f.number("duration")?)),
+            "runtime.waitUntilUserEvent" => {
+                Command::RuntimeCommand(RuntimeCommand::WaitUntilUserEvent)
+            }
+            "runtime.setFlag" => Command::RuntimeCommand(RuntimeCommand::SetFlag(
+                f.string("name")?,
+                f.number("value")?,
+            )),
+            "runtime.setVariable" => Command::RuntimeCommand(RuntimeCommand::SetVariable(
+                f.string("name")?,
+                f.expr("value")?,
+            )),
+            "runtime.test" => Command::RuntimeCommand(RuntimeCommand::Test(f.expr("condition")?)),
+            "runtime.label" => Command::RuntimeCommand(RuntimeCommand::Label(f.string("label")?)),
+            "runtime.branch" => Command::RuntimeCommand(RuntimeCommand::Branch(f.string("label")?)),
+            "runtime.branchUnless" => {
+                Command::RuntimeCommand(RuntimeCommand::BranchUnless(f.string("label")?))
+            }
+            "runtime.call" => Command::RuntimeCommand(RuntimeCommand::Call {
+                scenario: f.string("scenario")?,
+                label: f.opt_string("label")?,
+            }),
+            "runtime.return" => Command::RuntimeCommand(RuntimeCommand::Return),
+            "runtime.choice" => {
+                Command::RuntimeCommand(RuntimeCommand::Choice(f.choices("options")?))
+            }
+
+            "mm.playMovie" => Command::MmCommand(MmCommand::PlayMovie(f.string("filename")?)),
+            "mm.playSE" => {
+                Command::MmCommand(MmCommand::PlaySE(f.int("channel")?, f.string("filename")?))
+            }
+            "mm.playVoice" => Command::MmCommand(MmCommand::PlayVoice(f.string("filename")?)),
+            "mm.playMusic" => Command::MmCommand(MmCommand::PlayMusic {
+                filename: f.string("filename")?,
+                is_looped: f.bool("looped")?,
+            }),
+            "mm.fadeSE" => {
+                Command::MmCommand(MmCommand::FadeSE(f.int("channel")?, f.number("duration")?))
+            }
+            "mm.fadeMusic" => Command::MmCommand(MmCommand::FadeMusic(f.number("duration")?)),
+
+            "savedata.addLogEntry" => Command::SavedataCommand(SavedataCommand::AddLogEntry {
+                name: f.opt_string("name")?,
+                face: f.face("face")?,
+                text: f.string("text")?,
+                voice: f.opt_string("voice")?,
+            }),
+            "savedata.quickSave" => Command::SavedataCommand(SavedataCommand::QuickSave),
+            "savedata.quickLoad" => Command::SavedataCommand(SavedataCommand::QuickLoad),
+            "savedata.save" => Command::SavedataCommand(SavedataCommand::Save(f.int("slot")?)),
+            "savedata.load" => Command::SavedataCommand(SavedataCommand::Load(f.int("slot")?)),
+            "savedata.backupSave" => Command::SavedataCommand(SavedataCommand::BackupSave),
+            "savedata.backupLoadIfAvailable" => {
+                Command::SavedataCommand(SavedataCommand::BackupLoadIfAvailable)
+            }
+
+            "pass.faceAuto" => Command::PassCommand(PassCommand::FaceAuto(f.bool("enabled")?)),
+            "pass.addEntry" => Command::PassCommand(PassCommand::AddEntry),
+
+            _ => return Err(MarshalError::UnknownKind(kind)),
+        };
+
+        Ok(command)
+    }
+}
+
+#[test]
+fn test_marshal_roundtrip() {
+    use crate::script::rio::command::Command as RioCommand;
+    use miniserde::json;
+
+    let commands = vec![
+        Command::LayerCommand {
+            layer_no: 3,
+            command: LayerCommand::Load("BG01".into(), vec![0, -1]),
+        },
+        Command::LayerCommand {
+            layer_no: 3,
+            command: LayerCommand::SetBlendMode(BlendMode::Multiply),
+        },
+        Command::LayerCommand {
+            layer_no: 3,
+            command: LayerCommand::AnimateBlurRate(8, 4, 500.0),
+        },
+        Command::RendererCommand(RendererCommand::Dialogue(None, "text".into())),
+        Command::MmCommand(MmCommand::PlayMusic {
+            filename: "BGM01".into(),
+            is_looped: true,
+        }),
+        Command::RuntimeCommand(RuntimeCommand::SetVariable(
+            "route".into(),
+            Expr::parse("route + 1").unwrap(),
+        )),
+        Command::RuntimeCommand(RuntimeCommand::Choice(vec![ChoiceOption {
+            text: "はい".into(),
+            label: "10".into(),
+        }])),
+        Command::UnsupportedCommand(RioCommand::LPriorityClear),
+    ];
+
+    let mut marshaller = Marshaller::new();
+    let array = marshaller.encode_all(&commands);
+
+    // through the JSON text, as JS passes do
+    let text = json::to_string(&Value::Array(array));
+    let array: Vec<Value> = json::from_str(&text).unwrap();
+    let restored = marshaller.decode_all(&array).unwrap();
+
+    assert_eq!(format!("{:?}", restored), format!("{:?}", commands));
+
+    let broken: Value = json::from_str(r#"{"kind":"layer.load","filename":"BG01"}"#).unwrap();
+    assert!(matches!(
+        marshaller.decode(&broken),
+        Err(MarshalError::InvalidField("entries"))
+    ));
+
+    for entries in &["[1.5]", "[4294967296]"] {
+        let text = format!(
+            r#"{{"kind":"layer.load","layer":1,"filename":"BG01","entries":{}}}"#,
+            entries
+        );
+        let broken: Value = json::from_str(&text).unwrap();
+        assert!(matches!(
+            marshaller.decode(&broken),
+            Err(MarshalError::InvalidField("entries"))
+        ));
+    }
+
+    for layer in &["-1", "30"] {
+        let text = format!(r#"{{"kind":"layer.unload","layer":{}}}"#, layer);
+        let broken: Value = json::from_str(&text).unwrap();
+        assert!(matches!(
+            marshaller.decode(&broken),
+            Err(MarshalError::InvalidLayer(_))
+        ));
+    }
+}
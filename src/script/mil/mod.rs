@@ -3,4 +3,6 @@
 //! Transpiled from RioScript and shared between all graphic backends.
 
 pub mod command;
+pub mod expr;
+pub mod marshal;
 pub mod pass;
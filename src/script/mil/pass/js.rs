@@ -0,0 +1,68 @@
+//! passes written in JavaScript
+//!
+//! The script defines `process(commands)`, which receives the program as an
+//! array of command objects (see `mil::marshal`) and returns the new one.
+
+use miniserde::json::{self, Value};
+
+use super::Pass;
+use crate::script::mil::command::Command;
+use crate::script::mil::marshal::Marshaller;
+use crate::script::runtime::js::JsRuntime;
+
+pub struct JsPass<'a> {
+    runtime: &'a mut JsRuntime,
+    name: String,
+    source: String,
+}
+
+impl<'a> JsPass<'a> {
+    /// `name` is the script name shown in stack traces.
+    pub fn new<N, S>(runtime: &'a mut JsRuntime, name: N, source: S) -> Self
+    where
+        N: Into<String>,
+        S: Into<String>,
+    {
+        Self {
+            runtime,
+            name: name.into(),
+            source: source.into(),
+        }
+    }
+}
+
+impl Pass for JsPass<'_> {
+    /// Returns the program unchanged if the script fails or returns an
+    /// invalid command.
+    fn process(self, commands: Vec<Command>) -> Vec<Command> {
+        let mut marshaller = Marshaller::new();
+        let input = json::to_string(&Value::Array(marshaller.encode_all(&commands)));
+
+        let output = match self
+            .runtime
+            .call_json(&self.name, &self.source, "process", &input)
+        {
+            Ok(output) => output,
+            Err(err) => {
+                log::error!("JS pass {} failed: {}", self.name, err);
+                return commands;
+            }
+        };
+
+        let output = match json::from_str(&output) {
+            Ok(Value::Array(output)) => output,
+            _ => {
+                log::error!("JS pass must return an array of commands");
+                return commands;
+            }
+        };
+
+        match marshaller.decode_all(&output) {
+            Ok(output) => output,
+            Err((i, err)) => {
+                log::error!("JS pass returned an invalid command at {}: {}", i, err);
+                commands
+            }
+        }
+    }
+}
@@ -1,7 +1,12 @@
 //! generates log entry
+//!
+//! Replaces `PassCommand::AddEntry` with `SavedataCommand::AddLogEntry`,
+//! pairing the dialogue with the voice and the face shown along with it.
 
 use super::Pass;
-use crate::script::mil::command::Command;
+use crate::script::mil::command::{
+    Command, MmCommand, PassCommand, RendererCommand, RuntimeCommand, SavedataCommand,
+};
 
 #[derive(Clone, Debug, Default)]
 pub struct LogEntryPass;
@@ -14,6 +19,86 @@ impl LogEntryPass {
 
 impl Pass for LogEntryPass {
     fn process(self, command: Vec<Command>) -> Vec<Command> {
+        let mut dialogue = None;
+        let mut voice = None;
+        let mut face = None;
+
         command
+            .into_iter()
+            .filter_map(|cmd| match cmd {
+                Command::PassCommand(PassCommand::AddEntry) => {
+                    let (name, text) = dialogue.take()?;
+
+                    Some(Command::SavedataCommand(SavedataCommand::AddLogEntry {
+                        name,
+                        face: face.clone(),
+                        text,
+                        voice: voice.take(),
+                    }))
+                }
+                Command::RendererCommand(RendererCommand::Dialogue(ref name, ref text)) => {
+                    dialogue = Some((name.clone(), text.clone()));
+                    Some(cmd)
+                }
+                Command::MmCommand(MmCommand::PlayVoice(ref filename)) => {
+                    voice = Some(filename.clone());
+                    Some(cmd)
+                }
+                Command::RendererCommand(RendererCommand::PushFace(ref entry)) => {
+                    face = Some(entry.clone());
+                    Some(cmd)
+                }
+                Command::RendererCommand(RendererCommand::ClearFace) => {
+                    face = None;
+                    Some(cmd)
+                }
+                Command::RuntimeCommand(RuntimeCommand::WaitUntilUserEvent) => {
+                    // voices never carry over to the next line
+                    voice = None;
+                    Some(cmd)
+                }
+                _ => Some(cmd),
+            })
+            .collect()
     }
 }
+
+#[test]
+fn test_log_entry_pass() {
+    let commands = vec![
+        Command::MmCommand(MmCommand::PlayVoice("TOU0001".into())),
+        Command::RendererCommand(RendererCommand::Dialogue(
+            Some("桐香".into()),
+            "text".into(),
+        )),
+        Command::PassCommand(PassCommand::AddEntry),
+        Command::RuntimeCommand(RuntimeCommand::WaitUntilUserEvent),
+        Command::RendererCommand(RendererCommand::Dialogue(None, "narration".into())),
+        Command::PassCommand(PassCommand::AddEntry),
+        Command::RuntimeCommand(RuntimeCommand::WaitUntilUserEvent),
+    ];
+
+    let entries: Vec<_> = LogEntryPass::new()
+        .process(commands)
+        .into_iter()
+        .filter_map(|cmd| match cmd {
+            Command::SavedataCommand(SavedataCommand::AddLogEntry {
+                name, text, voice, ..
+            }) => Some((name, text, voice)),
+            Command::PassCommand(_) => panic!("pass command should be removed"),
+            _ => None,
+        })
+        .collect();
+
+    assert_eq!(
+        entries,
+        vec![
+            (
+                Some("桐香".to_string()),
+                "text".to_string(),
+                Some("TOU0001".to_string())
+            ),
+            (None, "narration".to_string(), None),
+        ]
+    );
+}
@@ -1,6 +1,7 @@
 //! Passes and optimizers.
 
 pub mod autoface;
+pub mod js;
 pub mod log_entry;
 pub mod prefetch;
 
@@ -1,5 +1,6 @@
 //! Script execution engine.
 
+pub mod loader;
 pub mod mil;
 pub mod rio;
 pub mod runtime;
@@ -122,7 +122,7 @@ pub enum Command {
         unknown: i32,
     },
     Label {
-        unknown: i32,
+        id: i32,
     },
     Movie {
         filename: String,
@@ -136,6 +136,33 @@ pub enum Command {
     GlEffect {
         unknown: Option<i32>,
     },
+    // Variables
+    Set {
+        name: String,
+        value: String,
+    },
+    If {
+        condition: String,
+    },
+    Else,
+    EndIf,
+    // Control flow
+    Jump {
+        label: String,
+    },
+    JumpIf {
+        label: String,
+        condition: String,
+    },
+    Call {
+        scenario: String,
+        label: Option<String>,
+    },
+    Return,
+    Select {
+        // text and label
+        options: Vec<(String, String)>,
+    },
     Unknown,
     Facet,
 }
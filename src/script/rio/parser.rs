@@ -62,7 +62,12 @@ where
 
             if cmd.starts_with('$') {
                 // it's a command!
-                let cmd: String = cmd.trim_end().chars().filter(|c| c.is_ascii()).collect();
+                let cmd: String = if cmd.starts_with("$SELECT,") {
+                    // the options are in Japanese
+                    cmd.trim_end().into()
+                } else {
+                    cmd.trim_end().chars().filter(|c| c.is_ascii()).collect()
+                };
                 commands.push(self.visit_command(&cmd));
                 continue;
             } else if cmd.starts_with(';') {
@@ -147,12 +152,35 @@ impl<R> Parser<R> {
             "$EFECT" => self.visit_effect(&args[1..]),
             "$GLEFECT" => self.visit_gleffect(&args[1..]),
             "$FACET" => Command::Facet,
+            "$SET" => self.visit_set(&args[1..]),
+            "$IF" => self.visit_if(&args[1..]),
+            "$ELSE" => Command::Else,
+            "$ENDIF" => Command::EndIf,
+            "$JUMP" => self.visit_jump(&args[1..]),
+            "$JUMP_IF" => self.visit_jump_if(&args[1..]),
+            "$CALL" => self.visit_call(&args[1..]),
+            "$RETURN" => Command::Return,
+            "$SELECT" => self.visit_select(&args[1..]),
             _ => {
                 panic!("unrecognized command: {}", args[0]);
             }
         }
     }
 
+    // expressions may contain commas
+    fn visit_set(&self, args: &[&str]) -> Command {
+        Command::Set {
+            name: args[0].trim().into(),
+            value: args[1..].join(","),
+        }
+    }
+
+    fn visit_if(&self, args: &[&str]) -> Command {
+        Command::If {
+            condition: args.join(","),
+        }
+    }
+
     fn visit_regmsg(&self, args: &[&str]) -> Command {
         Command::RegMsg {
             unknown: args[0].parse().unwrap(),
@@ -193,7 +221,37 @@ impl<R> Parser<R> {
 
     fn visit_label(&self, args: &[&str]) -> Command {
         Command::Label {
-            unknown: args[0].parse().unwrap(),
+            id: args[0].parse().unwrap(),
+        }
+    }
+
+    fn visit_jump(&self, args: &[&str]) -> Command {
+        Command::Jump {
+            label: args[0].trim().into(),
+        }
+    }
+
+    fn visit_jump_if(&self, args: &[&str]) -> Command {
+        Command::JumpIf {
+            label: args[0].trim().into(),
+            condition: args[1..].join(","),
+        }
+    }
+
+    fn visit_call(&self, args: &[&str]) -> Command {
+        Command::Call {
+            scenario: args[0].trim().into(),
+            label: args.get(1).map(|v| v.trim().into()),
+        }
+    }
+
+    fn visit_select(&self, args: &[&str]) -> Command {
+        Command::Select {
+            options: args
+                .chunks(2)
+                .filter(|v| v.len() == 2)
+                .map(|v| (v[0].into(), v[1].trim().into()))
+                .collect(),
         }
     }
 
@@ -427,24 +485,40 @@ fn parse_rio_script() {
 }
 
 #[test]
-fn parse_all_rio_script() {
-    for d in std::fs::read_dir("./blob/___t.WAR") {
-        for e in d {
-            if let Ok(entry) = e {
-                if entry.metadata().unwrap().is_dir() {
-                    continue;
-                }
-
-                let path = entry.path();
-                if let Some(extension) = path.extension() {
-                    if extension == "TXT" {
-                        println!("{:?}", path);
-
-                        let mut parser = Parser::open(path).unwrap();
-                        println!("{:#?}", parser.parse());
-                    }
-                }
-            }
-        }
-    }
+fn parse_control_flow() {
+    let source = concat!(
+        "$LABEL,10\n",
+        "$JUMP,20\n",
+        "$JUMP_IF,30,route == 1, 2\n",
+        "$CALL,SUB\n",
+        "$CALL,SUB,40\n",
+        "$RETURN\n",
+        "$SELECT,はい,50,いいえ,60\n",
+    );
+    let commands = Parser::from_raw_bytes(source.as_bytes()).parse().unwrap();
+
+    assert_eq!(
+        commands,
+        [
+            Command::Label { id: 10 },
+            Command::Jump { label: "20".into() },
+            // conditions may contain commas
+            Command::JumpIf {
+                label: "30".into(),
+                condition: "route == 1, 2".into(),
+            },
+            Command::Call {
+                scenario: "SUB".into(),
+                label: None,
+            },
+            Command::Call {
+                scenario: "SUB".into(),
+                label: Some("40".into()),
+            },
+            Command::Return,
+            Command::Select {
+                options: vec![("はい".into(), "50".into()), ("いいえ".into(), "60".into()),],
+            },
+        ]
+    );
 }
@@ -1,13 +1,17 @@
 use super::command::Command;
 use crate::script::mil::command::{
-    Command as MilCommand, FaceEntry, LayerCommand, MmCommand, PassCommand, RendererCommand,
-    RuntimeCommand,
+    ChoiceOption, Command as MilCommand, FaceEntry, LayerCommand, MmCommand, PassCommand,
+    RendererCommand, RuntimeCommand,
 };
+use crate::script::mil::expr::{Expr, UnaryOp, Variable};
 
 #[derive(Clone, Debug, Default)]
 pub struct Transpiler {
     commands: Vec<Command>,
     transpiled: Vec<MilCommand>,
+    // open `$IF` blocks; id and whether `$ELSE` has been seen
+    branches: Vec<(usize, bool)>,
+    branch_count: usize,
 }
 
 impl Transpiler {
@@ -81,7 +85,7 @@ impl Transpiler {
                 Command::RegMsg { unknown } => self.visit_regmsg(unknown),
                 Command::StrFlag { unknown } => self.visit_strflag(unknown),
                 Command::Window { unknown } => self.visit_window(unknown),
-                Command::Label { unknown } => self.visit_label(unknown),
+                Command::Label { id } => self.visit_label(id),
                 Command::Movie {
                     filename,
                     unknown,
@@ -89,11 +93,28 @@ impl Transpiler {
                 } => self.visit_movie(filename, unknown, unknown_1),
                 Command::Effect { unknown, unknown_1 } => self.visit_effect(unknown, unknown_1),
                 Command::GlEffect { unknown } => self.visit_gleffect(unknown),
+                Command::Set { name, value } => self.visit_set(name, value),
+                Command::If { condition } => self.visit_if(condition),
+                Command::Else => self.visit_else(),
+                Command::EndIf => self.visit_endif(),
+                Command::Jump { label } => self.visit_jump(label),
+                Command::JumpIf { label, condition } => self.visit_jump_if(label, condition),
+                Command::Call { scenario, label } => self.visit_call(scenario, label),
+                Command::Return => self.visit_return(),
+                Command::Select { options } => self.visit_select(options),
                 Command::Unknown => self.visit_unknown(),
                 Command::Facet => self.visit_facet(),
             }
         }
 
+        if !self.branches.is_empty() {
+            log::error!("$IF without $ENDIF");
+
+            while !self.branches.is_empty() {
+                self.visit_endif();
+            }
+        }
+
         // populate prefetch commands
 
         self.transpiled
@@ -104,6 +125,22 @@ impl Transpiler {
     fn send(&mut self, command: MilCommand) {
         self.transpiled.push(command);
     }
+
+    fn parse_expr(source: &str) -> Expr {
+        Expr::parse(source).unwrap_or_else(|err| {
+            log::error!("invalid expression `{}`: {}", source, err);
+            Expr::Literal(Variable::Empty)
+        })
+    }
+
+    // labels of `$IF` blocks; `#` never appears in the labels of scenarios
+    fn else_label(id: usize) -> String {
+        format!("#if{}.else", id)
+    }
+
+    fn end_label(id: usize) -> String {
+        format!("#if{}.end", id)
+    }
 }
 
 // visitors
@@ -287,8 +324,10 @@ impl Transpiler {
         log::error!("$WINDOW not implemented");
     }
 
-    fn visit_label(&mut self, _unknown: i32) {
-        log::error!("$LABEL not implemented");
+    fn visit_label(&mut self, id: i32) {
+        self.send(MilCommand::RuntimeCommand(RuntimeCommand::Label(
+            id.to_string(),
+        )));
     }
 
     fn visit_movie(&mut self, filename: String, _unknown: i32, _unknown_1: i32) {
@@ -303,6 +342,99 @@ impl Transpiler {
         log::error!("$GLEFFECT not implemented");
     }
 
+    fn visit_set(&mut self, name: String, value: String) {
+        self.send(MilCommand::RuntimeCommand(RuntimeCommand::SetVariable(
+            name,
+            Self::parse_expr(&value),
+        )));
+    }
+
+    fn visit_if(&mut self, condition: String) {
+        let id = self.branch_count;
+        self.branch_count += 1;
+        self.branches.push((id, false));
+
+        self.send(MilCommand::RuntimeCommand(RuntimeCommand::Test(
+            Self::parse_expr(&condition),
+        )));
+        self.send(MilCommand::RuntimeCommand(RuntimeCommand::BranchUnless(
+            Self::else_label(id),
+        )));
+    }
+
+    fn visit_else(&mut self) {
+        let id = match self.branches.last_mut() {
+            Some((id, has_else)) if !*has_else => {
+                *has_else = true;
+                *id
+            }
+            _ => {
+                log::error!("unexpected $ELSE");
+                return;
+            }
+        };
+
+        self.send(MilCommand::RuntimeCommand(RuntimeCommand::Branch(
+            Self::end_label(id),
+        )));
+        self.send(MilCommand::RuntimeCommand(RuntimeCommand::Label(
+            Self::else_label(id),
+        )));
+    }
+
+    fn visit_endif(&mut self) {
+        let (id, has_else) = match self.branches.pop() {
+            Some(branch) => branch,
+            None => {
+                log::error!("unexpected $ENDIF");
+                return;
+            }
+        };
+
+        if !has_else {
+            self.send(MilCommand::RuntimeCommand(RuntimeCommand::Label(
+                Self::else_label(id),
+            )));
+        }
+
+        self.send(MilCommand::RuntimeCommand(RuntimeCommand::Label(
+            Self::end_label(id),
+        )));
+    }
+
+    fn visit_jump(&mut self, label: String) {
+        self.send(MilCommand::RuntimeCommand(RuntimeCommand::Branch(label)));
+    }
+
+    fn visit_jump_if(&mut self, label: String, condition: String) {
+        let condition = Expr::Unary(UnaryOp::Not, Box::new(Self::parse_expr(&condition)));
+
+        self.send(MilCommand::RuntimeCommand(RuntimeCommand::Test(condition)));
+        self.send(MilCommand::RuntimeCommand(RuntimeCommand::BranchUnless(
+            label,
+        )));
+    }
+
+    fn visit_call(&mut self, scenario: String, label: Option<String>) {
+        self.send(MilCommand::RuntimeCommand(RuntimeCommand::Call {
+            scenario,
+            label,
+        }));
+    }
+
+    fn visit_return(&mut self) {
+        self.send(MilCommand::RuntimeCommand(RuntimeCommand::Return));
+    }
+
+    fn visit_select(&mut self, options: Vec<(String, String)>) {
+        let options = options
+            .into_iter()
+            .map(|(text, label)| ChoiceOption { text, label })
+            .collect();
+
+        self.send(MilCommand::RuntimeCommand(RuntimeCommand::Choice(options)));
+    }
+
     fn visit_unknown(&mut self) {}
 
     fn visit_facet(&mut self) {
@@ -311,29 +443,90 @@ impl Transpiler {
 }
 
 #[test]
-fn transpile_all_rio_script() {
-    use super::parser::Parser;
-
-    for d in std::fs::read_dir("./blob/___t.WAR") {
-        for e in d {
-            if let Ok(entry) = e {
-                if entry.metadata().unwrap().is_dir() {
-                    continue;
-                }
-
-                let path = entry.path();
-                if let Some(extension) = path.extension() {
-                    if extension == "TXT" {
-                        println!("{:?}", path);
-
-                        let mut parser = Parser::open(path).unwrap();
-                        let res = parser.parse().unwrap();
+fn transpile_branches() {
+    let commands = vec![
+        Command::Set {
+            name: "route".into(),
+            value: "route + 1".into(),
+        },
+        Command::If {
+            condition: "route == 1".into(),
+        },
+        Command::Wait { duration: 1.0 },
+        Command::Else,
+        Command::Wait { duration: 2.0 },
+        Command::EndIf,
+    ];
+
+    let transpiled: Vec<_> = Transpiler::new(commands)
+        .transpile()
+        .into_iter()
+        .map(|c| match c {
+            MilCommand::RuntimeCommand(c) => format!("{:?}", c),
+            _ => unreachable!(),
+        })
+        .collect();
+
+    assert_eq!(
+        transpiled[2..],
+        [
+            r##"BranchUnless("#if0.else")"##,
+            "Wait(1.0)",
+            r##"Branch("#if0.end")"##,
+            r##"Label("#if0.else")"##,
+            "Wait(2.0)",
+            r##"Label("#if0.end")"##,
+        ]
+    );
+}
 
-                        let transpiler = Transpiler::new(res);
-                        println!("{:#?}", transpiler.transpile());
-                    }
-                }
-            }
-        }
-    }
+#[test]
+fn transpile_control_flow() {
+    let commands = vec![
+        Command::Label { id: 10 },
+        Command::Jump { label: "10".into() },
+        Command::JumpIf {
+            label: "20".into(),
+            condition: "route == 1".into(),
+        },
+        Command::Call {
+            scenario: "SUB".into(),
+            label: Some("30".into()),
+        },
+        Command::Return,
+        Command::Select {
+            options: vec![("はい".into(), "40".into()), ("いいえ".into(), "50".into())],
+        },
+    ];
+
+    let transpiled: Vec<_> = Transpiler::new(commands)
+        .transpile()
+        .into_iter()
+        .map(|c| match c {
+            MilCommand::RuntimeCommand(c) => c,
+            _ => unreachable!(),
+        })
+        .collect();
+
+    assert_eq!(transpiled.len(), 7);
+    assert!(matches!(&transpiled[0], RuntimeCommand::Label(l) if l == "10"));
+    assert!(matches!(&transpiled[1], RuntimeCommand::Branch(l) if l == "10"));
+
+    // jumps if the condition holds, that is unless it does not
+    assert!(matches!(
+        &transpiled[2],
+        RuntimeCommand::Test(Expr::Unary(UnaryOp::Not, _))
+    ));
+    assert!(matches!(&transpiled[3], RuntimeCommand::BranchUnless(l) if l == "20"));
+
+    assert!(matches!(
+        &transpiled[4],
+        RuntimeCommand::Call { scenario, label: Some(label) } if scenario == "SUB" && label == "30"
+    ));
+    assert!(matches!(&transpiled[5], RuntimeCommand::Return));
+    assert!(matches!(
+        &transpiled[6],
+        RuntimeCommand::Choice(options)
+            if options.len() == 2 && options[0].text == "はい" && options[1].label == "50"
+    ));
 }
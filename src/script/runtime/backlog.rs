@@ -0,0 +1,92 @@
+//! Dialogue history.
+
+use miniserde::{Deserialize, Serialize};
+
+use std::collections::VecDeque;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LogEntry {
+    pub name: Option<String>,
+    pub text: String,
+    pub voice: Option<String>,
+}
+
+/// Bounded history of dialogue lines; the oldest entries are dropped first.
+#[derive(Clone, Debug)]
+pub struct Backlog {
+    entries: VecDeque<LogEntry>,
+    capacity: usize,
+}
+
+impl Backlog {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, entry: LogEntry) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        while self.entries.len() >= self.capacity {
+            self.entries.pop_front();
+        }
+
+        self.entries.push_back(entry);
+    }
+
+    /// Returns the `n`-th entry, counting from the newest one.
+    pub fn get_latest(&self, n: usize) -> Option<&LogEntry> {
+        self.entries.iter().rev().nth(n)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn to_vec(&self) -> Vec<LogEntry> {
+        self.entries.iter().cloned().collect()
+    }
+
+    /// Replaces the history, keeping the newest entries that fit.
+    pub fn restore(&mut self, entries: Vec<LogEntry>) {
+        self.entries.clear();
+
+        for e in entries {
+            self.push(e);
+        }
+    }
+}
+
+#[test]
+fn test_backlog_capacity() {
+    let entry = |text: &str| LogEntry {
+        name: None,
+        text: text.into(),
+        voice: None,
+    };
+
+    let mut backlog = Backlog::new(2);
+    backlog.push(entry("a"));
+    backlog.push(entry("b"));
+    backlog.push(entry("c"));
+
+    assert_eq!(backlog.len(), 2);
+    assert_eq!(backlog.get_latest(0), Some(&entry("c")));
+    assert_eq!(backlog.get_latest(1), Some(&entry("b")));
+    assert_eq!(backlog.get_latest(2), None);
+
+    backlog.restore(vec![entry("x"), entry("y"), entry("z")]);
+    assert_eq!(backlog.to_vec(), vec![entry("y"), entry("z")]);
+}
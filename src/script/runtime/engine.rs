@@ -0,0 +1,430 @@
+//! Engine API exposed to JavaScript as the global `engine` object.
+//!
+//! Every call is translated into MIL commands, which are queued in the
+//! isolate and taken by the runtime afterwards.
+
+use rusty_v8 as v8;
+use v8::{FunctionCallback, MapFnTo};
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::path::{Component, Path};
+
+use miniserde::json;
+
+use super::savedata::LayerState;
+use crate::config;
+
+use crate::script::mil::command::{
+    self, BlendMode, ChoiceOption, Command, LayerCommand, MmCommand, PassCommand, RendererCommand,
+    RuntimeCommand,
+};
+
+/// State shared with the callbacks; stored in an isolate slot.
+#[derive(Debug, Default)]
+pub struct EngineState {
+    pub commands: Vec<Command>,
+    pub flags: BTreeMap<String, f64>,
+    pub debug: DebugState,
+}
+
+/// Live game state for the debug console.
+#[derive(Debug, Default)]
+pub struct DebugState {
+    pub pc: usize,
+    pub program_len: usize,
+    pub layers: Vec<LayerState>,
+    /// Program counter requested by `engine.jump`.
+    pub jump: Option<usize>,
+}
+
+fn state<'a>(scope: &'a mut v8::HandleScope) -> &'a mut EngineState {
+    scope.get_slot_mut::<EngineState>().unwrap()
+}
+
+fn send(scope: &mut v8::HandleScope, command: Command) {
+    state(scope).commands.push(command);
+}
+
+fn throw_error(scope: &mut v8::HandleScope, message: &str) {
+    let message = v8::String::new(scope, message).unwrap();
+    let exception = v8::Exception::error(scope, message);
+    scope.throw_exception(exception);
+}
+
+fn layer_command(scope: &mut v8::HandleScope, layer_no: i32, command: LayerCommand) {
+    if command::layer_index(layer_no).is_none() {
+        throw_error(scope, &format!("no such layer: {}", layer_no));
+        return;
+    }
+
+    send(scope, Command::LayerCommand { layer_no, command });
+}
+
+// argument helpers
+
+fn int_arg(scope: &mut v8::HandleScope, args: &v8::FunctionCallbackArguments, i: i32) -> i32 {
+    args.get(i).int32_value(scope).unwrap_or_default()
+}
+
+fn number_arg(scope: &mut v8::HandleScope, args: &v8::FunctionCallbackArguments, i: i32) -> f64 {
+    args.get(i).number_value(scope).unwrap_or_default()
+}
+
+fn string_arg(
+    scope: &mut v8::HandleScope,
+    args: &v8::FunctionCallbackArguments,
+    i: i32,
+) -> Option<String> {
+    let value = args.get(i);
+
+    if value.is_null_or_undefined() {
+        return None;
+    }
+
+    Some(value.to_string(scope)?.to_rust_string_lossy(scope))
+}
+
+fn int_array_arg(
+    scope: &mut v8::HandleScope,
+    args: &v8::FunctionCallbackArguments,
+    i: i32,
+) -> Vec<i32> {
+    let array = match v8::Local::<v8::Array>::try_from(args.get(i)) {
+        Ok(array) => array,
+        Err(_) => return vec![],
+    };
+
+    (0..array.length())
+        .filter_map(|i| array.get_index(scope, i)?.int32_value(scope))
+        .collect()
+}
+
+// callbacks
+
+fn load_layer(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    _: v8::ReturnValue,
+) {
+    let layer_no = int_arg(scope, &args, 0);
+    let filename = string_arg(scope, &args, 1).unwrap_or_default();
+    let entries = int_array_arg(scope, &args, 2);
+
+    layer_command(scope, layer_no, LayerCommand::Load(filename, entries));
+}
+
+fn unload_layer(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    _: v8::ReturnValue,
+) {
+    let layer_no = int_arg(scope, &args, 0);
+    layer_command(scope, layer_no, LayerCommand::Unload);
+}
+
+fn set_layer_position(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    _: v8::ReturnValue,
+) {
+    let layer_no = int_arg(scope, &args, 0);
+    let x = number_arg(scope, &args, 1);
+    let y = number_arg(scope, &args, 2);
+
+    layer_command(scope, layer_no, LayerCommand::SetPosition(x, y));
+}
+
+fn set_layer_opacity(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    _: v8::ReturnValue,
+) {
+    let layer_no = int_arg(scope, &args, 0);
+    let opacity = number_arg(scope, &args, 1);
+
+    layer_command(scope, layer_no, LayerCommand::SetOpacity(opacity));
+}
+
+fn animate_layer_blur(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    _: v8::ReturnValue,
+) {
+    let layer_no = int_arg(scope, &args, 0);
+    let x = int_arg(scope, &args, 1);
+    let y = int_arg(scope, &args, 2);
+    let duration = number_arg(scope, &args, 3);
+
+    let command = LayerCommand::AnimateBlurRate(x, y, duration);
+    layer_command(scope, layer_no, command);
+}
+
+fn set_layer_blend_mode(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    _: v8::ReturnValue,
+) {
+    let layer_no = int_arg(scope, &args, 0);
+    let name = string_arg(scope, &args, 1).unwrap_or_default();
+
+    match BlendMode::from_name(&name) {
+        Some(mode) => layer_command(scope, layer_no, LayerCommand::SetBlendMode(mode)),
+        None => throw_error(scope, &format!("unknown blend mode: {}", name)),
+    }
+}
+
+fn dialogue(scope: &mut v8::HandleScope, args: v8::FunctionCallbackArguments, _: v8::ReturnValue) {
+    let name = string_arg(scope, &args, 0);
+    let text = string_arg(scope, &args, 1).unwrap_or_default();
+
+    // same as the transpiler does for rio dialogues
+    send(
+        scope,
+        Command::RendererCommand(RendererCommand::Dialogue(name, text)),
+    );
+    send(scope, Command::PassCommand(PassCommand::AddEntry));
+    send(
+        scope,
+        Command::RuntimeCommand(RuntimeCommand::WaitUntilUserEvent),
+    );
+}
+
+fn play_music(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    _: v8::ReturnValue,
+) {
+    let filename = string_arg(scope, &args, 0).unwrap_or_default();
+    let looped = args.get(1);
+    let is_looped = looped.is_undefined() || looped.boolean_value(scope);
+
+    send(
+        scope,
+        Command::MmCommand(MmCommand::PlayMusic {
+            filename,
+            is_looped,
+        }),
+    );
+}
+
+fn fade_music(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    _: v8::ReturnValue,
+) {
+    let duration = number_arg(scope, &args, 0);
+    send(scope, Command::MmCommand(MmCommand::FadeMusic(duration)));
+}
+
+fn play_voice(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    _: v8::ReturnValue,
+) {
+    let filename = string_arg(scope, &args, 0).unwrap_or_default();
+    send(scope, Command::MmCommand(MmCommand::PlayVoice(filename)));
+}
+
+fn wait(scope: &mut v8::HandleScope, args: v8::FunctionCallbackArguments, _: v8::ReturnValue) {
+    let duration = number_arg(scope, &args, 0);
+    send(
+        scope,
+        Command::RuntimeCommand(RuntimeCommand::Wait(duration)),
+    );
+}
+
+fn wait_for_click(
+    scope: &mut v8::HandleScope,
+    _: v8::FunctionCallbackArguments,
+    _: v8::ReturnValue,
+) {
+    send(
+        scope,
+        Command::RuntimeCommand(RuntimeCommand::WaitUntilUserEvent),
+    );
+}
+
+fn get_flag(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut retval: v8::ReturnValue,
+) {
+    let name = string_arg(scope, &args, 0).unwrap_or_default();
+    let value = state(scope).flags.get(&name).copied().unwrap_or_default();
+
+    retval.set(v8::Number::new(scope, value).into());
+}
+
+fn set_flag(scope: &mut v8::HandleScope, args: v8::FunctionCallbackArguments, _: v8::ReturnValue) {
+    let name = string_arg(scope, &args, 0).unwrap_or_default();
+    let value = number_arg(scope, &args, 1);
+
+    state(scope).flags.insert(name.clone(), value);
+    send(
+        scope,
+        Command::RuntimeCommand(RuntimeCommand::SetFlag(name, value)),
+    );
+}
+
+fn label(scope: &mut v8::HandleScope, args: v8::FunctionCallbackArguments, _: v8::ReturnValue) {
+    let label = string_arg(scope, &args, 0).unwrap_or_default();
+    send(scope, Command::RuntimeCommand(RuntimeCommand::Label(label)));
+}
+
+fn goto(scope: &mut v8::HandleScope, args: v8::FunctionCallbackArguments, _: v8::ReturnValue) {
+    let label = string_arg(scope, &args, 0).unwrap_or_default();
+    send(
+        scope,
+        Command::RuntimeCommand(RuntimeCommand::Branch(label)),
+    );
+}
+
+// options are `{ text, label }` objects
+fn choice(scope: &mut v8::HandleScope, args: v8::FunctionCallbackArguments, _: v8::ReturnValue) {
+    let array = match v8::Local::<v8::Array>::try_from(args.get(0)) {
+        Ok(array) => array,
+        Err(_) => return,
+    };
+
+    let mut options = vec![];
+
+    for i in 0..array.length() {
+        let option = match array
+            .get_index(scope, i)
+            .and_then(|v| v8::Local::<v8::Object>::try_from(v).ok())
+        {
+            Some(option) => option,
+            None => continue,
+        };
+
+        let mut field = |name: &str| {
+            let key = v8::String::new(scope, name).unwrap();
+            let value = option.get(scope, key.into())?;
+            Some(value.to_string(scope)?.to_rust_string_lossy(scope))
+        };
+
+        if let (Some(text), Some(label)) = (field("text"), field("label")) {
+            options.push(ChoiceOption { text, label });
+        }
+    }
+
+    send(
+        scope,
+        Command::RuntimeCommand(RuntimeCommand::Choice(options)),
+    );
+}
+
+fn log(scope: &mut v8::HandleScope, args: v8::FunctionCallbackArguments, _: v8::ReturnValue) {
+    let message = string_arg(scope, &args, 0).unwrap_or_default();
+    log::info!("[js] {}", message);
+}
+
+fn get_pc(
+    scope: &mut v8::HandleScope,
+    _: v8::FunctionCallbackArguments,
+    mut retval: v8::ReturnValue,
+) {
+    let pc = state(scope).debug.pc;
+    retval.set(v8::Number::new(scope, pc as f64).into());
+}
+
+fn get_program_length(
+    scope: &mut v8::HandleScope,
+    _: v8::FunctionCallbackArguments,
+    mut retval: v8::ReturnValue,
+) {
+    let len = state(scope).debug.program_len;
+    retval.set(v8::Number::new(scope, len as f64).into());
+}
+
+fn jump(scope: &mut v8::HandleScope, args: v8::FunctionCallbackArguments, _: v8::ReturnValue) {
+    let pc = int_arg(scope, &args, 0).max(0) as usize;
+    state(scope).debug.jump = Some(pc);
+}
+
+fn get_layers(
+    scope: &mut v8::HandleScope,
+    _: v8::FunctionCallbackArguments,
+    mut retval: v8::ReturnValue,
+) {
+    let layers = json::to_string(&state(scope).debug.layers);
+    let layers = v8::String::new(scope, &layers).unwrap();
+
+    if let Some(layers) = v8::json::parse(scope, layers) {
+        retval.set(layers);
+    }
+}
+
+// scripts have no other access to the filesystem; only relative paths inside
+// the asset root are allowed
+fn read_asset(
+    scope: &mut v8::HandleScope,
+    args: v8::FunctionCallbackArguments,
+    mut retval: v8::ReturnValue,
+) {
+    let path = string_arg(scope, &args, 0).unwrap_or_default();
+    let is_valid = !path.is_empty()
+        && Path::new(&path)
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)));
+
+    let text = if is_valid {
+        config::find_asset(&path).and_then(|p| std::fs::read_to_string(p).ok())
+    } else {
+        None
+    };
+
+    match text {
+        Some(text) => {
+            let text = v8::String::new(scope, &text).unwrap();
+            retval.set(text.into());
+        }
+        None => throw_error(scope, &format!("cannot read asset: {}", path)),
+    }
+}
+
+fn set_function(
+    scope: &mut v8::HandleScope,
+    object: v8::Local<v8::Object>,
+    name: &str,
+    callback: impl MapFnTo<FunctionCallback>,
+) {
+    let func = v8::Function::new(scope, callback).unwrap();
+    let key = v8::String::new(scope, name).unwrap();
+    object.set(scope, key.into(), func.into());
+}
+
+/// Installs the `engine` object into `global`.
+pub fn install(scope: &mut v8::HandleScope, global: v8::Local<v8::Object>) {
+    let engine = v8::Object::new(scope);
+
+    set_function(scope, engine, "loadLayer", load_layer);
+    set_function(scope, engine, "unloadLayer", unload_layer);
+    set_function(scope, engine, "setLayerPosition", set_layer_position);
+    set_function(scope, engine, "setLayerOpacity", set_layer_opacity);
+    set_function(scope, engine, "animateLayerBlur", animate_layer_blur);
+    set_function(scope, engine, "setLayerBlendMode", set_layer_blend_mode);
+    set_function(scope, engine, "dialogue", dialogue);
+    set_function(scope, engine, "playMusic", play_music);
+    set_function(scope, engine, "fadeMusic", fade_music);
+    set_function(scope, engine, "playVoice", play_voice);
+    set_function(scope, engine, "wait", wait);
+    set_function(scope, engine, "waitForClick", wait_for_click);
+    set_function(scope, engine, "getFlag", get_flag);
+    set_function(scope, engine, "setFlag", set_flag);
+    set_function(scope, engine, "label", label);
+    set_function(scope, engine, "goto", goto);
+    set_function(scope, engine, "choice", choice);
+    set_function(scope, engine, "readAsset", read_asset);
+    set_function(scope, engine, "log", log);
+
+    // debugging
+    set_function(scope, engine, "getPc", get_pc);
+    set_function(scope, engine, "getProgramLength", get_program_length);
+    set_function(scope, engine, "jump", jump);
+    set_function(scope, engine, "getLayers", get_layers);
+
+    let key = v8::String::new(scope, "engine").unwrap();
+    global.set(scope, key.into(), engine.into());
+}
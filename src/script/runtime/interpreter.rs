@@ -0,0 +1,507 @@
+//! MIL interpreter.
+//!
+//! Runs the control flow of a program, that is the variables, labels, calls
+//! and the scenario chain, and hands every other command over to its host.
+//! Shared between the game and the headless tools, so that all of them play
+//! the same story.
+
+use std::collections::HashMap;
+
+use crate::script::loader;
+use crate::script::mil::command::{Command, RendererCommand, RuntimeCommand};
+use crate::script::mil::expr::{Expr, Variable};
+use crate::script::runtime::js::JsRuntime;
+use crate::script::runtime::persistent::PersistentData;
+use crate::script::runtime::savedata::CallFrame;
+use crate::script::runtime::variable::{self, Variables};
+
+/// What the interpreter needs from the one running it.
+pub trait Host {
+    /// Loads `scenario` ready to run; JavaScript scenes see `variables`.
+    fn load(&mut self, scenario: &str, variables: &Variables) -> std::io::Result<Vec<Command>>;
+
+    /// Where the system variables live; without it, they are kept with the
+    /// others.
+    fn persistent(&mut self) -> Option<&mut PersistentData> {
+        None
+    }
+}
+
+impl<F> Host for F
+where
+    F: FnMut(&str, &Variables) -> std::io::Result<Vec<Command>>,
+{
+    fn load(&mut self, scenario: &str, variables: &Variables) -> std::io::Result<Vec<Command>> {
+        self(scenario, variables)
+    }
+}
+
+impl Host for JsRuntime {
+    fn load(&mut self, scenario: &str, variables: &Variables) -> std::io::Result<Vec<Command>> {
+        self.set_flags(variables.numbers());
+
+        let script = loader::load_scene(self, scenario)?;
+        Ok(loader::apply_passes(self, script))
+    }
+}
+
+#[derive(Default)]
+pub struct Interpreter {
+    scenario: String,
+    program: Vec<Command>,
+    labels: HashMap<String, usize>,
+    pc: usize,
+    variables: Variables,
+    // result of the last `RuntimeCommand::Test`
+    condition: bool,
+    call_stack: Vec<CallFrame>,
+    // scenarios in the order played
+    chain: Vec<String>,
+    // the story has reached its end
+    finished: bool,
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Runs `program`; `scenario` is its name, which calls resolve against.
+    pub fn with_program(scenario: &str, program: Vec<Command>) -> Self {
+        Self {
+            scenario: scenario.into(),
+            labels: loader::find_labels(&program),
+            program,
+            ..Default::default()
+        }
+    }
+
+    /// Scenarios in the order played; each one continues to the next at its
+    /// end.
+    pub fn set_chain<I>(&mut self, chain: I)
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.chain = chain.into_iter().map(Into::into).collect();
+    }
+
+    pub fn scenario(&self) -> &str {
+        &self.scenario
+    }
+
+    /// Index of the next command.
+    pub fn position(&self) -> usize {
+        self.pc
+    }
+
+    pub fn program_len(&self) -> usize {
+        self.program.len()
+    }
+
+    /// How many dialogue lines with `text` come before the last command
+    /// taken in the program; tells identical lines apart.
+    pub fn line_ordinal(&self, text: &str) -> usize {
+        let end = self.pc.saturating_sub(1).min(self.program.len());
+
+        self.program[..end]
+            .iter()
+            .filter(|command| match command {
+                Command::RendererCommand(RendererCommand::Dialogue(_, t)) => t == text,
+                _ => false,
+            })
+            .count()
+    }
+
+    pub fn variables(&self) -> &Variables {
+        &self.variables
+    }
+
+    pub fn call_stack(&self) -> &[CallFrame] {
+        &self.call_stack
+    }
+
+    /// The story has reached its end, or a scenario has failed to load.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Starts `scenario` from the top; the story ends if it fails to load.
+    pub fn load(&mut self, host: &mut dyn Host, scenario: &str) -> std::io::Result<()> {
+        match host.load(scenario, &self.variables) {
+            Ok(program) => {
+                self.set_program(scenario, program);
+                self.finished = false;
+                Ok(())
+            }
+            Err(err) => {
+                self.finished = true;
+                Err(err)
+            }
+        }
+    }
+
+    fn set_program(&mut self, scenario: &str, program: Vec<Command>) {
+        self.scenario = scenario.into();
+        self.labels = loader::find_labels(&program);
+        self.program = program;
+        self.pc = 0;
+    }
+
+    fn load_or_finish(&mut self, host: &mut dyn Host, scenario: &str) -> bool {
+        match self.load(host, scenario) {
+            Ok(_) => true,
+            Err(err) => {
+                log::error!("failed to load {}: {}", scenario, err);
+                false
+            }
+        }
+    }
+
+    /// Resumes at `position` of `scenario`, as saved.
+    pub fn restore(
+        &mut self,
+        host: &mut dyn Host,
+        scenario: &str,
+        position: usize,
+        variables: Variables,
+        call_stack: Vec<CallFrame>,
+    ) -> std::io::Result<()> {
+        self.variables = variables;
+        self.condition = false;
+        self.call_stack = call_stack;
+
+        if !self.is_current(scenario) {
+            self.load(host, scenario)?;
+        }
+
+        self.finished = false;
+        self.seek(position);
+
+        Ok(())
+    }
+
+    /// Moves to the command at `pc`.
+    pub fn seek(&mut self, pc: usize) {
+        self.pc = pc.min(self.program.len());
+    }
+
+    /// Runs up to the next command for the host; `None` once the story has
+    /// ended.
+    pub fn next(&mut self, host: &mut dyn Host) -> Option<Command> {
+        while !self.finished {
+            let command = match self.program.get(self.pc).cloned() {
+                Some(command) => command,
+                None => {
+                    self.end_of_scenario(host);
+                    continue;
+                }
+            };
+            self.pc += 1;
+
+            if let Some(command) = self.exec(host, command) {
+                return Some(command);
+            }
+        }
+
+        None
+    }
+
+    /// Runs a command of the control flow, or gives it back for the host.
+    pub fn exec(&mut self, host: &mut dyn Host, command: Command) -> Option<Command> {
+        match command {
+            Command::RuntimeCommand(RuntimeCommand::SetFlag(name, value)) => {
+                self.set_variable(host, &name, Variable::Float(value));
+            }
+            Command::RuntimeCommand(RuntimeCommand::SetVariable(name, value)) => {
+                let value = self.eval(host, &value);
+                self.set_variable(host, &name, value);
+            }
+            Command::RuntimeCommand(RuntimeCommand::Test(condition)) => {
+                self.condition = self.eval(host, &condition).is_truthy();
+            }
+            Command::RuntimeCommand(RuntimeCommand::Label(_)) => {}
+            Command::RuntimeCommand(RuntimeCommand::Branch(label)) => self.branch(&label),
+            Command::RuntimeCommand(RuntimeCommand::BranchUnless(label)) => {
+                if !self.condition {
+                    self.branch(&label);
+                }
+            }
+            Command::RuntimeCommand(RuntimeCommand::Call { scenario, label }) => {
+                self.call(host, &scenario, label.as_deref());
+            }
+            Command::RuntimeCommand(RuntimeCommand::Return) => self.ret(host),
+            command => return Some(command),
+        }
+
+        None
+    }
+
+    fn eval(&self, host: &mut dyn Host, expr: &Expr) -> Variable {
+        let persistent = host.persistent();
+        let persistent = persistent.as_deref();
+
+        expr.eval(&|name: &str| match persistent {
+            Some(persistent) if variable::is_system(name) => persistent.system_variable(name),
+            _ => self.variables.get(name),
+        })
+    }
+
+    fn set_variable(&mut self, host: &mut dyn Host, name: &str, value: Variable) {
+        match host.persistent() {
+            Some(persistent) if variable::is_system(name) => {
+                persistent.set_system_variable(name, value);
+            }
+            _ => {
+                self.variables.set(name, value);
+            }
+        }
+    }
+
+    /// Continues from `label`.
+    pub fn branch(&mut self, label: &str) {
+        match self.labels.get(label) {
+            Some(&pc) => self.pc = pc + 1,
+            None => log::error!("undefined label: {}", label),
+        }
+    }
+
+    /// Goes back to the command just given, so that it is given again; the
+    /// game stays on a choice until an option is picked.
+    pub fn stay(&mut self) {
+        self.pc = self.pc.saturating_sub(1);
+    }
+
+    /// Leaves the choice stayed on for the option at `label`.
+    pub fn choose(&mut self, label: &str) {
+        self.seek(self.pc + 1);
+        self.branch(label);
+    }
+
+    fn call(&mut self, host: &mut dyn Host, scenario: &str, label: Option<&str>) {
+        let scenario = loader::resolve_scenario(&self.scenario, scenario);
+        let frame = CallFrame {
+            scenario: self.scenario.clone(),
+            position: self.pc,
+        };
+
+        if !self.is_current(&scenario) {
+            match host.load(&scenario, &self.variables) {
+                Ok(program) => self.set_program(&scenario, program),
+                Err(err) => {
+                    // the call is left out
+                    log::error!("failed to load {}: {}", scenario, err);
+                    return;
+                }
+            }
+        }
+
+        self.call_stack.push(frame);
+        self.pc = 0;
+
+        if let Some(label) = label {
+            self.branch(label);
+        }
+    }
+
+    fn ret(&mut self, host: &mut dyn Host) {
+        let frame = match self.call_stack.pop() {
+            Some(frame) => frame,
+            None => {
+                log::error!("return without a call");
+                self.pc = self.program.len();
+                return;
+            }
+        };
+
+        if !self.is_current(&frame.scenario) && !self.load_or_finish(host, &frame.scenario) {
+            return;
+        }
+
+        self.seek(frame.position);
+    }
+
+    fn end_of_scenario(&mut self, host: &mut dyn Host) {
+        if !self.call_stack.is_empty() {
+            // scenarios return at their end
+            self.ret(host);
+            return;
+        }
+
+        match self.next_scenario() {
+            Some(next) => {
+                log::info!("continuing to {}", next);
+                self.load_or_finish(host, &next);
+            }
+            None => {
+                log::info!("reached the end of the story");
+                self.finished = true;
+            }
+        }
+    }
+
+    // `scenario` is the one running, however the path is written
+    fn is_current(&self, scenario: &str) -> bool {
+        loader::normalize(scenario) == loader::normalize(&self.scenario)
+    }
+
+    fn next_scenario(&self) -> Option<String> {
+        let position = self.chain.iter().position(|s| self.is_current(s))?;
+
+        self.chain.get(position + 1).cloned()
+    }
+}
+
+#[test]
+fn test_interpreter_control_flow() {
+    fn rio(source: &str) -> Vec<Command> {
+        use crate::script::rio::parser::Parser;
+        use crate::script::rio::transpiler::Transpiler;
+
+        let script = Parser::from_raw_bytes(source.as_bytes()).parse().unwrap();
+        Transpiler::new(script).transpile()
+    }
+
+    // Plays through, picking the option `choice` of every choice; returns the
+    // lines shown.
+    fn play(interp: &mut Interpreter, host: &mut dyn Host, choice: usize) -> Vec<String> {
+        let mut lines = vec![];
+
+        while let Some(command) = interp.next(host) {
+            match command {
+                Command::RendererCommand(RendererCommand::Dialogue(_, text)) => lines.push(text),
+                Command::RuntimeCommand(RuntimeCommand::Choice(options)) => {
+                    interp.branch(&options[choice].label);
+                }
+                _ => {}
+            }
+        }
+
+        lines
+    }
+
+    let mut host = |scenario: &str, _: &Variables| match scenario {
+        "MAIN.TXT" => Ok(rio(concat!(
+            "$SET,route,1\n",
+            "$JUMP_IF,10,route == 2\n",
+            "not jumped\n\n",
+            "$LABEL,10\n",
+            "$CALL,SUB,20\n",
+            "$JUMP_IF,30,route == 2\n",
+            "jumped over\n\n",
+            "$LABEL,30\n",
+            "$JUMP,99\n",
+            "past an unknown label\n\n",
+            "$SELECT,はい,40,いいえ,50\n",
+            "$LABEL,40\n",
+            "yes\n\n",
+            "$JUMP,60\n",
+            "$LABEL,50\n",
+            "no\n\n",
+            "$LABEL,60\n",
+            "end\n\n",
+        ))),
+        "SUB.TXT" => Ok(rio(concat!(
+            "before the label\n\n",
+            "$LABEL,20\n",
+            "sub\n\n",
+            "$CALL,INNER\n",
+            "$SET,route,2\n",
+            "$RETURN\n",
+            "after the return\n\n",
+        ))),
+        // returns at its end
+        "INNER.TXT" => Ok(rio("inner\n\n")),
+        _ => Err(std::io::ErrorKind::NotFound.into()),
+    };
+
+    for &(choice, line) in &[(0, "yes"), (1, "no")] {
+        let mut interp = Interpreter::new();
+        interp.load(&mut host, "MAIN.TXT").unwrap();
+
+        assert_eq!(
+            play(&mut interp, &mut host, choice),
+            [
+                "not jumped",
+                "sub",
+                "inner",
+                "past an unknown label",
+                line,
+                "end"
+            ]
+        );
+        assert!(interp.is_finished());
+        assert!(interp.call_stack().is_empty());
+    }
+}
+
+#[test]
+fn test_interpreter_chain() {
+    use crate::script::mil::command::RendererCommand;
+
+    let dialogue =
+        |text: &str| Command::RendererCommand(RendererCommand::Dialogue(None, text.into()));
+    let mut loads = vec![];
+
+    let mut host = |scenario: &str, _: &Variables| {
+        loads.push(scenario.to_owned());
+
+        match scenario {
+            "a/01.TXT" => Ok(vec![dialogue("01")]),
+            "./a/02.TXT" => Ok(vec![dialogue("02")]),
+            _ => Err(std::io::ErrorKind::NotFound.into()),
+        }
+    };
+
+    let mut interp = Interpreter::new();
+    interp.set_chain(vec!["./a/01.TXT", "./a/02.TXT", "a/03.TXT", "a/01.TXT"]);
+
+    // a missing entry ends the story at once
+    assert!(interp.load(&mut host, "a/MISSING.TXT").is_err());
+    assert!(interp.is_finished());
+    assert!(interp.next(&mut host).is_none());
+
+    // the chain is followed however the paths are written, and stops at a
+    // scenario failing to load instead of going round
+    interp.load(&mut host, "a/01.TXT").unwrap();
+
+    let mut lines = vec![];
+    while let Some(command) = interp.next(&mut host) {
+        if let Command::RendererCommand(RendererCommand::Dialogue(_, text)) = command {
+            lines.push(text);
+        }
+    }
+
+    assert_eq!(lines, ["01", "02"]);
+    assert!(interp.is_finished());
+    assert_eq!(
+        loads,
+        ["a/MISSING.TXT", "a/01.TXT", "./a/02.TXT", "a/03.TXT"]
+    );
+}
+
+#[test]
+fn test_interpreter_line_ordinal() {
+    let dialogue =
+        |text: &str| Command::RendererCommand(RendererCommand::Dialogue(None, text.into()));
+
+    let mut interp = Interpreter::with_program(
+        "MAIN.TXT",
+        vec![
+            dialogue("……"),
+            dialogue("text"),
+            dialogue("……"),
+            dialogue("……"),
+        ],
+    );
+    let mut host = |_: &str, _: &Variables| Ok(vec![]);
+
+    let mut ordinals = vec![];
+    while let Some(Command::RendererCommand(RendererCommand::Dialogue(_, text))) =
+        interp.next(&mut host)
+    {
+        ordinals.push(interp.line_ordinal(&text));
+    }
+
+    assert_eq!(ordinals, [0, 0, 1, 2]);
+}
@@ -0,0 +1,458 @@
+//! Persistent JavaScript runtime.
+//!
+//! Scripts are sandboxed: every call is bounded in time, the heap has a hard
+//! cap, and files can only be reached through the engine's asset API.
+
+use rusty_v8 as v8;
+use thiserror::Error;
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::ffi::c_void;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use super::engine::{self, DebugState, EngineState};
+use crate::constants::{JS_EXECUTION_TIMEOUT, JS_HEAP_LIMIT};
+use crate::script::mil::command::Command;
+
+#[derive(Debug, Error)]
+pub enum JsError {
+    /// An uncaught exception, with the stack trace if available.
+    #[error("{0}")]
+    Exception(String),
+    #[error("script timed out after {0:?}")]
+    Timeout(Duration),
+    #[error("script exceeded the heap limit")]
+    OutOfMemory,
+    #[error("`{0}` is not a function")]
+    NotAFunction(String),
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct JsLimits {
+    /// Maximum time a single call may run.
+    pub timeout: Duration,
+    /// Hard limit of the heap, in bytes.
+    pub heap_limit: usize,
+}
+
+impl Default for JsLimits {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_millis(JS_EXECUTION_TIMEOUT),
+            heap_limit: JS_HEAP_LIMIT,
+        }
+    }
+}
+
+// terminates the execution once the heap gets close to the limit
+struct HeapGuard {
+    handle: v8::IsolateHandle,
+    exceeded: AtomicBool,
+}
+
+impl HeapGuard {
+    fn data(&self) -> *mut c_void {
+        self as *const Self as *mut c_void
+    }
+}
+
+extern "C" fn near_heap_limit(data: *mut c_void, current_heap_limit: usize, _: usize) -> usize {
+    let guard = unsafe { &*(data as *const HeapGuard) };
+
+    guard.exceeded.store(true, Ordering::SeqCst);
+    guard.handle.terminate_execution();
+
+    // leave some room for the termination to unwind, as V8 aborts otherwise;
+    // the limit is restored once it has
+    current_heap_limit * 2
+}
+
+// terminates the execution unless stopped within the timeout
+struct Watchdog {
+    stop: mpsc::Sender<()>,
+    thread: JoinHandle<bool>,
+}
+
+impl Watchdog {
+    fn start(handle: v8::IsolateHandle, timeout: Duration) -> Self {
+        let (stop, rx) = mpsc::channel();
+
+        let thread = std::thread::spawn(move || match rx.recv_timeout(timeout) {
+            Err(RecvTimeoutError::Timeout) => handle.terminate_execution(),
+            _ => false,
+        });
+
+        Self { stop, thread }
+    }
+
+    /// Returns `true` if the execution has been terminated.
+    fn stop(self) -> bool {
+        let _ = self.stop.send(());
+        self.thread.join().unwrap_or_default()
+    }
+}
+
+fn compile<'s>(
+    scope: &mut v8::HandleScope<'s>,
+    name: &str,
+    source: &str,
+) -> Option<v8::Local<'s, v8::Script>> {
+    let source = v8::String::new(scope, source)?;
+    let name = v8::String::new(scope, name)?;
+
+    let zero = v8::Integer::new(scope, 0);
+    let no = v8::Boolean::new(scope, false);
+    let undefined = v8::undefined(scope);
+
+    let origin = v8::ScriptOrigin::new(
+        name.into(),
+        zero,
+        zero,
+        no,
+        zero,
+        undefined.into(),
+        no,
+        no,
+        no,
+    );
+
+    v8::Script::compile(scope, source, Some(&origin))
+}
+
+fn to_string(scope: &mut v8::HandleScope, value: v8::Local<v8::Value>) -> Option<String> {
+    Some(value.to_string(scope)?.to_rust_string_lossy(scope))
+}
+
+fn caught_exception(tc: &mut v8::TryCatch<v8::HandleScope>) -> JsError {
+    let stack = tc
+        .stack_trace()
+        .filter(|v| !v.is_null_or_undefined())
+        .and_then(|v| to_string(tc, v));
+
+    if let Some(stack) = stack {
+        return JsError::Exception(stack);
+    }
+
+    let exception = tc
+        .exception()
+        .and_then(|v| to_string(tc, v))
+        .unwrap_or_else(|| "unknown error".into());
+
+    let location = tc.message().map(|message| {
+        let name = message
+            .get_script_resource_name(tc)
+            .and_then(|v| to_string(tc, v))
+            .unwrap_or_default();
+        let line = message.get_line_number(tc).unwrap_or_default();
+
+        format!("{}:{}", name, line)
+    });
+
+    match location {
+        Some(location) => JsError::Exception(format!("{} ({})", exception, location)),
+        None => JsError::Exception(exception),
+    }
+}
+
+pub struct JsRuntime {
+    context: v8::Global<v8::Context>,
+    isolate: v8::OwnedIsolate,
+    // referred to by the isolate; dropped after it
+    heap_guard: Box<HeapGuard>,
+    // limit of the heap before any termination
+    heap_size_limit: usize,
+    limits: JsLimits,
+}
+
+impl JsRuntime {
+    pub fn new() -> Self {
+        Self::with_limits(Default::default())
+    }
+
+    pub fn with_limits(limits: JsLimits) -> Self {
+        super::init();
+
+        let params = v8::Isolate::create_params().heap_limits(0, limits.heap_limit);
+        let mut isolate = v8::Isolate::new(params);
+        isolate.set_slot(EngineState::default());
+
+        let heap_guard = Box::new(HeapGuard {
+            handle: isolate.thread_safe_handle(),
+            exceeded: AtomicBool::new(false),
+        });
+        isolate.add_near_heap_limit_callback(near_heap_limit, heap_guard.data());
+
+        let context = {
+            let scope = &mut v8::HandleScope::new(&mut isolate);
+            let context = v8::Context::new(scope);
+            let scope = &mut v8::ContextScope::new(scope, context);
+
+            let global = context.global(scope);
+            engine::install(scope, global);
+
+            v8::Global::new(scope, context)
+        };
+
+        let mut runtime = Self {
+            context,
+            isolate,
+            heap_guard,
+            heap_size_limit: 0,
+            limits,
+        };
+
+        runtime.heap_size_limit = runtime.current_heap_size_limit();
+        runtime
+    }
+
+    fn current_heap_size_limit(&mut self) -> usize {
+        let mut stats = v8::HeapStatistics::default();
+        self.isolate.get_heap_statistics(&mut stats);
+        stats.heap_size_limit()
+    }
+
+    // Takes back the room given by `near_heap_limit`, so that every run is
+    // held to the same limit.
+    fn restore_heap_limit(&mut self) {
+        // the garbage of the terminated run goes first, so that the heap fits
+        self.isolate.low_memory_notification();
+
+        // the callback is called once; added again for the next run
+        self.isolate
+            .remove_near_heap_limit_callback(near_heap_limit, self.heap_size_limit);
+        self.isolate
+            .add_near_heap_limit_callback(near_heap_limit, self.heap_guard.data());
+    }
+
+    // Runs `f` under the limits; exceptions are turned into errors.
+    fn run<T, F>(&mut self, f: F) -> Result<T, JsError>
+    where
+        F: FnOnce(&mut v8::TryCatch<v8::HandleScope>) -> Result<T, JsError>,
+    {
+        self.heap_guard.exceeded.store(false, Ordering::SeqCst);
+        let watchdog = Watchdog::start(self.isolate.thread_safe_handle(), self.limits.timeout);
+
+        let result = {
+            let scope = &mut v8::HandleScope::with_context(&mut self.isolate, &self.context);
+            let tc = &mut v8::TryCatch::new(scope);
+
+            match f(tc) {
+                Ok(_) if tc.has_caught() => Err(caught_exception(tc)),
+                result => result,
+            }
+        };
+
+        let terminated = watchdog.stop();
+
+        if self.heap_guard.exceeded.load(Ordering::SeqCst) {
+            self.isolate
+                .thread_safe_handle()
+                .cancel_terminate_execution();
+            self.restore_heap_limit();
+            return Err(JsError::OutOfMemory);
+        }
+
+        if terminated {
+            self.isolate
+                .thread_safe_handle()
+                .cancel_terminate_execution();
+            return Err(JsError::Timeout(self.limits.timeout));
+        }
+
+        result
+    }
+
+    /// Evaluates `source`; returns the completion value as a string.
+    pub fn execute(&mut self, name: &str, source: &str) -> Result<String, JsError> {
+        self.run(|tc| {
+            let result = compile(tc, name, source)
+                .and_then(|script| script.run(tc))
+                .and_then(|v| to_string(tc, v));
+
+            result.ok_or_else(|| caught_exception(tc))
+        })
+    }
+
+    /// Evaluates `source`, then calls the global function `function` with
+    /// `argument`; `source` must define the function.
+    ///
+    /// Both the argument and the return value are passed as JSON text.
+    pub fn call_json(
+        &mut self,
+        name: &str,
+        source: &str,
+        function: &str,
+        argument: &str,
+    ) -> Result<String, JsError> {
+        self.run(|tc| {
+            let global = tc.get_current_context().global(tc);
+            let key = v8::String::new(tc, function).unwrap();
+
+            // scripts share the context; one defined before does not count
+            global.delete(tc, key.into());
+
+            compile(tc, name, source)
+                .and_then(|script| script.run(tc))
+                .ok_or_else(|| caught_exception(tc))?;
+
+            let func = global
+                .get(tc, key.into())
+                .and_then(|v| v8::Local::<v8::Function>::try_from(v).ok())
+                .ok_or_else(|| JsError::NotAFunction(function.into()))?;
+
+            let argument = v8::String::new(tc, argument).unwrap();
+            let recv = v8::undefined(tc).into();
+
+            let result = v8::json::parse(tc, argument)
+                .and_then(|argument| func.call(tc, recv, &[argument]))
+                .and_then(|result| v8::json::stringify(tc, result))
+                .map(|result| result.to_rust_string_lossy(tc));
+
+            result.ok_or_else(|| caught_exception(tc))
+        })
+    }
+
+    /// Runs a scene script and returns the MIL program it generated.
+    pub fn run_scene(&mut self, name: &str, source: &str) -> Result<Vec<Command>, JsError> {
+        self.take_commands();
+
+        let result = self.execute(name, source);
+        let commands = self.take_commands();
+
+        result.map(|_| commands)
+    }
+
+    /// Takes the commands queued by the engine API.
+    pub fn take_commands(&mut self) -> Vec<Command> {
+        std::mem::take(&mut self.state_mut().commands)
+    }
+
+    pub fn flags(&self) -> &BTreeMap<String, f64> {
+        &self.isolate.get_slot::<EngineState>().unwrap().flags
+    }
+
+    pub fn set_flags(&mut self, flags: BTreeMap<String, f64>) {
+        self.state_mut().flags = flags;
+    }
+
+    /// State read and written by the debugging API.
+    pub fn debug_mut(&mut self) -> &mut DebugState {
+        &mut self.state_mut().debug
+    }
+
+    fn state_mut(&mut self) -> &mut EngineState {
+        self.isolate.get_slot_mut::<EngineState>().unwrap()
+    }
+}
+
+impl Default for JsRuntime {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[test]
+fn test_js_engine_api() {
+    use crate::script::mil::command::{LayerCommand, RuntimeCommand};
+
+    let mut runtime = JsRuntime::new();
+    let commands = runtime
+        .run_scene(
+            "test.js",
+            r#"
+            engine.loadLayer(1, "BG01", [0]);
+            engine.animateLayerBlur(1, 4, 2, 500);
+            engine.setFlag("route", engine.getFlag("route") + 1);
+            engine.dialogue("桐香", "text");
+            "#,
+        )
+        .unwrap();
+
+    assert_eq!(commands.len(), 6);
+    assert!(matches!(
+        &commands[0],
+        Command::LayerCommand {
+            layer_no: 1,
+            command: LayerCommand::Load(filename, _),
+        } if filename == "BG01"
+    ));
+    assert!(matches!(
+        &commands[1],
+        Command::LayerCommand {
+            layer_no: 1,
+            command: LayerCommand::AnimateBlurRate(4, 2, duration),
+        } if *duration == 500.0
+    ));
+    assert!(matches!(
+        &commands[2],
+        Command::RuntimeCommand(RuntimeCommand::SetFlag(name, value)) if name == "route" && *value == 1.0
+    ));
+    assert_eq!(runtime.flags().get("route"), Some(&1.0));
+
+    // layers out of range throw
+    for layer in &["-1", "30"] {
+        let source = format!("engine.unloadLayer({});", layer);
+        let err = runtime.run_scene("layer.js", &source).unwrap_err();
+        assert!(matches!(&err, JsError::Exception(message) if message.contains("no such layer")));
+    }
+}
+
+#[test]
+fn test_js_sandbox() {
+    let mut runtime = JsRuntime::with_limits(JsLimits {
+        timeout: Duration::from_millis(100),
+        ..Default::default()
+    });
+
+    let err = runtime.execute("loop.js", "while (true) {}").unwrap_err();
+    assert!(matches!(err, JsError::Timeout(_)));
+
+    // the runtime is still usable after the termination
+    assert_eq!(runtime.execute("ok.js", "1 + 1").unwrap(), "2");
+
+    let err = runtime
+        .execute(
+            "throw.js",
+            "function f() { throw new Error('boom'); }\nf();",
+        )
+        .unwrap_err();
+    assert!(
+        matches!(&err, JsError::Exception(stack) if stack.contains("boom") && stack.contains("throw.js"))
+    );
+
+    // a function left by another script is not called
+    let echo = "function process(x) { return x; }";
+    assert_eq!(
+        runtime.call_json("a.js", echo, "process", "[1]").unwrap(),
+        "[1]"
+    );
+    let err = runtime
+        .call_json("b.js", "1", "process", "[1]")
+        .unwrap_err();
+    assert!(matches!(err, JsError::NotAFunction(_)));
+
+    // no ambient I/O
+    let err = runtime.execute("fs.js", "require('fs')").unwrap_err();
+    assert!(matches!(err, JsError::Exception(_)));
+
+    let mut runtime = JsRuntime::with_limits(JsLimits {
+        heap_limit: 16 << 20,
+        ..Default::default()
+    });
+    let limit = runtime.current_heap_size_limit();
+
+    // every run past the limit is terminated, and the limit stays the same
+    let hog = "(() => { const a = []; while (true) a.push(new Array(1 << 16).fill(1)); })()";
+
+    for _ in 0..2 {
+        let err = runtime.execute("hog.js", hog).unwrap_err();
+        assert!(matches!(err, JsError::OutOfMemory));
+        assert_eq!(runtime.current_heap_size_limit(), limit);
+    }
+
+    assert_eq!(runtime.execute("ok.js", "1 + 1").unwrap(), "2");
+}
@@ -1,36 +1,25 @@
+pub mod backlog;
+pub mod engine;
+pub mod interpreter;
+pub mod js;
+pub mod persistent;
+pub mod rewind;
 pub mod savedata;
+pub mod variable;
 
 use rusty_v8 as v8;
-use v8::FunctionCallback;
-use v8::MapFnTo;
 
-fn hello_from_rust(
-    scope: &mut v8::HandleScope,
-    _: v8::FunctionCallbackArguments,
-    mut retval: v8::ReturnValue,
-) {
-    log::debug!("script runtime started from: {:?}", std::thread::current());
-    retval.set(v8::undefined(scope).into());
-}
-
-pub fn init() {
-    let platform = v8::new_default_platform().unwrap();
-    v8::V8::initialize_platform(platform);
-    v8::V8::initialize();
+use std::sync::Once;
 
-    let isolate = &mut v8::Isolate::new(Default::default());
+static V8_INIT: Once = Once::new();
 
-    let scope = &mut v8::HandleScope::new(isolate);
-    let context = v8::Context::new(scope);
-    let scope = &mut v8::ContextScope::new(scope, context);
-
-    let func = v8::Function::new(scope, hello_from_rust).unwrap();
-    let global = context.global(scope);
-    let key = v8::String::new(scope, "helloFromRust").unwrap();
-    global.set(scope, key.into(), func.into());
-
-    let code = v8::String::new(scope, "helloFromRust()").unwrap();
+/// Initializes V8; safe to call more than once.
+pub fn init() {
+    V8_INIT.call_once(|| {
+        let platform = v8::new_default_platform().unwrap();
+        v8::V8::initialize_platform(platform);
+        v8::V8::initialize();
 
-    let script = v8::Script::compile(scope, code, None).unwrap();
-    let _ = script.run(scope).unwrap();
+        log::debug!("script runtime started from: {:?}", std::thread::current());
+    });
 }
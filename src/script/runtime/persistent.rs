@@ -0,0 +1,253 @@
+//! Global persistent data.
+//!
+//! Shared between all save slots; holds the read-text state, unlocked CGs
+//! and music, and user preferences.
+
+use miniserde::json::{self, Value};
+use miniserde::{Deserialize, Serialize};
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::path::{Path, PathBuf};
+
+use super::variable::Variables;
+use crate::script::mil::expr::Variable;
+use crate::utils;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Preferences {
+    /// Characters per second; zero displays the whole line at once.
+    pub text_speed: f64,
+    pub music_volume: f64,
+    pub voice_volume: f64,
+    pub se_volume: f64,
+    /// Whether skip mode should go past unread lines.
+    pub skip_unread: bool,
+}
+
+impl Default for Preferences {
+    fn default() -> Self {
+        Self {
+            text_speed: 0.0,
+            music_volume: 1.0,
+            voice_volume: 1.0,
+            se_volume: 1.0,
+            skip_unread: false,
+        }
+    }
+}
+
+// on-disk representation
+#[derive(Serialize, Deserialize)]
+struct PersistentDataFile {
+    read_lines: Vec<u64>,
+    unlocked_cgs: Vec<String>,
+    unlocked_music: Vec<String>,
+    preferences: Preferences,
+    // missing in older files
+    system_variables: Option<BTreeMap<String, Value>>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct PersistentData {
+    path: PathBuf,
+    read_lines: HashSet<u64>,
+    unlocked_cgs: BTreeSet<String>,
+    unlocked_music: BTreeSet<String>,
+    pub preferences: Preferences,
+    system_variables: Variables,
+    dirty: bool,
+}
+
+/// Identifies a dialogue line by its scenario, text, and `ordinal` among the
+/// lines with the same text in the scenario (64-bit FNV-1a).
+///
+/// The hash is stable across runs and does not depend on the position of
+/// the line, hence edits elsewhere in the scenario keep the read state. The
+/// first of identical lines hashes as it did before ordinals were counted.
+pub fn line_hash(scenario: &str, text: &str, ordinal: usize) -> u64 {
+    const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    let scenario = Path::new(scenario)
+        .file_name()
+        .and_then(|v| v.to_str())
+        .unwrap_or(scenario);
+
+    let ordinal = match ordinal {
+        0 => vec![],
+        n => std::iter::once(0).chain(n.to_string().bytes()).collect(),
+    };
+
+    scenario
+        .to_ascii_uppercase()
+        .bytes()
+        .chain(std::iter::once(0))
+        .chain(text.bytes())
+        .chain(ordinal)
+        .fold(FNV_OFFSET_BASIS, |hash, b| {
+            (hash ^ b as u64).wrapping_mul(FNV_PRIME)
+        })
+}
+
+impl PersistentData {
+    /// Opens the persistent data at `path`, or creates an empty one.
+    pub fn open<P: AsRef<Path>>(path: P) -> Self {
+        let path = path.as_ref().to_path_buf();
+
+        let file = match std::fs::read_to_string(&path) {
+            Ok(file) => file,
+            Err(_) => {
+                log::info!("no persistent data found at {:?}", path);
+                return Self {
+                    path,
+                    ..Default::default()
+                };
+            }
+        };
+
+        match json::from_str::<PersistentDataFile>(&file) {
+            Ok(data) => Self {
+                path,
+                read_lines: data.read_lines.into_iter().collect(),
+                unlocked_cgs: data.unlocked_cgs.into_iter().collect(),
+                unlocked_music: data.unlocked_music.into_iter().collect(),
+                preferences: data.preferences,
+                system_variables: data
+                    .system_variables
+                    .as_ref()
+                    .map(Variables::from_json)
+                    .unwrap_or_default(),
+                dirty: false,
+            },
+            Err(_) => {
+                // keep the broken file around instead of overwriting it
+                let mut backup = path.clone().into_os_string();
+                backup.push(".bak");
+
+                log::error!("failed to parse persistent data; moved to {:?}", backup);
+                let _ = std::fs::rename(&path, backup);
+
+                Self {
+                    path,
+                    ..Default::default()
+                }
+            }
+        }
+    }
+
+    /// Whether the line has been shown; see `line_hash` for `ordinal`.
+    pub fn is_read(&self, scenario: &str, text: &str, ordinal: usize) -> bool {
+        self.read_lines
+            .contains(&line_hash(scenario, text, ordinal))
+    }
+
+    pub fn mark_read(&mut self, scenario: &str, text: &str, ordinal: usize) {
+        self.dirty |= self.read_lines.insert(line_hash(scenario, text, ordinal));
+    }
+
+    pub fn is_cg_unlocked(&self, filename: &str) -> bool {
+        self.unlocked_cgs.contains(&filename.to_ascii_uppercase())
+    }
+
+    /// Records an image as seen. The CG gallery filters them by its own list.
+    pub fn unlock_cg(&mut self, filename: &str) {
+        self.dirty |= self.unlocked_cgs.insert(filename.to_ascii_uppercase());
+    }
+
+    pub fn is_music_unlocked(&self, filename: &str) -> bool {
+        self.unlocked_music.contains(&filename.to_ascii_uppercase())
+    }
+
+    pub fn unlock_music(&mut self, filename: &str) {
+        self.dirty |= self.unlocked_music.insert(filename.to_ascii_uppercase());
+    }
+
+    pub fn set_preferences(&mut self, preferences: Preferences) {
+        self.preferences = preferences;
+        self.dirty = true;
+    }
+
+    pub fn system_variable(&self, name: &str) -> Variable {
+        self.system_variables.get(name)
+    }
+
+    pub fn set_system_variable(&mut self, name: &str, value: Variable) {
+        self.dirty |= self.system_variables.set(name, value);
+    }
+
+    /// Writes the data back to the disk if anything has changed.
+    ///
+    /// The file is replaced atomically, so a crash never leaves it half-written.
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+
+        let mut read_lines: Vec<_> = self.read_lines.iter().copied().collect();
+        read_lines.sort_unstable();
+
+        let data = PersistentDataFile {
+            read_lines,
+            unlocked_cgs: self.unlocked_cgs.iter().cloned().collect(),
+            unlocked_music: self.unlocked_music.iter().cloned().collect(),
+            preferences: self.preferences.clone(),
+            system_variables: Some(self.system_variables.to_json()),
+        };
+
+        utils::io::write_atomic(&self.path, json::to_string(&data).as_bytes())?;
+        self.dirty = false;
+
+        Ok(())
+    }
+}
+
+#[test]
+fn test_read_lines() {
+    let mut data = PersistentData::default();
+
+    // unread when first shown, and read from then on
+    assert!(!data.is_read("./testcase/01.TXT", "text", 0));
+    data.mark_read("./testcase/01.TXT", "text", 0);
+    assert!(data.is_read("./testcase/01.TXT", "text", 0));
+
+    // the same scenario from elsewhere, but not the same text elsewhere
+    assert!(data.is_read("01.txt", "text", 0));
+    assert!(!data.is_read("./testcase/01.TXT", "next text", 0));
+    assert!(!data.is_read("./testcase/02.TXT", "text", 0));
+
+    // nor a later line with the same text
+    assert!(!data.is_read("./testcase/01.TXT", "text", 1));
+    data.mark_read("./testcase/01.TXT", "text", 1);
+    assert!(data.is_read("./testcase/01.TXT", "text", 1));
+    assert!(!data.is_read("./testcase/01.TXT", "text", 10));
+
+    // marking again leaves nothing to write
+    data.dirty = false;
+    data.mark_read("./testcase/01.TXT", "text", 0);
+    assert!(!data.dirty);
+
+    // the first line keeps the hash from before ordinals
+    assert_eq!(line_hash("01.TXT", "text", 0), 0x808b_6995_34c4_06a3);
+}
+
+#[test]
+fn test_persistent_data_roundtrip() {
+    let dir = std::env::temp_dir().join(format!("nkts-persistent-{}", std::process::id()));
+    let path = dir.join("global.json");
+
+    let mut data = PersistentData::open(&path);
+    assert!(!data.is_read("./testcase/02_NK_23H.TXT", "text", 0));
+
+    data.mark_read("./testcase/02_NK_23H.TXT", "text", 0);
+    data.unlock_music("BGM01");
+    data.set_system_variable("sys.cleared", Variable::Decimal(1));
+    data.flush().unwrap();
+
+    let data = PersistentData::open(&path);
+    assert!(data.is_read("02_nk_23h.txt", "text", 0));
+    assert!(!data.is_read("02_NK_23H.TXT", "other text", 0));
+    assert!(data.is_music_unlocked("bgm01"));
+    assert_eq!(data.system_variable("sys.cleared"), Variable::Decimal(1));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
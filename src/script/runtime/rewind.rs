@@ -0,0 +1,134 @@
+//! Rewind history.
+//!
+//! Snapshots are taken every few lines; going back to a line restores the
+//! nearest snapshot before it and replays the program up to the line.
+
+use super::savedata::Savedata;
+
+use std::collections::VecDeque;
+
+/// Result of a rewind; restore `snapshot`, then replay `replay_lines` lines.
+#[derive(Clone, Debug)]
+pub struct Rewind {
+    pub snapshot: Savedata,
+    pub replay_lines: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct RewindHistory {
+    // serial number of the current line
+    current: Option<u64>,
+    snapshots: VecDeque<(u64, Savedata)>,
+    interval: u64,
+    capacity: usize,
+}
+
+impl RewindHistory {
+    /// `interval` is the number of lines between snapshots, and `capacity`
+    /// the number of snapshots kept.
+    pub fn new(interval: usize, capacity: usize) -> Self {
+        Self {
+            current: None,
+            snapshots: VecDeque::new(),
+            interval: interval.max(1) as u64,
+            capacity: capacity.max(1),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.current = None;
+        self.snapshots.clear();
+    }
+
+    fn next_line(&self) -> u64 {
+        self.current.map(|v| v + 1).unwrap_or_default()
+    }
+
+    /// Returns `true` if the next line should be recorded with a snapshot.
+    pub fn wants_snapshot(&self) -> bool {
+        self.snapshots.is_empty() || self.next_line() % self.interval == 0
+    }
+
+    /// Records a line reached; `snapshot` is the state while the line is shown.
+    pub fn push_line(&mut self, snapshot: Option<Savedata>) {
+        let line = self.next_line();
+        self.current = Some(line);
+
+        if let Some(snapshot) = snapshot {
+            self.snapshots.push_back((line, snapshot));
+
+            while self.snapshots.len() > self.capacity {
+                self.snapshots.pop_front();
+            }
+        }
+    }
+
+    /// Goes back to the previous line, if it is still in the history.
+    pub fn rewind(&mut self) -> Option<Rewind> {
+        let target = self.current?.checked_sub(1)?;
+
+        let (line, _) = self.snapshots.front()?;
+        if *line > target {
+            return None;
+        }
+
+        while let Some((line, _)) = self.snapshots.back() {
+            if *line <= target {
+                break;
+            }
+
+            self.snapshots.pop_back();
+        }
+
+        let (line, snapshot) = self.snapshots.back()?;
+        self.current = Some(target);
+
+        Some(Rewind {
+            snapshot: snapshot.clone(),
+            replay_lines: (target - line) as usize,
+        })
+    }
+}
+
+#[test]
+fn test_rewind_history() {
+    let snapshot = |position| Savedata {
+        scenario: "02_NK_23H.TXT".into(),
+        position,
+        waiting: true,
+        layers: vec![],
+        dialogue: None,
+        music: None,
+        backlog: vec![],
+        variables: Default::default(),
+        call_stack: vec![],
+    };
+
+    let mut history = RewindHistory::new(3, 2);
+    assert!(history.rewind().is_none());
+
+    // lines 0..=7; snapshots at 0, 3 and 6, of which 3 and 6 are kept
+    for line in 0..8 {
+        let s = if history.wants_snapshot() {
+            Some(snapshot(line))
+        } else {
+            None
+        };
+        history.push_line(s);
+    }
+
+    let r = history.rewind().unwrap();
+    assert_eq!((r.snapshot.position, r.replay_lines), (6, 0));
+
+    let r = history.rewind().unwrap();
+    assert_eq!((r.snapshot.position, r.replay_lines), (3, 2));
+
+    // the snapshot of line 6 has been dropped; line 6 is recorded again
+    assert!(history.wants_snapshot());
+    history.push_line(Some(snapshot(6)));
+
+    let replayed: Vec<_> = std::iter::from_fn(|| history.rewind())
+        .map(|r| (r.snapshot.position, r.replay_lines))
+        .collect();
+    assert_eq!(replayed, vec![(3, 2), (3, 1), (3, 0)]);
+}
@@ -1 +1,196 @@
+//! Slot savedata.
 
+use miniserde::json::{self, Value};
+use miniserde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use super::backlog::LogEntry;
+use crate::script::mil::command::{BlendMode, Command, LayerCommand};
+use crate::{config, utils};
+
+/// State of a layer as seen from MIL; independent from graphic backends.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LayerState {
+    pub filename: Option<String>,
+    pub entries: Vec<i32>,
+    pub x: f64,
+    pub y: f64,
+    pub opacity: f64,
+    pub blur_x: i32,
+    pub blur_y: i32,
+    /// Saved by name; see `BlendMode::name`.
+    pub blend_mode: BlendMode,
+}
+
+impl Default for LayerState {
+    fn default() -> Self {
+        Self {
+            filename: None,
+            entries: vec![],
+            x: 0.0,
+            y: 0.0,
+            opacity: 1.0,
+            blur_x: 0,
+            blur_y: 0,
+            blend_mode: BlendMode::Normal,
+        }
+    }
+}
+
+impl LayerState {
+    /// Tracks a layer command.
+    pub fn apply(&mut self, command: &LayerCommand) {
+        match command {
+            LayerCommand::Load(filename, entries) => {
+                self.filename = Some(filename.clone());
+                self.entries = entries.clone();
+            }
+            LayerCommand::Unload => {
+                self.filename = None;
+                self.entries = vec![];
+            }
+            LayerCommand::SetPosition(x, y) => {
+                self.x = *x;
+                self.y = *y;
+            }
+            LayerCommand::SetOpacity(opacity) => {
+                self.opacity = *opacity;
+            }
+            // restored as it ends
+            LayerCommand::SetBlurRate(rx, ry) | LayerCommand::AnimateBlurRate(rx, ry, _) => {
+                self.blur_x = *rx;
+                self.blur_y = *ry;
+            }
+            LayerCommand::SetBlendMode(mode) => {
+                self.blend_mode = *mode;
+            }
+            _ => {}
+        }
+    }
+
+    /// Generates commands which bring a layer to this state.
+    pub fn restore(&self, layer_no: i32) -> Vec<Command> {
+        let mut commands = vec![LayerCommand::FinalizeAnimation, LayerCommand::Unload];
+
+        if let Some(filename) = &self.filename {
+            commands.push(LayerCommand::Load(filename.clone(), self.entries.clone()));
+        }
+
+        commands.push(LayerCommand::SetPosition(self.x, self.y));
+        commands.push(LayerCommand::SetOpacity(self.opacity));
+        commands.push(LayerCommand::SetBlurRate(self.blur_x, self.blur_y));
+        commands.push(LayerCommand::SetBlendMode(self.blend_mode));
+
+        commands
+            .into_iter()
+            .map(|command| Command::LayerCommand { layer_no, command })
+            .collect()
+    }
+}
+
+/// Where `RuntimeCommand::Return` goes back to.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CallFrame {
+    pub scenario: String,
+    pub position: usize,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Savedata {
+    pub scenario: String,
+    /// Position in the MIL program.
+    pub position: usize,
+    pub waiting: bool,
+    pub layers: Vec<LayerState>,
+    pub dialogue: Option<LogEntry>,
+    pub music: Option<String>,
+    pub backlog: Vec<LogEntry>,
+    /// Scenario variables; see `Variables::to_json`.
+    pub variables: BTreeMap<String, Value>,
+    pub call_stack: Vec<CallFrame>,
+}
+
+impl Savedata {
+    pub fn read<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        use std::io::{Error, ErrorKind};
+
+        let file = std::fs::read_to_string(path)?;
+        json::from_str(&file).map_err(|_| Error::new(ErrorKind::InvalidData, "broken savedata"))
+    }
+
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        utils::io::write_atomic(path, json::to_string(self).as_bytes())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SaveSlot {
+    Quick,
+    Backup,
+    Slot(i32),
+}
+
+impl SaveSlot {
+    pub fn path(self) -> PathBuf {
+        let mut path = PathBuf::from(config::get_save_path());
+
+        match self {
+            SaveSlot::Quick => path.push("quick.json"),
+            SaveSlot::Backup => path.push("backup.json"),
+            SaveSlot::Slot(n) => path.push(format!("slot{:03}.json", n)),
+        }
+
+        path
+    }
+}
+
+#[test]
+fn test_savedata_roundtrip() {
+    let path = std::env::temp_dir()
+        .join(format!("nkts-savedata-{}", std::process::id()))
+        .join("slot.json");
+
+    let mut layer: LayerState = Default::default();
+    layer.apply(&LayerCommand::Load("BG01".into(), vec![0, -1]));
+    layer.apply(&LayerCommand::SetPosition(10.0, 20.0));
+    layer.apply(&LayerCommand::SetBlendMode(BlendMode::Screen));
+
+    let savedata = Savedata {
+        scenario: "02_NK_23H.TXT".into(),
+        position: 42,
+        waiting: true,
+        layers: vec![layer.clone(), Default::default()],
+        dialogue: None,
+        music: Some("BGM01".into()),
+        backlog: vec![LogEntry {
+            name: Some("桐香".into()),
+            text: "text".into(),
+            voice: Some("TOU0001".into()),
+        }],
+        variables: vec![("route".to_owned(), Value::String("tohka".into()))]
+            .into_iter()
+            .collect(),
+        call_stack: vec![CallFrame {
+            scenario: "02_NK_22H.TXT".into(),
+            position: 7,
+        }],
+    };
+
+    savedata.write(&path).unwrap();
+    // blend modes are saved by name
+    let json = std::fs::read_to_string(&path).unwrap();
+    assert!(json.contains(r#""blend_mode":"screen""#), "{}", json);
+    assert!(json::from_str::<Savedata>(&json.replace("screen", "dodge")).is_err());
+
+    let loaded = Savedata::read(&path).unwrap();
+
+    assert_eq!(loaded.position, 42);
+    assert_eq!(loaded.layers[0], layer);
+    assert_eq!(loaded.backlog, savedata.backlog);
+    assert_eq!(loaded.call_stack, savedata.call_stack);
+    assert!(matches!(loaded.variables.get("route"), Some(Value::String(v)) if v == "tohka"));
+
+    std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
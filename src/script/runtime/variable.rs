@@ -0,0 +1,110 @@
+//! Variable store.
+//!
+//! Scenario flags live in save data. System variables, whose names start
+//! with `sys.`, are shared between all slots and live in the persistent data.
+
+use miniserde::json::{Number, Value};
+
+use std::collections::BTreeMap;
+
+use crate::script::mil::expr::Variable;
+
+pub const SYSTEM_PREFIX: &str = "sys.";
+
+pub fn is_system(name: &str) -> bool {
+    name.starts_with(SYSTEM_PREFIX)
+}
+
+pub fn to_value(v: &Variable) -> Value {
+    match v {
+        Variable::String(s) => Value::String(s.clone()),
+        Variable::Decimal(v) => Value::Number(Number::I64(*v as i64)),
+        Variable::Float(v) => Value::Number(Number::F64(*v)),
+        Variable::Empty => Value::Null,
+    }
+}
+
+pub fn from_value(v: &Value) -> Variable {
+    match v {
+        Value::String(s) => Variable::String(s.clone()),
+        Value::Number(Number::U64(v)) => Variable::Decimal(*v as i32),
+        Value::Number(Number::I64(v)) => Variable::Decimal(*v as i32),
+        Value::Number(Number::F64(v)) => Variable::Float(*v),
+        Value::Bool(v) => Variable::Decimal(*v as i32),
+        _ => Variable::Empty,
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Variables {
+    values: BTreeMap<String, Variable>,
+}
+
+impl Variables {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.values.clear();
+    }
+
+    /// Undefined variables are `Variable::Empty`.
+    pub fn get(&self, name: &str) -> Variable {
+        self.values.get(name).cloned().unwrap_or(Variable::Empty)
+    }
+
+    /// Returns `true` if the value has changed.
+    pub fn set(&mut self, name: &str, value: Variable) -> bool {
+        if value == Variable::Empty {
+            return self.values.remove(name).is_some();
+        }
+
+        self.values.insert(name.into(), value.clone()) != Some(value)
+    }
+
+    /// Numeric variables, as seen from JavaScript.
+    pub fn numbers(&self) -> BTreeMap<String, f64> {
+        self.values
+            .iter()
+            .filter_map(|(name, v)| Some((name.clone(), v.as_f64()?)))
+            .collect()
+    }
+
+    pub fn to_json(&self) -> BTreeMap<String, Value> {
+        self.values
+            .iter()
+            .map(|(name, v)| (name.clone(), to_value(v)))
+            .collect()
+    }
+
+    pub fn from_json(values: &BTreeMap<String, Value>) -> Self {
+        let mut variables = Self::new();
+
+        for (name, v) in values {
+            variables.set(name, from_value(v));
+        }
+
+        variables
+    }
+}
+
+#[test]
+fn test_variables() {
+    use miniserde::json;
+
+    let mut variables = Variables::new();
+    assert!(variables.set("route", Variable::Decimal(2)));
+    assert!(!variables.set("route", Variable::Decimal(2)));
+    variables.set("rate", Variable::Float(0.5));
+    variables.set("name", Variable::String("桐香".into()));
+    variables.set("gone", Variable::Decimal(1));
+    assert!(variables.set("gone", Variable::Empty));
+
+    let text = json::to_string(&variables.to_json());
+    let restored = Variables::from_json(&json::from_str(&text).unwrap());
+
+    assert_eq!(restored, variables);
+    assert_eq!(restored.get("gone"), Variable::Empty);
+    assert_eq!(restored.numbers().len(), 2);
+}
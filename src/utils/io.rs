@@ -1,4 +1,5 @@
 use std::io::Read;
+use std::path::Path;
 
 pub fn read_i16<R: Read>(mut reader: R) -> std::io::Result<i16> {
     let mut buf = 0_i16.to_le_bytes();
@@ -11,3 +12,27 @@ pub fn read_i32<R: Read>(mut reader: R) -> std::io::Result<i32> {
     reader.read_exact(&mut buf)?;
     Ok(i32::from_le_bytes(buf))
 }
+
+/// Replaces the file at `path` atomically by writing to a temporary file and
+/// renaming it over the original.
+pub fn write_atomic<P: AsRef<Path>>(path: P, contents: &[u8]) -> std::io::Result<()> {
+    use std::fs::{self, File};
+    use std::io::Write;
+
+    let path = path.as_ref();
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+
+    {
+        let mut file = File::create(&temp_path)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+    }
+
+    fs::rename(&temp_path, path)
+}
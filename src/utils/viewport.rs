@@ -26,3 +26,92 @@ pub fn point_unscaled(x: i32, y: i32) -> [f32; 2] {
 pub fn point_unscaled_boxed(x: i32, y: i32, w: i32, h: i32) -> [f32; 2] {
     [(x as f64 / w as f64) as f32, (y as f64 / h as f64) as f32]
 }
+
+use crate::config::{self, ScaleMode};
+use crate::constants::{GAME_WINDOW_HEIGHT, GAME_WINDOW_WIDTH};
+
+/// Where the screen is shown in a window of another size, in physical
+/// pixels; the rest of the window is black.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Letterbox {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    // size of the screen
+    screen: (u32, u32),
+}
+
+impl Letterbox {
+    /// Centers `screen` in `window`, keeping its aspect ratio.
+    pub fn new(screen: (u32, u32), window: (u32, u32), mode: ScaleMode) -> Self {
+        let (sw, sh) = (screen.0.max(1) as u64, screen.1.max(1) as u64);
+        let (ww, wh) = (window.0 as u64, window.1 as u64);
+
+        let (width, height) = match mode {
+            ScaleMode::Integer if sw <= ww && sh <= wh => {
+                let scale = (ww / sw).min(wh / sh);
+                (sw * scale, sh * scale)
+            }
+            // narrower than the screen: bars above and below
+            _ if ww * sh <= wh * sw => (ww, ww * sh / sw),
+            _ => (wh * sw / sh, wh),
+        };
+
+        Self {
+            x: ((ww - width) / 2) as u32,
+            y: ((wh - height) / 2) as u32,
+            width: width as u32,
+            height: height as u32,
+            screen,
+        }
+    }
+
+    /// Of the game screen in `window`, scaled as configured.
+    pub fn for_window(window: (u32, u32)) -> Self {
+        Self::new(
+            (GAME_WINDOW_WIDTH, GAME_WINDOW_HEIGHT),
+            window,
+            config::get_scale_mode(),
+        )
+    }
+
+    /// Window coordinates to screen coordinates; outside the screen if on
+    /// the bars.
+    pub fn to_screen(&self, (x, y): (f64, f64)) -> (f64, f64) {
+        (
+            (x - self.x as f64) * self.screen.0 as f64 / self.width.max(1) as f64,
+            (y - self.y as f64) * self.screen.1 as f64 / self.height.max(1) as f64,
+        )
+    }
+}
+
+#[test]
+fn test_letterbox() {
+    let screen = (1600, 900);
+
+    let wide = Letterbox::new(screen, (2000, 900), ScaleMode::Fit);
+    assert_eq!(
+        (wide.x, wide.y, wide.width, wide.height),
+        (200, 0, 1600, 900)
+    );
+    assert_eq!(wide.to_screen((1000.0, 450.0)), (800.0, 450.0));
+
+    let tall = Letterbox::new(screen, (800, 800), ScaleMode::Fit);
+    assert_eq!(
+        (tall.x, tall.y, tall.width, tall.height),
+        (0, 175, 800, 450)
+    );
+    assert_eq!(tall.to_screen((0.0, 175.0)), (0.0, 0.0));
+
+    // 2x fits in 3840x2160, but 3x does not
+    let integer = Letterbox::new(screen, (3840, 2160), ScaleMode::Integer);
+    assert_eq!(
+        (integer.x, integer.y, integer.width, integer.height),
+        (320, 180, 3200, 1800)
+    );
+
+    // no whole factor in a smaller window
+    let small = Letterbox::new(screen, (800, 450), ScaleMode::Integer);
+    assert_eq!((small.width, small.height), (800, 450));
+}